@@ -0,0 +1,110 @@
+#!/usr/bin/env python3
+"""Generate src/unicode_tables.rs, the tables behind DiffOptions::unicode_normalization and the
+widths of wrapped lines, from the Unicode database of the Python running this script. Run it
+from the root of the repository."""
+
+import unicodedata
+
+HANGUL = range(0xAC00, 0xD7A4)
+
+
+def char(c):
+    return "'" + escape(chr(c), "'") + "'"
+
+
+def string(s):
+    return '"' + "".join(escape(ch, '"') for ch in s) + '"'
+
+
+def escape(ch, quote):
+    if ch == quote or ch == "\\" or not (0x20 <= ord(ch) < 0x7F):
+        return f"\\u{{{ord(ch):x}}}"
+    return ch
+
+
+def main():
+    classes = []
+    decompositions = []
+    compat_decompositions = []
+    compositions = []
+    wide = []
+    for c in range(0x110000):
+        ch = chr(c)
+        # Python gives unassigned characters a width of their own, so they're left out
+        assigned = unicodedata.category(ch) != "Cn"
+        if assigned and unicodedata.east_asian_width(ch) in ("W", "F"):
+            if wide and wide[-1][1] == c - 1:
+                wide[-1][1] = c
+            else:
+                wide.append([c, c])
+        ccc = unicodedata.combining(ch)
+        if ccc:
+            if classes and classes[-1][1] == c - 1 and classes[-1][2] == ccc:
+                classes[-1][1] = c
+            else:
+                classes.append([c, c, ccc])
+        if c in HANGUL or 0xD800 <= c < 0xE000:
+            continue
+        nfd = unicodedata.normalize("NFD", ch)
+        nfkd = unicodedata.normalize("NFKD", ch)
+        if nfd != ch:
+            decompositions.append((c, nfd))
+        if nfkd != nfd:
+            compat_decompositions.append((c, nfkd))
+        mapping = unicodedata.decomposition(ch).split()
+        if len(mapping) == 2 and not mapping[0].startswith("<"):
+            if unicodedata.normalize("NFC", ch) == ch:
+                compositions.append(((int(mapping[0], 16), int(mapping[1], 16)), c))
+    compositions.sort()
+
+    lines = [
+        "// Generated by scripts/unicode_tables.py from Unicode "
+        + unicodedata.unidata_version
+        + ". Do not edit.",
+        "",
+        "/// The ranges of characters with a nonzero canonical combining class, and their class",
+        "#[rustfmt::skip]",
+        "pub(crate) const COMBINING_CLASSES: &[(char, char, u8)] = &[",
+    ]
+    lines += [f"    ({char(a)}, {char(b)}, {ccc})," for a, b, ccc in classes]
+    lines += [
+        "];",
+        "",
+        "/// The full canonical decomposition of each character that has one, besides Hangul",
+        "/// syllables",
+        "#[rustfmt::skip]",
+        "pub(crate) const DECOMPOSITIONS: &[(char, &str)] = &[",
+    ]
+    lines += [f"    ({char(c)}, {string(d)})," for c, d in decompositions]
+    lines += [
+        "];",
+        "",
+        "/// The full compatibility decomposition of each character where it differs from the",
+        "/// canonical one",
+        "#[rustfmt::skip]",
+        "pub(crate) const COMPAT_DECOMPOSITIONS: &[(char, &str)] = &[",
+    ]
+    lines += [f"    ({char(c)}, {string(d)})," for c, d in compat_decompositions]
+    lines += [
+        "];",
+        "",
+        "/// The pairs of characters that compose into another, besides Hangul syllables",
+        "#[rustfmt::skip]",
+        "pub(crate) const COMPOSITIONS: &[((char, char), char)] = &[",
+    ]
+    lines += [f"    (({char(a)}, {char(b)}), {char(c)})," for (a, b), c in compositions]
+    lines += [
+        "];",
+        "",
+        "/// The ranges of East Asian Wide and Fullwidth characters, which take up two columns",
+        "#[rustfmt::skip]",
+        "pub(crate) const WIDE_CHARS: &[(char, char)] = &[",
+    ]
+    lines += [f"    ({char(a)}, {char(b)})," for a, b in wide]
+    lines += ["];", ""]
+    with open("src/unicode_tables.rs", "w") as f:
+        f.write("\n".join(lines))
+
+
+if __name__ == "__main__":
+    main()
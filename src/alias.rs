@@ -0,0 +1,81 @@
+use crate::{DiffOptions, DiffValue, PathSegment, TomlChange, TomlDiff, ValueKind};
+
+/// Report the key at `old` as having moved to `new`, if it did, by replacing its deletion and
+/// its addition among `changes` with a [`TomlChange::Moved`] and the changes between its old and
+/// new values.
+pub(crate) fn apply<'a, V: DiffValue>(
+    old: &[String],
+    new: &[String],
+    options: &DiffOptions<V>,
+    changes: &mut Vec<TomlChange<'a, V>>,
+) {
+    let at = |key_path: &[PathSegment], keys: &[String]| {
+        key_path.len() == keys.len() && key_path.iter().zip(keys).all(|(k, key)| *k == &**key)
+    };
+    // Tables added or deleted along with the key would have to be split up around it, so only
+    // the key itself may have been
+    let deletion = changes
+        .iter()
+        .position(|change| matches!(change, TomlChange::Deleted(key_path, _) if at(key_path, old)));
+    let addition = changes
+        .iter()
+        .position(|change| matches!(change, TomlChange::Added(key_path, _) if at(key_path, new)));
+    let (Some(deletion), Some(addition)) = (deletion, addition) else {
+        return;
+    };
+    let TomlChange::Deleted(old_path, old_val) = changes.remove(deletion) else {
+        unreachable!()
+    };
+    let addition = if deletion < addition {
+        addition - 1
+    } else {
+        addition
+    };
+    let TomlChange::Added(new_path, new_val) = &changes[addition] else {
+        unreachable!()
+    };
+    let (new_path, new_val) = (new_path.clone(), *new_val);
+
+    let mut moved = vec![TomlChange::Moved(new_path.clone(), old_path)];
+    if old_val != new_val {
+        match (new_val.kind(), old_val.kind()) {
+            (ValueKind::Table, ValueKind::Table) => {
+                // Aliases are key paths from the top level, so they don't apply inside the key
+                let options = DiffOptions {
+                    aliases: vec![],
+                    ..options.clone()
+                };
+                let inner = TomlDiff::diff_with_options(new_val, old_val, &options);
+                moved.extend(
+                    inner
+                        .changes
+                        .into_iter()
+                        .map(|change| prefixed(&new_path, change)),
+                );
+            }
+            _ => moved.push(TomlChange::Changed(new_path, old_val, new_val)),
+        }
+    }
+    changes.splice(addition..=addition, moved);
+}
+
+/// `change` with `prefix` put in front of its key path
+fn prefixed<'a, V>(prefix: &[PathSegment<'a>], change: TomlChange<'a, V>) -> TomlChange<'a, V> {
+    let with_prefix = |key_path: Vec<PathSegment<'a>>| [prefix, &key_path].concat();
+    match change {
+        TomlChange::Added(key_path, val) => TomlChange::Added(with_prefix(key_path), val),
+        TomlChange::Deleted(key_path, val) => TomlChange::Deleted(with_prefix(key_path), val),
+        TomlChange::Changed(key_path, old, new) => {
+            TomlChange::Changed(with_prefix(key_path), old, new)
+        }
+        TomlChange::KeyStyleChanged(key_path, old_key) => {
+            TomlChange::KeyStyleChanged(with_prefix(key_path), old_key)
+        }
+        TomlChange::Moved(key_path, old_path) => {
+            TomlChange::Moved(with_prefix(key_path), with_prefix(old_path))
+        }
+        TomlChange::Reordered(key_path, permutation) => {
+            TomlChange::Reordered(with_prefix(key_path), permutation)
+        }
+    }
+}
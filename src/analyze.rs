@@ -0,0 +1,226 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use toml::Value as TomlValue;
+
+use crate::path::format_key_path;
+use crate::{DiffValue, PathSegment};
+
+/// A table or array that appears, identically, at several key paths of a document.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Duplicate<'v> {
+    pub value: &'v TomlValue,
+    /// Where the value appears, in order
+    pub key_paths: Vec<Vec<PathSegment<'v>>>,
+}
+
+/// Renders the key paths a duplicate appears at, separated by commas.
+impl fmt::Display for Duplicate<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, key_path) in self.key_paths.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            f.write_str(&format_key_path(key_path))?;
+        }
+        Ok(())
+    }
+}
+
+/// Find the non-empty tables and arrays that appear more than once in `value`, to spot
+/// copy-pasted blocks of config. Copies nested inside other copies are only reported if they
+/// also appear somewhere else, so two identical tables are reported once rather than once for
+/// every table inside them. Duplicates are ordered by the first key path they appear at.
+pub fn find_duplicates(value: &TomlValue) -> Vec<Duplicate<'_>> {
+    // Every table and array below the top level, grouped by their toml representation
+    let mut groups: BTreeMap<String, (&TomlValue, Vec<Vec<PathSegment>>)> = BTreeMap::new();
+    let mut stack = vec![(value, vec![])];
+    while let Some((val, key_path)) = stack.pop() {
+        let children: Vec<_> = match val {
+            TomlValue::Table(table) => table
+                .iter()
+                .map(|(key, val)| (PathSegment::Key(key), val))
+                .collect(),
+            TomlValue::Array(array) => array
+                .iter()
+                .enumerate()
+                .map(|(i, val)| (PathSegment::Index(i), val))
+                .collect(),
+            _ => continue,
+        };
+        if !key_path.is_empty() && !children.is_empty() {
+            let group = groups.entry(val.sort_key()).or_insert((val, vec![]));
+            group.1.push(key_path.clone());
+        }
+        for (segment, child) in children {
+            let mut key_path = key_path.clone();
+            key_path.push(segment);
+            stack.push((child, key_path));
+        }
+    }
+    let duplicated: Vec<_> = groups
+        .values()
+        .filter(|(_, key_paths)| key_paths.len() > 1)
+        .flat_map(|(_, key_paths)| key_paths)
+        .collect();
+    // A key path inside another duplicate is only a copy of that duplicate's insides
+    let nested = |key_path: &Vec<PathSegment>| {
+        duplicated
+            .iter()
+            .any(|dup| dup.len() < key_path.len() && key_path.starts_with(dup))
+    };
+    let mut duplicates: Vec<_> = groups
+        .values()
+        .filter(|(_, key_paths)| key_paths.len() > 1 && !key_paths.iter().all(nested))
+        .map(|(value, key_paths)| {
+            let mut key_paths = key_paths.clone();
+            key_paths.sort();
+            Duplicate { value, key_paths }
+        })
+        .collect();
+    duplicates.sort_by(|x, y| x.key_paths[0].cmp(&y.key_paths[0]));
+    duplicates
+}
+
+/// Which key paths each of several documents holds, and whether they agree on the values there,
+/// from [`key_matrix`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyMatrix<'v> {
+    /// The number of documents, which is the number of values in each row
+    pub documents: usize,
+    /// A row for each key path any of the documents holds, sorted by key path
+    pub rows: Vec<KeyRow<'v>>,
+}
+
+/// A key path of a [`KeyMatrix`], and the value each document holds there.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyRow<'v> {
+    pub key_path: Vec<PathSegment<'v>>,
+    /// The value at the key path of each document, in the order the documents were given
+    pub values: Vec<Option<&'v TomlValue>>,
+}
+
+impl KeyRow<'_> {
+    /// The number of documents that hold the key path
+    pub fn count(&self) -> usize {
+        self.values.iter().flatten().count()
+    }
+
+    /// Whether every document holds the key path, all with the same value
+    pub fn consistent(&self) -> bool {
+        let first = self.values.first().copied().flatten();
+        first.is_some() && self.values.iter().all(|&val| val == first)
+    }
+}
+
+/// Tabulate the key paths of `docs` against the documents holding them, to audit many
+/// documents of the same kind, like the configs of dozens of services, for keys some of them
+/// lack and values they disagree on. Tables are broken down into the key paths of their keys,
+/// while arrays and empty tables are values of their own.
+pub fn key_matrix(docs: &[TomlValue]) -> KeyMatrix<'_> {
+    let mut rows: BTreeMap<Vec<PathSegment>, Vec<Option<&TomlValue>>> = BTreeMap::new();
+    for (i, doc) in docs.iter().enumerate() {
+        let mut stack = vec![(doc, vec![])];
+        while let Some((val, key_path)) = stack.pop() {
+            match val {
+                TomlValue::Table(table) if !table.is_empty() => {
+                    for (key, val) in table {
+                        let mut key_path = key_path.clone();
+                        key_path.push(PathSegment::Key(key));
+                        stack.push((val, key_path));
+                    }
+                }
+                _ if key_path.is_empty() => {}
+                _ => {
+                    rows.entry(key_path)
+                        .or_insert_with(|| vec![None; docs.len()])[i] = Some(val)
+                }
+            }
+        }
+    }
+    KeyMatrix {
+        documents: docs.len(),
+        rows: rows
+            .into_iter()
+            .map(|(key_path, values)| KeyRow { key_path, values })
+            .collect(),
+    }
+}
+
+impl KeyMatrix<'_> {
+    /// Render the matrix as CSV, with a header of `key` and the document `names`, then a line
+    /// for each key path with the value each document holds there, or nothing if it doesn't
+    /// hold the key path. Strings are written as they are, and any other value as TOML.
+    pub fn to_csv(&self, names: &[&str]) -> String {
+        let field = |s: &str| {
+            if s.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", s.replace('"', "\"\""))
+            } else {
+                s.to_owned()
+            }
+        };
+        let header: Vec<_> = std::iter::once("key")
+            .chain(names.iter().copied())
+            .collect();
+        let mut csv = header
+            .iter()
+            .map(|s| field(s))
+            .collect::<Vec<_>>()
+            .join(",");
+        csv.push('\n');
+        for row in &self.rows {
+            csv.push_str(&field(&format_key_path(&row.key_path)));
+            for val in &row.values {
+                csv.push(',');
+                let val = val.map(|val| val.as_str().map_or_else(|| val.sort_key(), str::to_owned));
+                csv.push_str(&field(&val.unwrap_or_default()));
+            }
+            csv.push('\n');
+        }
+        csv
+    }
+}
+
+/// Renders a line for each key path, followed by a column for each document, numbered from 1.
+/// Each column holds a letter for the value the document holds there, the same for documents
+/// that agree, from `A` for the first document's value, or `-` if it doesn't hold the key path.
+/// Past `Z`, the values are all marked `*`.
+impl fmt::Display for KeyMatrix<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let key_paths: Vec<_> = self
+            .rows
+            .iter()
+            .map(|row| format_key_path(&row.key_path))
+            .collect();
+        let width = key_paths
+            .iter()
+            .map(|s| s.chars().count())
+            .max()
+            .unwrap_or(0);
+        let column = self.documents.to_string().len();
+        write!(f, "{:width$}", "")?;
+        for i in 1..=self.documents {
+            write!(f, " {i:>column$}")?;
+        }
+        writeln!(f)?;
+        for (row, key_path) in self.rows.iter().zip(&key_paths) {
+            write!(f, "{key_path:width$}")?;
+            let mut seen: Vec<&TomlValue> = vec![];
+            for val in &row.values {
+                let mark = match val {
+                    None => '-',
+                    Some(val) => {
+                        let n = seen.iter().position(|seen| seen == val).unwrap_or_else(|| {
+                            seen.push(val);
+                            seen.len() - 1
+                        });
+                        (b'A'..=b'Z').nth(n).map_or('*', char::from)
+                    }
+                };
+                write!(f, " {mark:>column$}")?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,72 @@
+use crate::path::keys;
+use crate::{PathPattern, TomlChange, TomlDiff};
+
+/// A piece of metadata attached to the changes at some key paths.
+#[derive(Clone)]
+pub(crate) struct Annotation {
+    target: Target,
+    key: String,
+    value: String,
+}
+
+#[derive(Clone)]
+enum Target {
+    Pattern(PathPattern),
+    /// Exactly this key path, for annotations read back from a patch
+    KeyPath(Vec<String>),
+}
+
+impl Annotation {
+    pub(crate) fn at_key_path(key_path: Vec<String>, key: String, value: String) -> Self {
+        Self {
+            target: Target::KeyPath(key_path),
+            key,
+            value,
+        }
+    }
+
+    fn applies_to<V>(&self, change: &TomlChange<V>) -> bool {
+        let key_path = keys(change.key_path());
+        match &self.target {
+            Target::Pattern(pattern) => pattern.matches(&key_path),
+            Target::KeyPath(keys) => keys.iter().eq(&key_path),
+        }
+    }
+}
+
+impl<'a, V> TomlDiff<'a, V> {
+    /// Attach `value` under `key` to every change whose key path matches `pattern`, such as the
+    /// team that owns a section of the document or a link to the ticket behind a change. Every
+    /// rendered form of the diff that has room for them shows the annotations of each change
+    /// alongside it, and they're carried through [patches](crate::patch).
+    pub fn annotate(
+        mut self,
+        pattern: impl Into<PathPattern>,
+        key: impl Into<String>,
+        value: impl Into<String>,
+    ) -> Self {
+        self.annotations.push(Annotation {
+            target: Target::Pattern(pattern.into()),
+            key: key.into(),
+            value: value.into(),
+        });
+        self
+    }
+
+    /// The annotations attached to `change`, as key and value pairs in the order their keys were
+    /// first added. If several annotations with the same key apply, the one added last wins.
+    pub fn annotations(&self, change: &TomlChange<V>) -> Vec<(&str, &str)> {
+        let mut annotations: Vec<(&str, &str)> = vec![];
+        for annotation in &self.annotations {
+            if !annotation.applies_to(change) {
+                continue;
+            }
+            let (key, value) = (annotation.key.as_str(), annotation.value.as_str());
+            match annotations.iter_mut().find(|(k, _)| *k == key) {
+                Some(existing) => existing.1 = value,
+                None => annotations.push((key, value)),
+            }
+        }
+        annotations
+    }
+}
@@ -0,0 +1,377 @@
+use std::cmp::Reverse;
+
+use toml::{map::Map, Value as TomlValue};
+
+use crate::{DiffOptions, PathSegment, TomlChange, TomlDiff};
+
+/// How [`TomlDiff::apply_with_mode`] treats changes that don't match the target document.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApplyMode {
+    /// Skip any change whose recorded old value doesn't match the target
+    Strict,
+    /// Apply changes even if their recorded old value doesn't match the target
+    Lenient,
+}
+
+/// Why a change didn't match the document it was applied to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictKind {
+    /// There is nothing to delete, change, rename, move or reorder at the change's key path
+    Missing,
+    /// The value at the change's key path isn't the change's old value, or an added or renamed
+    /// key already holds a different value, or a key is already where another moves to, or a
+    /// reordered array has a different length
+    Mismatch,
+}
+
+/// A change that didn't match the document it was applied to.
+#[derive(Debug, Clone, Copy)]
+pub struct ApplyConflict<'d, 'a> {
+    pub change: &'d TomlChange<'a>,
+    pub kind: ConflictKind,
+    /// Whether the change was applied anyway, which only happens in [`ApplyMode::Lenient`]
+    pub applied: bool,
+}
+
+/// The changes [`TomlDiff::apply_with_mode`] found conflicts for, in the order they were applied.
+#[derive(Debug, Clone)]
+pub struct ApplyReport<'d, 'a> {
+    pub conflicts: Vec<ApplyConflict<'d, 'a>>,
+}
+
+impl ApplyReport<'_, '_> {
+    /// Whether every change was applied
+    pub fn is_complete(&self) -> bool {
+        self.conflicts.iter().all(|conflict| conflict.applied)
+    }
+}
+
+impl<'a> TomlDiff<'a> {
+    /// Apply this diff to `target`, turning the "old" document into the "new" one.
+    ///
+    /// Keys that moved are moved first, then keys whose spelling changed are renamed, then
+    /// reordered arrays are reordered, then all deletions are applied, then changed values,
+    /// then additions. Array elements are inserted at their index in the new array and removed
+    /// from their index in the old one, falling back to the first equal element if the
+    /// target's array has drifted. Deleting or changing something that doesn't exist is a
+    /// no-op, and adding under a missing table creates it.
+    pub fn apply(&self, target: &mut TomlValue) {
+        for change in self.apply_order() {
+            apply_change(target, change);
+        }
+    }
+
+    /// Like [`TomlDiff::apply`], but checks each change against `target` as it goes, so that
+    /// patches can be applied safely to documents that have drifted from the old document.
+    /// Changes that don't match are skipped in [`ApplyMode::Strict`], while
+    /// [`ApplyMode::Lenient`] applies them anyway where it can, deleting and replacing whatever
+    /// value is there and overwriting keys that already exist. Either way, every change that
+    /// didn't match is reported, along with whether it was applied.
+    pub fn apply_with_mode<'d>(
+        &'d self,
+        target: &mut TomlValue,
+        mode: ApplyMode,
+    ) -> ApplyReport<'d, 'a> {
+        let mut conflicts = vec![];
+        for change in self.apply_order() {
+            let Some(kind) = conflict(target, change) else {
+                apply_change(target, change);
+                continue;
+            };
+            let applied = mode == ApplyMode::Lenient
+                && kind == ConflictKind::Mismatch
+                && force_change(target, change);
+            conflicts.push(ApplyConflict {
+                change,
+                kind,
+                applied,
+            });
+        }
+        ApplyReport { conflicts }
+    }
+
+    /// The document `target` would become with this diff applied, leaving `target` as it is, so
+    /// that the result can be shown alongside the diff before anything is changed. Each change
+    /// is checked against `target` as in [`ApplyMode::Strict`], and if any don't match, the
+    /// conflicts are returned instead of a result missing some of the changes.
+    pub fn preview<'d>(&'d self, target: &TomlValue) -> Result<TomlValue, ApplyReport<'d, 'a>> {
+        let mut result = target.clone();
+        let report = self.apply_with_mode(&mut result, ApplyMode::Strict);
+        if report.conflicts.is_empty() {
+            Ok(result)
+        } else {
+            Err(report)
+        }
+    }
+
+    /// The changes in the order [`TomlDiff::apply`] applies them, in which each change's key path
+    /// is valid once the changes before it have been applied.
+    pub(crate) fn apply_order(&self) -> Vec<&TomlChange<'a>> {
+        let is_move = |c: &&TomlChange| matches!(c, TomlChange::Moved(..));
+        let is_rename = |c: &&TomlChange| matches!(c, TomlChange::KeyStyleChanged(..));
+        // Rename outer keys before inner ones, since inner key paths use the new outer spelling
+        let mut renames: Vec<_> = self.changes.iter().filter(is_rename).collect();
+        renames.sort_by_key(|change| change.key_path().len());
+        // Changes inside array elements carry the old index of their element, so they come
+        // before any elements are removed or inserted
+        let (inside, outside): (Vec<_>, Vec<_>) = self
+            .changes
+            .iter()
+            .filter(|change| !is_move(change) && !is_rename(change))
+            .partition(|change| {
+                let parent = change.key_path().split_last().map_or(&[][..], |(_, p)| p);
+                parent.iter().any(|segment| segment.as_index().is_some())
+            });
+        self.changes
+            .iter()
+            .filter(is_move)
+            .chain(renames)
+            .chain(in_apply_order(inside))
+            .chain(in_apply_order(outside))
+            .collect()
+    }
+}
+
+/// Order `changes`, none of which are moves or renames, so that each change's key path is valid
+/// once the changes before it have been applied
+fn in_apply_order<'c, 'a>(changes: Vec<&'c TomlChange<'a>>) -> Vec<&'c TomlChange<'a>> {
+    let of_kind = |kind: fn(&TomlChange) -> bool| changes.iter().copied().filter(move |c| kind(c));
+    let reorders = of_kind(|c| matches!(c, TomlChange::Reordered(..)));
+    // Remove elements from the back of each array first, so that earlier indices stay valid
+    let mut deletions: Vec<_> = of_kind(|c| matches!(c, TomlChange::Deleted(..))).collect();
+    deletions.sort_by_key(|change| Reverse(last_index(change.key_path())));
+    let changed = of_kind(|c| matches!(c, TomlChange::Changed(..)));
+    // Insert elements from the front of each array first, so that each lands at its index
+    let mut additions: Vec<_> = of_kind(|c| matches!(c, TomlChange::Added(..))).collect();
+    additions.sort_by_key(|change| last_index(change.key_path()));
+    reorders
+        .chain(deletions)
+        .chain(changed)
+        .chain(additions)
+        .collect()
+}
+
+/// Check that applying `diff(a, b)` to `b` produces exactly `a`, panicking with the remaining
+/// differences if it doesn't.
+///
+/// The result is compared to `a` with `==`, so arrays have to come out in the same order too,
+/// which is why the diff is made with [`DiffOptions::report_reorders`].
+pub fn verify_roundtrip(a: &TomlValue, b: &TomlValue) {
+    let options = DiffOptions::new().report_reorders(true);
+    let mut patched = b.clone();
+    TomlDiff::diff_with_options(a, b, &options).apply(&mut patched);
+    if patched != *a {
+        let remaining = TomlDiff::diff_with_options(a, &patched, &options);
+        #[cfg(feature = "render")]
+        panic!("Applying the diff did not reproduce the new document:\n{remaining}");
+        #[cfg(not(feature = "render"))]
+        panic!(
+            "Applying the diff did not reproduce the new document:\n{:#?}",
+            remaining.changes
+        );
+    }
+}
+
+fn apply_change(target: &mut TomlValue, change: &TomlChange) {
+    match change {
+        TomlChange::KeyStyleChanged(key_path, old_key) => rename(target, key_path, old_key),
+        TomlChange::Moved(key_path, old_path) => move_key(target, key_path, old_path),
+        TomlChange::Reordered(key_path, permutation) => reorder(target, key_path, permutation),
+        TomlChange::Deleted(key_path, val) => delete(target, key_path, val),
+        TomlChange::Changed(key_path, _, new) => replace(target, key_path, new),
+        TomlChange::Added(key_path, val) => add(target, key_path, val),
+    }
+}
+
+/// How `change` doesn't match `target`, if it doesn't
+fn conflict(target: &mut TomlValue, change: &TomlChange) -> Option<ConflictKind> {
+    let key_path = change.key_path();
+    let (last, parent_path) = key_path.split_last()?;
+    let parent = get_mut(target, parent_path);
+    let existing = parent.as_deref().and_then(|parent| match (parent, last) {
+        (TomlValue::Table(table), PathSegment::Key(key)) => table.get(*key),
+        (TomlValue::Array(array), &PathSegment::Index(i)) => array.get(i),
+        _ => None,
+    });
+    let mismatch = |matches: bool| (!matches).then_some(ConflictKind::Mismatch);
+    match change {
+        TomlChange::Deleted(_, val) => match parent.as_deref() {
+            // Deleted elements are looked for anywhere in the array, like `apply` does
+            Some(TomlValue::Array(array)) if last.as_index().is_some() => {
+                mismatch(array.contains(val))
+            }
+            _ => existing.map_or(Some(ConflictKind::Missing), |existing| {
+                mismatch(existing == *val)
+            }),
+        },
+        TomlChange::Changed(_, old, _) => existing
+            .map_or(Some(ConflictKind::Missing), |existing| {
+                mismatch(existing == *old)
+            }),
+        TomlChange::Added(_, val) => match last {
+            PathSegment::Key(_) => existing.and_then(|existing| mismatch(existing == *val)),
+            PathSegment::Index(_) => None,
+        },
+        TomlChange::KeyStyleChanged(_, old_key) => {
+            let Some(TomlValue::Table(parent)) = parent.as_deref() else {
+                return Some(ConflictKind::Missing);
+            };
+            match parent.get(*old_key) {
+                None => Some(ConflictKind::Missing),
+                Some(_) if existing.is_some() => Some(ConflictKind::Mismatch),
+                Some(_) => None,
+            }
+        }
+        TomlChange::Moved(_, old_path) => {
+            let occupied = existing.is_some();
+            match get_mut(target, old_path) {
+                None => Some(ConflictKind::Missing),
+                Some(_) if occupied => Some(ConflictKind::Mismatch),
+                Some(_) => None,
+            }
+        }
+        TomlChange::Reordered(_, permutation) => match existing {
+            Some(TomlValue::Array(array)) => mismatch(array.len() == permutation.len()),
+            _ => Some(ConflictKind::Missing),
+        },
+    }
+}
+
+/// Apply `change` to `target` despite a mismatch, returning whether it could be applied
+fn force_change(target: &mut TomlValue, change: &TomlChange) -> bool {
+    match change {
+        TomlChange::Deleted(key_path, _) => {
+            let Some((last, parent_path)) = key_path.split_last() else {
+                return false;
+            };
+            match (get_mut(target, parent_path), last) {
+                (Some(TomlValue::Table(parent)), PathSegment::Key(key)) => {
+                    parent.remove(*key).is_some()
+                }
+                (Some(TomlValue::Array(array)), &PathSegment::Index(i)) if i < array.len() => {
+                    array.remove(i);
+                    true
+                }
+                _ => false,
+            }
+        }
+        TomlChange::Changed(..)
+        | TomlChange::Added(..)
+        | TomlChange::KeyStyleChanged(..)
+        | TomlChange::Moved(..) => {
+            apply_change(target, change);
+            true
+        }
+        TomlChange::Reordered(..) => false,
+    }
+}
+
+fn delete(target: &mut TomlValue, key_path: &[PathSegment], val: &TomlValue) {
+    let Some((last, parent_path)) = key_path.split_last() else {
+        return;
+    };
+    match (get_mut(target, parent_path), last) {
+        (Some(TomlValue::Table(parent)), PathSegment::Key(key))
+            if parent.get(*key) == Some(val) =>
+        {
+            parent.remove(*key);
+        }
+        (Some(TomlValue::Array(array)), &PathSegment::Index(i)) => {
+            let i = if array.get(i) == Some(val) {
+                Some(i)
+            } else {
+                array.iter().position(|elem| elem == val)
+            };
+            if let Some(i) = i {
+                array.remove(i);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn add(target: &mut TomlValue, key_path: &[PathSegment], val: &TomlValue) {
+    let Some((last, parent_path)) = key_path.split_last() else {
+        return;
+    };
+    let mut parent = target;
+    for segment in parent_path {
+        parent = match (parent, segment) {
+            (TomlValue::Table(table), PathSegment::Key(key)) => table
+                .entry((*key).to_owned())
+                .or_insert_with(|| TomlValue::Table(Map::new())),
+            (TomlValue::Array(array), &PathSegment::Index(i)) => match array.get_mut(i) {
+                Some(elem) => elem,
+                None => return,
+            },
+            _ => return,
+        };
+    }
+    match (parent, last) {
+        (TomlValue::Table(table), PathSegment::Key(key)) => {
+            table.insert((*key).to_owned(), val.clone());
+        }
+        (TomlValue::Array(array), &PathSegment::Index(i)) => {
+            array.insert(i.min(array.len()), val.clone());
+        }
+        _ => {}
+    }
+}
+
+fn rename(target: &mut TomlValue, key_path: &[PathSegment], old_key: &str) {
+    let Some((PathSegment::Key(key), parent_path)) = key_path.split_last() else {
+        return;
+    };
+    if let Some(TomlValue::Table(parent)) = get_mut(target, parent_path) {
+        if let Some(val) = parent.remove(old_key) {
+            parent.insert((*key).to_owned(), val);
+        }
+    }
+}
+
+/// Move the key at `old_path` to `key_path`, creating any tables missing on the way
+fn move_key(target: &mut TomlValue, key_path: &[PathSegment], old_path: &[PathSegment]) {
+    let Some((PathSegment::Key(old_key), parent_path)) = old_path.split_last() else {
+        return;
+    };
+    let Some(TomlValue::Table(parent)) = get_mut(target, parent_path) else {
+        return;
+    };
+    if let Some(val) = parent.remove(*old_key) {
+        add(target, key_path, &val);
+    }
+}
+
+/// Reorder the array at `key_path`, unless it no longer has as many elements as `permutation`
+/// or `permutation` names an element it doesn't have
+fn reorder(target: &mut TomlValue, key_path: &[PathSegment], permutation: &[usize]) {
+    if let Some(TomlValue::Array(array)) = get_mut(target, key_path) {
+        if array.len() == permutation.len() {
+            let reordered: Option<Vec<_>> =
+                permutation.iter().map(|&i| array.get(i).cloned()).collect();
+            if let Some(reordered) = reordered {
+                *array = reordered;
+            }
+        }
+    }
+}
+
+fn replace(target: &mut TomlValue, key_path: &[PathSegment], val: &TomlValue) {
+    if let Some(existing) = get_mut(target, key_path) {
+        *existing = val.clone();
+    }
+}
+
+fn get_mut<'v>(target: &'v mut TomlValue, key_path: &[PathSegment]) -> Option<&'v mut TomlValue> {
+    key_path
+        .iter()
+        .try_fold(target, |val, segment| match (val, segment) {
+            (TomlValue::Table(table), PathSegment::Key(key)) => table.get_mut(*key),
+            (TomlValue::Array(array), &PathSegment::Index(i)) => array.get_mut(i),
+            _ => None,
+        })
+}
+
+fn last_index(key_path: &[PathSegment]) -> Option<usize> {
+    key_path.last().and_then(PathSegment::as_index)
+}
@@ -0,0 +1,53 @@
+//! The [`assert_toml_eq!`](crate::assert_toml_eq) macro, enabled with the `test-helpers` feature.
+
+use std::fmt;
+
+use toml::Value as TomlValue;
+
+use crate::{DiffOptions, TomlDiff};
+
+/// Like `assert_eq!`, but for two [`toml::Value`]s. If they aren't equal, the panic message holds
+/// their diff rather than their `Debug` output, with `+` lines only in the left value and `-`
+/// lines only in the right one. A custom message can follow the values, as with `assert_eq!`.
+#[macro_export]
+macro_rules! assert_toml_eq {
+    ($left:expr, $right:expr $(,)?) => {
+        match (&$left, &$right) {
+            (left, right) => {
+                if *left != *right {
+                    $crate::assert_toml_eq_failed(left, right, None);
+                }
+            }
+        }
+    };
+    ($left:expr, $right:expr, $($arg:tt)+) => {
+        match (&$left, &$right) {
+            (left, right) => {
+                if *left != *right {
+                    $crate::assert_toml_eq_failed(left, right, Some(format_args!($($arg)+)));
+                }
+            }
+        }
+    };
+}
+
+#[doc(hidden)]
+#[track_caller]
+pub fn assert_toml_eq_failed(
+    left: &TomlValue,
+    right: &TomlValue,
+    message: Option<fmt::Arguments>,
+) -> ! {
+    let message = match message {
+        Some(message) => format!(": {message}"),
+        None => String::new(),
+    };
+    if !(left.is_table() && right.is_table()) {
+        panic!("assertion `left == right` failed{message}\n  left: {left:?}\n right: {right:?}");
+    }
+    // Arrays are compared without regard to order, so report reorders to explain values that only
+    // differ in the order of their arrays
+    let options = DiffOptions::new().report_reorders(true);
+    let diff = TomlDiff::diff_with_options(left, right, &options);
+    panic!("assertion `left == right` failed{message}\n{diff}");
+}
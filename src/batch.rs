@@ -0,0 +1,222 @@
+use std::fmt;
+
+use toml::Value as TomlValue;
+
+use crate::{DiffOptions, DiffValue, TomlChange, TomlDiff, ValueKind};
+
+/// The diffs of many pairs of documents, from [`TomlDiff::diff_batch`].
+pub struct BatchReport<'a, V = TomlValue> {
+    /// The name and diff of each pair, in the order the pairs were given
+    pub diffs: Vec<(&'a str, TomlDiff<'a, V>)>,
+}
+
+/// How many changes of each kind one or more diffs hold.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ChangeCounts {
+    pub added: usize,
+    pub deleted: usize,
+    pub changed: usize,
+    /// Of the changed values, the booleans that flipped, like feature flags turned on or off
+    pub flipped: usize,
+    /// Keys whose spelling changed, and keys that [moved](TomlChange::Moved)
+    pub renamed: usize,
+    pub reordered: usize,
+    /// Changes left out by [`DiffOptions::max_changes`], whose kinds aren't known
+    pub truncated: usize,
+    /// The total [size](TomlChange::size) of the added values
+    pub added_size: ChangeSize,
+    /// The total [size](TomlChange::size) of the deleted values
+    pub deleted_size: ChangeSize,
+}
+
+/// How much of a document a change touches, from [`TomlChange::size`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ChangeSize {
+    /// The number of values that aren't tables or arrays, so `port = 80` has one and a table of
+    /// 500 keys has 500
+    pub entries: usize,
+    /// The bytes the keys and values take as written, not counting the whitespace, `=` signs,
+    /// brackets and commas between them
+    pub bytes: usize,
+}
+
+impl std::ops::AddAssign for ChangeSize {
+    fn add_assign(&mut self, other: Self) {
+        self.entries += other.entries;
+        self.bytes += other.bytes;
+    }
+}
+
+impl<V: DiffValue> TomlChange<'_, V> {
+    /// How much of the document the change touches, so that deleting a 500-line table can be
+    /// told apart from removing one key. Added and deleted values are measured with everything
+    /// in them, and a changed value by the larger of its old and new values. Renamed and moved
+    /// keys and reordered arrays keep every value, so they touch none.
+    pub fn size(&self) -> ChangeSize {
+        match self {
+            TomlChange::Added(_, val) | TomlChange::Deleted(_, val) => size(*val),
+            TomlChange::Changed(_, old, new) => size(*old).max(size(*new)),
+            TomlChange::KeyStyleChanged(..) | TomlChange::Moved(..) | TomlChange::Reordered(..) => {
+                ChangeSize::default()
+            }
+        }
+    }
+}
+
+fn size<V: DiffValue>(val: &V) -> ChangeSize {
+    match val.kind() {
+        ValueKind::Table => {
+            let mut total = ChangeSize::default();
+            for (key, val) in val.entries() {
+                total += size(val);
+                total.bytes += key.len();
+            }
+            total
+        }
+        ValueKind::Array => {
+            let mut total = ChangeSize::default();
+            for elem in val.elements() {
+                total += size(elem);
+            }
+            total
+        }
+        ValueKind::Scalar => ChangeSize {
+            entries: 1,
+            bytes: val.sort_key().len(),
+        },
+    }
+}
+
+impl ChangeCounts {
+    pub fn of<V: DiffValue>(diff: &TomlDiff<V>) -> Self {
+        let mut counts = Self {
+            truncated: diff.truncated,
+            ..Self::default()
+        };
+        for change in &diff.changes {
+            match change {
+                TomlChange::Added(..) => {
+                    counts.added += 1;
+                    counts.added_size += change.size();
+                }
+                TomlChange::Deleted(..) => {
+                    counts.deleted += 1;
+                    counts.deleted_size += change.size();
+                }
+                TomlChange::Changed(_, old, new) => {
+                    counts.changed += 1;
+                    if old.as_bool().is_some() && new.as_bool().is_some() {
+                        counts.flipped += 1;
+                    }
+                }
+                TomlChange::KeyStyleChanged(..) | TomlChange::Moved(..) => counts.renamed += 1,
+                TomlChange::Reordered(..) => counts.reordered += 1,
+            }
+        }
+        counts
+    }
+
+    pub fn total(&self) -> usize {
+        self.added + self.deleted + self.changed + self.renamed + self.reordered + self.truncated
+    }
+}
+
+impl std::ops::AddAssign for ChangeCounts {
+    fn add_assign(&mut self, other: Self) {
+        self.added += other.added;
+        self.deleted += other.deleted;
+        self.changed += other.changed;
+        self.flipped += other.flipped;
+        self.renamed += other.renamed;
+        self.reordered += other.reordered;
+        self.truncated += other.truncated;
+        self.added_size += other.added_size;
+        self.deleted_size += other.deleted_size;
+    }
+}
+
+impl<'a, V: DiffValue> TomlDiff<'a, V> {
+    /// Diff each of `pairs`, given as a name and the `a` and `b` of [`TomlDiff::diff`], with the
+    /// same `options`. With the `parallel` feature and [`DiffOptions::parallel`] set, the pairs
+    /// are diffed on separate threads instead of each diff being spread across threads.
+    pub fn diff_batch(
+        pairs: &[(&'a str, &'a V, &'a V)],
+        options: &DiffOptions<V>,
+    ) -> BatchReport<'a, V> {
+        #[cfg(feature = "parallel")]
+        if options.parallel {
+            return BatchReport {
+                diffs: crate::parallel::diff_batch(pairs, options),
+            };
+        }
+        let diffs = pairs
+            .iter()
+            .map(|&(name, a, b)| (name, Self::diff_with_options(a, b, options)))
+            .collect();
+        BatchReport { diffs }
+    }
+}
+
+impl<V: DiffValue> BatchReport<'_, V> {
+    /// The number of changes of each kind across every diff
+    pub fn counts(&self) -> ChangeCounts {
+        let mut counts = ChangeCounts::default();
+        for (_, diff) in &self.diffs {
+            counts += ChangeCounts::of(diff);
+        }
+        counts
+    }
+
+    /// The number of pairs whose documents differ
+    pub fn changed(&self) -> usize {
+        self.diffs
+            .iter()
+            .filter(|(_, diff)| !diff.changes.is_empty() || diff.truncated > 0)
+            .count()
+    }
+}
+
+impl fmt::Display for ChangeCounts {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let counts = [
+            (self.added, "added"),
+            (self.deleted, "deleted"),
+            (self.changed, "changed"),
+            (self.renamed, "renamed"),
+            (self.reordered, "reordered"),
+            (self.truncated, "more"),
+        ];
+        let parts: Vec<_> = counts
+            .iter()
+            .filter(|(count, _)| *count > 0)
+            .map(|&(count, kind)| match kind {
+                "changed" if self.flipped > 0 => {
+                    format!("{count} {kind} ({} flipped)", self.flipped)
+                }
+                _ => format!("{count} {kind}"),
+            })
+            .collect();
+        if parts.is_empty() {
+            f.write_str("unchanged")
+        } else {
+            f.write_str(&parts.join(", "))
+        }
+    }
+}
+
+/// Summarizes each diff on a line of its own, like `billing.toml: 2 added, 1 changed`, followed
+/// by a line totalling them all.
+impl<V: DiffValue> fmt::Display for BatchReport<'_, V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (name, diff) in &self.diffs {
+            writeln!(f, "{name}: {}", ChangeCounts::of(diff))?;
+        }
+        writeln!(
+            f,
+            "{} of {} differ: {}",
+            self.changed(),
+            self.diffs.len(),
+            self.counts()
+        )
+    }
+}
@@ -0,0 +1,80 @@
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+
+use toml::Value as TomlValue;
+
+use crate::suppress::fnv1a;
+use crate::{is_empty, DiffOptions, DiffValue};
+
+/// Rewrite `value` into a canonical form, so that documents the diff would consider equal under
+/// `options` end up identical. Canonical documents can be stored and compared later, or diffed
+/// without any options at all.
+///
+/// Keys are sorted and rewritten to the form they're matched by, keeping the first value in key
+/// order if several keys collide. Arrays are sorted the way the diff matches their elements,
+/// and deduplicated if [`DiffOptions::arrays_as_sets`] is set. `-0.0` becomes `0.0`, every NaN
+/// becomes the same NaN, and datetimes with a `+00:00` offset use `Z` instead. Keys holding
+/// empty values are removed if [`DiffOptions::empty_equals_missing`] is set, and strings are put
+/// in the form given to [`DiffOptions::unicode_normalization`].
+pub fn canonicalize(value: &mut TomlValue, options: &DiffOptions) {
+    match value {
+        TomlValue::Table(table) => {
+            // Sort the keys first, so the first of any keys that collide is the first in key
+            // order, whichever order the map keeps them in
+            let mut entries: Vec<_> = std::mem::take(table).into_iter().collect();
+            entries.sort_by(|x, y| x.0.cmp(&y.0));
+            let mut canonical = BTreeMap::new();
+            for (key, mut val) in entries {
+                canonicalize(&mut val, options);
+                if options.empty_equals_missing && is_empty(&val) {
+                    continue;
+                }
+                let key = options.normalize_key(&key).into_owned();
+                canonical.entry(key).or_insert(val);
+            }
+            *table = canonical.into_iter().collect();
+        }
+        TomlValue::Array(array) => {
+            for elem in array.iter_mut() {
+                canonicalize(elem, options);
+            }
+            let mut elems: Vec<_> = std::mem::take(array)
+                .into_iter()
+                .map(|e| (e.sort_key(), e))
+                .collect();
+            elems.sort_by(|x, y| x.0.cmp(&y.0));
+            if options.arrays_as_sets {
+                elems.dedup_by(|x, y| x.0 == y.0);
+            }
+            *array = elems.into_iter().map(|(_, e)| e).collect();
+        }
+        TomlValue::String(s) => {
+            if let Cow::Owned(normalized) = options.normalize_text(s) {
+                *s = normalized;
+            }
+        }
+        TomlValue::Float(f) if *f == 0.0 => *f = 0.0,
+        TomlValue::Float(f) if f.is_nan() => *f = f64::NAN,
+        TomlValue::Datetime(datetime) => {
+            let s = datetime.to_string();
+            if let Some(local) = s
+                .strip_suffix("+00:00")
+                .or_else(|| s.strip_suffix("-00:00"))
+            {
+                *datetime = format!("{local}Z").parse().unwrap();
+            }
+        }
+        _ => {}
+    }
+}
+
+/// A hash of `value` in its [canonical form](canonicalize) under `options`, as 16 hex digits,
+/// so that a copy of a document can be checked against it without fetching and diffing the
+/// whole thing. Documents that are the same once canonicalized hash the same however their keys
+/// are ordered and formatted. Unlike std's hashers, the hash is the same on every machine and
+/// in every run, so hashes made on different hosts can be compared.
+pub fn content_hash(value: &TomlValue, options: &DiffOptions) -> String {
+    let mut value = value.clone();
+    canonicalize(&mut value, options);
+    format!("{:016x}", fnv1a(value.sort_key().as_bytes()))
+}
@@ -0,0 +1,186 @@
+use std::collections::HashMap;
+
+use toml::Value as TomlValue;
+
+use crate::{DiffValue, PathSegment, TomlChange, TomlDiff};
+
+/// A key path in a [`PathArena`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct PathId(u32);
+
+/// Key paths stored once each, as a segment and a link to the path of its parent, so that the
+/// paths of many changes under the same tables share their prefixes. Each distinct path gets
+/// one [`PathId`], so two paths of the same arena are equal exactly when their ids are.
+#[derive(Debug, Clone, Default)]
+pub struct PathArena<'a> {
+    /// The parent and last segment of each path, by id
+    nodes: Vec<(Option<PathId>, PathSegment<'a>)>,
+    ids: HashMap<(Option<PathId>, PathSegment<'a>), PathId>,
+}
+
+impl<'a> PathArena<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The id of `key_path`, adding it and any of its prefixes the arena doesn't have yet.
+    /// Panics on the empty path, which no change is made at.
+    pub fn intern(&mut self, key_path: &[PathSegment<'a>]) -> PathId {
+        let mut parent = None;
+        for &segment in key_path {
+            let next = PathId(self.nodes.len() as u32);
+            let id = *self.ids.entry((parent, segment)).or_insert(next);
+            if id == next {
+                self.nodes.push((parent, segment));
+            }
+            parent = Some(id);
+        }
+        parent.expect("Expected a non-empty key path")
+    }
+
+    /// The path of `id`, from the top-level table down
+    pub fn key_path(&self, id: PathId) -> Vec<PathSegment<'a>> {
+        let mut key_path = vec![];
+        let mut next = Some(id);
+        while let Some(id) = next {
+            let (parent, segment) = self.nodes[id.0 as usize];
+            key_path.push(segment);
+            next = parent;
+        }
+        key_path.reverse();
+        key_path
+    }
+
+    /// The path `id` is inside, or `None` for a top-level key
+    pub fn parent(&self, id: PathId) -> Option<PathId> {
+        self.nodes[id.0 as usize].0
+    }
+
+    /// The last segment of the path of `id`
+    pub fn segment(&self, id: PathId) -> PathSegment<'a> {
+        self.nodes[id.0 as usize].1
+    }
+
+    /// Whether the path of `id` is `ancestor`'s or inside it, found without building either path
+    pub fn starts_with(&self, id: PathId, ancestor: PathId) -> bool {
+        let mut next = Some(id);
+        while let Some(id) = next {
+            if id == ancestor {
+                return true;
+            }
+            next = self.parent(id);
+        }
+        false
+    }
+
+    /// The number of distinct paths and prefixes of paths held
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+}
+
+/// A [`TomlChange`] whose key paths are held in a [`PathArena`].
+#[derive(Debug)]
+pub enum CompactChange<'a, V = TomlValue> {
+    Added(PathId, &'a V),
+    Deleted(PathId, &'a V),
+    Changed(PathId, &'a V, &'a V),
+    KeyStyleChanged(PathId, &'a str),
+    Moved(PathId, PathId),
+    Reordered(PathId, Vec<usize>),
+}
+
+/// The changes of a diff with their key paths interned, from [`TomlDiff::compact`], for diffs
+/// with so many changes under long paths that a path for each of them would take up much of
+/// their memory.
+#[derive(Debug)]
+pub struct CompactDiff<'a, V = TomlValue> {
+    pub paths: PathArena<'a>,
+    pub changes: Vec<CompactChange<'a, V>>,
+    /// The changes the diff left out, as in [`TomlDiff::truncated`]
+    pub truncated: usize,
+}
+
+impl<V> CompactChange<'_, V> {
+    /// The key path of the value that changed, as in [`TomlChange::key_path`]
+    pub fn key_path(&self) -> PathId {
+        match self {
+            CompactChange::Added(id, _)
+            | CompactChange::Deleted(id, _)
+            | CompactChange::Changed(id, _, _)
+            | CompactChange::KeyStyleChanged(id, _)
+            | CompactChange::Moved(id, _)
+            | CompactChange::Reordered(id, _) => *id,
+        }
+    }
+}
+
+impl<'a, V: DiffValue> TomlDiff<'a, V> {
+    /// The changes of the diff with their key paths interned, sharing the segments of paths
+    /// under the same tables and arrays. Redactions, annotations and the other settings of the
+    /// diff aren't kept, and [`CompactDiff::expand`] turns the changes back into a diff.
+    pub fn compact(&self) -> CompactDiff<'a, V> {
+        let mut paths = PathArena::new();
+        let changes = self
+            .changes
+            .iter()
+            .map(|change| match change {
+                TomlChange::Added(key_path, val) => {
+                    CompactChange::Added(paths.intern(key_path), *val)
+                }
+                TomlChange::Deleted(key_path, val) => {
+                    CompactChange::Deleted(paths.intern(key_path), *val)
+                }
+                TomlChange::Changed(key_path, old, new) => {
+                    CompactChange::Changed(paths.intern(key_path), *old, *new)
+                }
+                TomlChange::KeyStyleChanged(key_path, old_key) => {
+                    CompactChange::KeyStyleChanged(paths.intern(key_path), old_key)
+                }
+                TomlChange::Moved(key_path, old_path) => {
+                    CompactChange::Moved(paths.intern(key_path), paths.intern(old_path))
+                }
+                TomlChange::Reordered(key_path, permutation) => {
+                    CompactChange::Reordered(paths.intern(key_path), permutation.clone())
+                }
+            })
+            .collect();
+        CompactDiff {
+            paths,
+            changes,
+            truncated: self.truncated,
+        }
+    }
+}
+
+impl<'a, V: DiffValue> CompactDiff<'a, V> {
+    /// A diff of the changes, with their key paths built again
+    pub fn expand(&self) -> TomlDiff<'a, V> {
+        let key_path = |id| self.paths.key_path(id);
+        let changes = self
+            .changes
+            .iter()
+            .map(|change| match change {
+                CompactChange::Added(id, val) => TomlChange::Added(key_path(*id), *val),
+                CompactChange::Deleted(id, val) => TomlChange::Deleted(key_path(*id), *val),
+                CompactChange::Changed(id, old, new) => {
+                    TomlChange::Changed(key_path(*id), *old, *new)
+                }
+                CompactChange::KeyStyleChanged(id, old_key) => {
+                    TomlChange::KeyStyleChanged(key_path(*id), old_key)
+                }
+                CompactChange::Moved(id, old_id) => {
+                    TomlChange::Moved(key_path(*id), key_path(*old_id))
+                }
+                CompactChange::Reordered(id, permutation) => {
+                    TomlChange::Reordered(key_path(*id), permutation.clone())
+                }
+            })
+            .collect();
+        TomlDiff::from_changes(changes, self.truncated)
+    }
+}
@@ -1,30 +1,1006 @@
-use std::fmt;
+use std::fmt::{self, Write};
+use std::rc::Rc;
 
+use toml::value::{Datetime, Offset};
 use toml::{map::Map, Value as TomlValue};
 
-use crate::{TomlChange, TomlDiff};
+use crate::json::write_str;
+use crate::path::{format_key, format_key_path, natural_cmp};
+use crate::redact::{redact, Redaction};
+use crate::report::inline;
+use crate::similarity::{lcs_table, string_similarity};
+use crate::value::DatetimeKind;
+use crate::{PathSegment, RenderSink, TomlChange, TomlDiff};
 
+/// Renders the diff with colors for a terminal, if the `color` feature is enabled. The alternate
+/// form, `{:#}`, always leaves the colors out. The `+` lines of each change, and its `-` lines,
+/// are valid TOML once their markers are stripped, as [`verify_hunks`] checks, with values that
+/// can't be written under table headers written inline. Strings holding newlines are rendered as
+/// multi-line strings, and a change to one is rendered as a diff of its lines, with the lines
+/// that didn't change shown unprefixed. A change between two datetimes is rendered on one line
+/// along with how far apart they are, or the kinds of datetime they are if those differ.
+/// Changes left out for the [memory budget](crate::DiffOptions::memory_budget) are summed up by
+/// the table they're in, like `... [huge_table]: 12431 entries differ`, and the changes left out
+/// by ignore rules, if they were [counted](crate::DiffOptions::record_suppressed), on a last
+/// line like `... 17 changes suppressed by 3 ignore rules`. The markers and labels
+/// used can be changed with [`TomlDiff::render_style`].
 impl<'a> fmt::Display for TomlDiff<'a> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.render_to(TextSink {
+            f,
+            style: &self.style,
+        })
+    }
+}
+
+/// The [`RenderSink`] behind the [`Display`](fmt::Display) of a diff
+struct TextSink<'f, 'g, 's> {
+    f: &'f mut fmt::Formatter<'g>,
+    style: &'s Style,
+}
+
+impl TextSink<'_, '_, '_> {
+    fn change(&mut self, change: TomlChange) -> fmt::Result {
+        fmt_change(self.f, &change, &[], self.style)
+    }
+}
+
+impl RenderSink for TextSink<'_, '_, '_> {
+    type Error = fmt::Error;
+
+    fn emit_added(&mut self, key_path: &[PathSegment], val: &TomlValue) -> fmt::Result {
+        self.change(TomlChange::Added(key_path.to_vec(), val))
+    }
+    fn emit_deleted(&mut self, key_path: &[PathSegment], val: &TomlValue) -> fmt::Result {
+        self.change(TomlChange::Deleted(key_path.to_vec(), val))
+    }
+    fn emit_changed(
+        &mut self,
+        key_path: &[PathSegment],
+        old: &TomlValue,
+        new: &TomlValue,
+    ) -> fmt::Result {
+        self.change(TomlChange::Changed(key_path.to_vec(), old, new))
+    }
+    fn emit_key_style_changed(&mut self, key_path: &[PathSegment], old_key: &str) -> fmt::Result {
+        self.change(TomlChange::KeyStyleChanged(key_path.to_vec(), old_key))
+    }
+    fn emit_moved(&mut self, key_path: &[PathSegment], old_path: &[PathSegment]) -> fmt::Result {
+        self.change(TomlChange::Moved(key_path.to_vec(), old_path.to_vec()))
+    }
+    fn emit_reordered(&mut self, key_path: &[PathSegment], permutation: &[usize]) -> fmt::Result {
+        self.change(TomlChange::Reordered(
+            key_path.to_vec(),
+            permutation.to_vec(),
+        ))
+    }
+    fn emit_annotations(
+        &mut self,
+        _key_path: &[PathSegment],
+        annotations: &[(&str, &str)],
+    ) -> fmt::Result {
+        fmt_annotations(self.f, annotations)
+    }
+    fn emit_summary(&mut self, key_path: &[PathSegment], count: usize) -> fmt::Result {
+        let render = &self.style.render;
+        match key_path {
+            [] => writeln!(
+                self.f,
+                "... {}",
+                render.label(Label::EntriesDifferAtTopLevel(count))
+            ),
+            key_path => writeln!(
+                self.f,
+                "... [{}]: {}",
+                format_key_path(key_path),
+                render.label(Label::EntriesDiffer(count))
+            ),
+        }
+    }
+    fn emit_truncated(&mut self, count: usize) -> fmt::Result {
+        let label = self.style.render.label(Label::MoreChanges(count));
+        writeln!(self.f, "... {label}")
+    }
+    fn emit_suppressed(&mut self, changes: usize, rules: usize) -> fmt::Result {
+        let label = self
+            .style
+            .render
+            .label(Label::Suppressed { changes, rules });
+        writeln!(self.f, "... {label}")
+    }
+}
+
+/// A [`TextSink`] writing to a string of its own rather than a formatter, so that it can be fed
+/// alongside other sinks by [`TomlDiff::render_all`]
+pub(crate) struct StringSink<'s> {
+    pub(crate) out: String,
+    color: bool,
+    style: &'s Style,
+}
+
+impl<'s> StringSink<'s> {
+    pub(crate) fn new(diff: &'s TomlDiff, color: bool) -> Self {
+        Self {
+            out: String::new(),
+            color,
+            style: &diff.style,
+        }
+    }
+
+    /// Write what `emit` writes to a [`TextSink`], which only exists while formatting
+    fn write(&mut self, emit: impl Fn(&mut TextSink) -> fmt::Result) -> fmt::Result {
+        let style = self.style;
+        let display = WithFormatter(|f: &mut fmt::Formatter| emit(&mut TextSink { f, style }));
+        if self.color {
+            write!(self.out, "{display}")
+        } else {
+            write!(self.out, "{display:#}")
+        }
+    }
+}
+
+/// Displays as whatever its closure writes to the formatter
+struct WithFormatter<F>(F);
+
+impl<F: Fn(&mut fmt::Formatter) -> fmt::Result> fmt::Display for WithFormatter<F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        (self.0)(f)
+    }
+}
+
+impl RenderSink for StringSink<'_> {
+    type Error = fmt::Error;
+
+    fn emit_added(&mut self, key_path: &[PathSegment], val: &TomlValue) -> fmt::Result {
+        self.write(|sink| sink.emit_added(key_path, val))
+    }
+    fn emit_deleted(&mut self, key_path: &[PathSegment], val: &TomlValue) -> fmt::Result {
+        self.write(|sink| sink.emit_deleted(key_path, val))
+    }
+    fn emit_changed(
+        &mut self,
+        key_path: &[PathSegment],
+        old: &TomlValue,
+        new: &TomlValue,
+    ) -> fmt::Result {
+        self.write(|sink| sink.emit_changed(key_path, old, new))
+    }
+    fn emit_key_style_changed(&mut self, key_path: &[PathSegment], old_key: &str) -> fmt::Result {
+        self.write(|sink| sink.emit_key_style_changed(key_path, old_key))
+    }
+    fn emit_moved(&mut self, key_path: &[PathSegment], old_path: &[PathSegment]) -> fmt::Result {
+        self.write(|sink| sink.emit_moved(key_path, old_path))
+    }
+    fn emit_reordered(&mut self, key_path: &[PathSegment], permutation: &[usize]) -> fmt::Result {
+        self.write(|sink| sink.emit_reordered(key_path, permutation))
+    }
+    fn emit_annotations(
+        &mut self,
+        key_path: &[PathSegment],
+        annotations: &[(&str, &str)],
+    ) -> fmt::Result {
+        self.write(|sink| sink.emit_annotations(key_path, annotations))
+    }
+    fn emit_summary(&mut self, key_path: &[PathSegment], count: usize) -> fmt::Result {
+        self.write(|sink| sink.emit_summary(key_path, count))
+    }
+    fn emit_truncated(&mut self, count: usize) -> fmt::Result {
+        self.write(|sink| sink.emit_truncated(count))
+    }
+    fn emit_suppressed(&mut self, changes: usize, rules: usize) -> fmt::Result {
+        self.write(|sink| sink.emit_suppressed(changes, rules))
+    }
+}
+
+/// A [`TomlDiff`] rendered with its changes grouped by top-level table, from
+/// [`TomlDiff::grouped`].
+pub struct Grouped<'d, 'a> {
+    diff: &'d TomlDiff<'a>,
+}
+
+impl<'a> fmt::Display for Grouped<'_, 'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let render = &self.diff.style.render;
+        let group = |change: &TomlChange<'a>| {
+            let key_path = change.key_path();
+            // Top-level keys that aren't tables belong to no group
+            let in_table = key_path.len() > 1
+                || match change {
+                    TomlChange::Added(_, val) | TomlChange::Deleted(_, val) => val.is_table(),
+                    TomlChange::Changed(_, _, new) => new.is_table(),
+                    TomlChange::KeyStyleChanged(..)
+                    | TomlChange::Moved(..)
+                    | TomlChange::Reordered(..) => false,
+                };
+            key_path[0].as_key().filter(|_| in_table)
+        };
+        let mut changes: Vec<_> = self.diff.changes.iter().collect();
+        // The sort is stable, so changes keep their order within each group
+        changes.sort_by(|x, y| match (group(x), group(y)) {
+            (Some(x), Some(y)) if self.diff.natural_key_order => natural_cmp(x, y),
+            (x, y) => x.cmp(&y),
+        });
+        let mut current = None;
+        for (i, change) in changes.into_iter().enumerate() {
+            let key = group(change);
+            if i == 0 || key != current {
+                if i > 0 {
+                    writeln!(f)?;
+                }
+                if let Some(key) = key {
+                    writeln!(f, "{}", (render.header)(&format_key(key)))?;
+                }
+                current = key;
+            }
+            fmt_change(f, change, &self.diff.redactions, &self.diff.style)?;
+            fmt_annotations(f, &self.diff.annotations(change))?;
+        }
+        if self.diff.truncated > 0 {
+            let label = Label::MoreChanges(self.diff.truncated);
+            writeln!(f, "... {}", render.label(label))?;
+        }
+        if let Some(label) = self.diff.suppressed_label() {
+            writeln!(f, "... {}", render.label(label))?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a> TomlDiff<'a> {
+    /// The footnote counting the changes left out by ignore rules, if any were counted
+    fn suppressed_label(&self) -> Option<Label> {
+        let changes = self.suppressed().iter().map(|(_, n)| n).sum();
+        let rules = self.suppressed().len();
+        (changes > 0).then_some(Label::Suppressed { changes, rules })
+    }
+
+    /// Render the diff with its changes grouped under a `[header]` line, or the
+    /// [header](RenderStyle::header) of the render style, for each top-level table they belong
+    /// to, in key order, so that changes to unrelated areas of a large
+    /// document don't interleave. Changes to top-level keys outside any table come first, under
+    /// no header. Like the diff itself, this renders with colors unless formatted with `{:#}`.
+    pub fn grouped(&self) -> Grouped<'_, 'a> {
+        Grouped { diff: self }
+    }
+
+    /// Render each change between two numbers on one line along with the difference between
+    /// them and, unless the old number is zero, the percent change, like
+    /// `~ max_connections: 100 -> 250 (+150, +150%)`.
+    pub fn numeric_deltas(mut self, yes: bool) -> Self {
+        self.style.numeric_deltas = yes;
+        self
+    }
+
+    /// Render each change between two booleans on one line, in bold when rendering with colors,
+    /// like `~ feature.enabled: false -> true`, so that flipped feature flags stand out from the
+    /// `+` and `-` lines of other changes.
+    pub fn highlight_flags(mut self, yes: bool) -> Self {
+        self.style.highlight_flags = yes;
+        self
+    }
+
+    /// Render each change between two single-line strings at least `threshold` alike, by
+    /// [`TomlChange::similarity`], on one line as an edit of the old string, with the removed
+    /// characters marked like `[-this-]` and the added ones like `{+this+}`, as in
+    /// `~ host: "db[-1-]{+2+}.internal"`. Changes between less similar strings are rendered as
+    /// one value replacing another, as usual.
+    pub fn inline_edits(mut self, threshold: f64) -> Self {
+        self.style.inline_edits = Some(threshold);
+        self
+    }
+
+    /// Render each change between two arrays that is reported whole, as with
+    /// [`DiffOptions::values_only`](crate::DiffOptions::values_only), on one line comparing
+    /// their elements by index, with each run of elements that are the same in both shown as
+    /// `...`, like `~ ports: [ ..., ~ [4]: 7 -> 9, ..., + [6]: 10 ]`. This keeps a change to one
+    /// element of a huge array from rendering the whole array twice.
+    pub fn collapse_arrays(mut self, yes: bool) -> Self {
+        self.style.collapse_arrays = yes;
+        self
+    }
+
+    /// Render the diff with the markers, headers and labels of `render_style`, in place of the
+    /// usual `+`, `-`, `~`, `[table]` and English labels.
+    pub fn render_style(mut self, render_style: RenderStyle) -> Self {
+        self.style.render = render_style;
+        self
+    }
+
+    /// Render the diff in two columns like `diff -y`, with old values on the left and new values
+    /// on the right, fitting each line in `width` characters. The gutter between the columns
+    /// holds `|` for changed values and keys, `<` for deleted values and `>` for added ones.
+    /// Lines too long for their column are cut short. No colors are used, so that the columns
+    /// line up in any output. Only the labels of the [render style](TomlDiff::render_style)
+    /// apply, since the gutter takes the place of its markers.
+    pub fn to_side_by_side(&self, width: usize) -> String {
+        let labels = &self.style.render;
+        let column = (width.saturating_sub(3) / 2).max(1);
+        let mut s = String::new();
         for change in &self.changes {
-            match change {
-                TomlChange::Added(key_path, val) => {
-                    writeln!(
+            let key_path = change.key_path();
+            let render = |val| {
+                let val = redact(&self.redactions, key_path, val);
+                format_value(key_path, &val).unwrap()
+            };
+            let (old, new, gutter) = match change {
+                TomlChange::Added(_, val) => (String::new(), render(val), '>'),
+                TomlChange::Deleted(_, val) => (render(val), String::new(), '<'),
+                TomlChange::Changed(_, old, new) => (render(old), render(new), '|'),
+                TomlChange::KeyStyleChanged(_, old_key) => {
+                    let mut old_path = key_path.to_vec();
+                    if let Some(last) = old_path.last_mut() {
+                        *last = PathSegment::Key(old_key);
+                    }
+                    (format_key_path(&old_path), format_key_path(key_path), '|')
+                }
+                TomlChange::Moved(_, old_path) => {
+                    (format_key_path(old_path), format_key_path(key_path), '|')
+                }
+                TomlChange::Reordered(..) => {
+                    let key_path = format_key_path(key_path);
+                    let reordered = labels.label(Label::Reordered);
+                    (key_path.clone(), format!("{key_path} ({reordered})"), '|')
+                }
+            };
+            let mut old = old.lines();
+            let mut new = new.lines();
+            loop {
+                let (left, right) = match (old.next(), new.next()) {
+                    (None, None) => break,
+                    (left, right) => (left.unwrap_or(""), right.unwrap_or("")),
+                };
+                let left: String = left.chars().take(column).collect();
+                let right: String = right.chars().take(column).collect();
+                let line = format!("{left:<column$} {gutter} {right}");
+                s.push_str(line.trim_end());
+                s.push('\n');
+            }
+        }
+        if self.truncated > 0 {
+            let label = Label::MoreChanges(self.truncated);
+            writeln!(s, "... {}", labels.label(label)).unwrap();
+        }
+        if let Some(label) = self.suppressed_label() {
+            writeln!(s, "... {}", labels.label(label)).unwrap();
+        }
+        s
+    }
+}
+
+/// Renders a single change the way it appears in a rendered diff, without any redactions.
+impl<'a> fmt::Display for TomlChange<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt_change(f, self, &[], &Style::default())
+    }
+}
+
+impl TomlChange<'_> {
+    /// The [old value](TomlChange::old_value) as the TOML it would be written as at the
+    /// change's key path, under a header for the table it's in, like `[server]\nport = 80\n`,
+    /// and with a table of its own written under its own header. This is how the value is
+    /// rendered in a diff, without the markers, for showing before and after side by side.
+    /// Returns `None` if the change has no old value, and no redactions apply, since they
+    /// belong to the diff.
+    pub fn render_old(&self) -> Option<String> {
+        let old = self.old_value()?;
+        format_value(self.key_path(), old).ok()
+    }
+
+    /// Like [`TomlChange::render_old`], but renders the [new value](TomlChange::new_value).
+    pub fn render_new(&self) -> Option<String> {
+        let new = self.new_value()?;
+        format_value(self.key_path(), new).ok()
+    }
+}
+
+/// How a diff is rendered, beyond the options every change is rendered with
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Style {
+    numeric_deltas: bool,
+    highlight_flags: bool,
+    inline_edits: Option<f64>,
+    collapse_arrays: bool,
+    render: RenderStyle,
+    /// Whether changes to whole tables are rendered whole, rather than as the changes inside them
+    whole_tables: bool,
+}
+
+impl Style {
+    /// The style of a diff made with [`DiffOptions`](crate::DiffOptions), where a change to a
+    /// whole table was [coalesced](crate::DiffOptions::coalesce_tables) from the changes inside
+    /// it, so it's rendered whole rather than diffed again without the options
+    pub(crate) fn with_options() -> Self {
+        Self {
+            whole_tables: true,
+            ..Self::default()
+        }
+    }
+}
+
+/// The markers, section headers and labels a diff is rendered with, from
+/// [`TomlDiff::render_style`], so that a diff can match the conventions of the product showing
+/// it, or be shown in another language than English.
+///
+/// ```
+/// # use toml_diff::{Label, RenderStyle};
+/// let style = RenderStyle::new()
+///     .markers("ADD", "DEL", "MOD")
+///     .header(|table| format!("== {table} =="))
+///     .translate(|label| match label {
+///         Label::MoreChanges(n) => Some(format!("und {n} weitere Änderungen")),
+///         _ => None,
+///     });
+/// ```
+#[derive(Clone)]
+pub struct RenderStyle {
+    added: String,
+    deleted: String,
+    changed: String,
+    header: Rc<dyn Fn(&str) -> String>,
+    translate: Option<Rc<dyn Fn(Label) -> Option<String>>>,
+}
+
+/// A piece of text a diff is rendered with, which a [`RenderStyle`] can
+/// [translate](RenderStyle::translate). Its [`Display`](fmt::Display) is the English text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Label {
+    /// Follows an array whose elements were reordered, like `~ servers reordered`
+    Reordered,
+    /// The changes left out by [`DiffOptions::max_changes`](crate::DiffOptions::max_changes),
+    /// like `... and 12 more changes`
+    MoreChanges(usize),
+    /// The changes summed up under a table for the
+    /// [memory budget](crate::DiffOptions::memory_budget), like
+    /// `... [huge_table]: 12431 entries differ`
+    EntriesDiffer(usize),
+    /// The changes to top-level keys summed up for the memory budget, like
+    /// `... 12 entries differ at the top level`
+    EntriesDifferAtTopLevel(usize),
+    /// The changes left out by ignore rules, counted with
+    /// [`DiffOptions::record_suppressed`](crate::DiffOptions::record_suppressed), like
+    /// `... 17 changes suppressed by 3 ignore rules`
+    Suppressed { changes: usize, rules: usize },
+}
+
+impl fmt::Display for Label {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Label::Reordered => write!(f, "reordered"),
+            Label::MoreChanges(count) => write!(f, "and {count} more changes"),
+            Label::EntriesDiffer(count) => write!(f, "{count} entries differ"),
+            Label::EntriesDifferAtTopLevel(count) => {
+                write!(f, "{count} entries differ at the top level")
+            }
+            Label::Suppressed { changes, rules } => write!(
+                f,
+                "{changes} {} suppressed by {rules} ignore {}",
+                if *changes == 1 { "change" } else { "changes" },
+                if *rules == 1 { "rule" } else { "rules" },
+            ),
+        }
+    }
+}
+
+impl Default for RenderStyle {
+    fn default() -> Self {
+        Self {
+            added: "+".to_owned(),
+            deleted: "-".to_owned(),
+            changed: "~".to_owned(),
+            header: Rc::new(|table| format!("[{table}]")),
+            translate: None,
+        }
+    }
+}
+
+impl fmt::Debug for RenderStyle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RenderStyle")
+            .field("added", &self.added)
+            .field("deleted", &self.deleted)
+            .field("changed", &self.changed)
+            .finish_non_exhaustive()
+    }
+}
+
+impl RenderStyle {
+    /// The usual style, with `+`, `-` and `~` markers, `[table]` headers and English labels
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Mark added lines with `added`, deleted lines with `deleted`, and lines describing a
+    /// change in place, like a renamed key or a reordered array, with `changed`. Each marker is
+    /// followed by a space.
+    pub fn markers(
+        mut self,
+        added: impl Into<String>,
+        deleted: impl Into<String>,
+        changed: impl Into<String>,
+    ) -> Self {
+        self.added = added.into();
+        self.deleted = deleted.into();
+        self.changed = changed.into();
+        self
+    }
+
+    /// Render the header of each group of a [grouped](TomlDiff::grouped) diff as `header`
+    /// returns it, given the top-level table's key as it's written in TOML.
+    pub fn header(mut self, header: impl Fn(&str) -> String + 'static) -> Self {
+        self.header = Rc::new(header);
+        self
+    }
+
+    /// Render each label as `translate` returns it, or in English if it returns `None`.
+    pub fn translate(mut self, translate: impl Fn(Label) -> Option<String> + 'static) -> Self {
+        self.translate = Some(Rc::new(translate));
+        self
+    }
+
+    fn label(&self, label: Label) -> String {
+        let translated = self
+            .translate
+            .as_ref()
+            .and_then(|translate| translate(label));
+        translated.unwrap_or_else(|| label.to_string())
+    }
+}
+
+fn fmt_change(
+    f: &mut fmt::Formatter,
+    change: &TomlChange,
+    redactions: &[Redaction],
+    style: &Style,
+) -> fmt::Result {
+    let palette = if f.alternate() || !cfg!(feature = "color") {
+        &PLAIN
+    } else {
+        &COLORS
+    };
+    let Palette {
+        yellow,
+        bold,
+        reset,
+        ..
+    } = palette;
+    let render = &style.render;
+    let changed = &render.changed;
+    match change {
+        TomlChange::Added(key_path, val) => {
+            let val = redact(redactions, key_path, val);
+            writeln!(
+                f,
+                "{}",
+                format_change(palette, render, ChangeKind::Added, key_path, &val)?
+            )
+        }
+        TomlChange::Deleted(key_path, val) => {
+            let val = redact(redactions, key_path, val);
+            writeln!(
+                f,
+                "{}",
+                format_change(palette, render, ChangeKind::Deleted, key_path, &val)?
+            )
+        }
+        TomlChange::KeyStyleChanged(key_path, old_key) => {
+            let mut old_path = key_path.clone();
+            if let Some(last) = old_path.last_mut() {
+                *last = PathSegment::Key(old_key);
+            }
+            writeln!(
+                f,
+                "{yellow}{changed} {} -> {}{reset}",
+                format_key_path(&old_path),
+                format_key_path(key_path)
+            )
+        }
+        TomlChange::Moved(key_path, old_path) => {
+            writeln!(
+                f,
+                "{yellow}{changed} {} -> {}{reset}",
+                format_key_path(old_path),
+                format_key_path(key_path)
+            )
+        }
+        TomlChange::Reordered(key_path, _) => {
+            writeln!(
+                f,
+                "{yellow}{changed} {} {}{reset}",
+                format_key_path(key_path),
+                render.label(Label::Reordered)
+            )
+        }
+        TomlChange::Changed(key_path, old, new) => {
+            let old = redact(redactions, key_path, old);
+            let new = redact(redactions, key_path, new);
+            if old.is_table() && new.is_table() && !style.whole_tables {
+                // Show only what changed inside the tables, as if the diff had recursed into them
+                let inner = TomlDiff::diff(&*new, &*old);
+                if !inner.changes.is_empty() {
+                    return fmt_table_changes(f, key_path, &inner.changes, style);
+                }
+            }
+            if let (TomlValue::String(old), TomlValue::String(new)) = (&*old, &*new) {
+                if is_multi_line(old) || is_multi_line(new) {
+                    return fmt_string_change(f, palette, render, key_path, old, new);
+                }
+                let similar = |threshold| string_similarity(old, new) >= threshold;
+                if style.inline_edits.is_some_and(similar) {
+                    return writeln!(
+                        f,
+                        "{yellow}{changed} {}: {}{reset}",
+                        format_key_path(key_path),
+                        inline_edit(palette, old, new)
+                    );
+                }
+            }
+            if let (TomlValue::Array(old), TomlValue::Array(new)) = (&*old, &*new) {
+                if style.collapse_arrays {
+                    return writeln!(
                         f,
-                        "{}",
-                        format_change(ChangeKind::Added, key_path.clone(), val)?
-                    )
+                        "{yellow}{changed} {}: {}{reset}",
+                        format_key_path(key_path),
+                        collapsed_array(render, old, new)
+                    );
                 }
-                TomlChange::Deleted(key_path, val) => {
-                    writeln!(
+            }
+            if let (TomlValue::Boolean(_), TomlValue::Boolean(_)) = (&*old, &*new) {
+                if style.highlight_flags {
+                    return writeln!(
                         f,
-                        "{}",
-                        format_change(ChangeKind::Deleted, key_path.clone(), val)?
-                    )
+                        "{bold}{yellow}{changed} {}: {old} -> {new}{reset}",
+                        format_key_path(key_path)
+                    );
                 }
-            }?;
+            }
+            if style.numeric_deltas {
+                if let Some(delta) = numeric_delta(&old, &new) {
+                    return writeln!(
+                        f,
+                        "{yellow}{changed} {}: {old} -> {new} ({delta}){reset}",
+                        format_key_path(key_path)
+                    );
+                }
+            }
+            if let (TomlValue::Datetime(old), TomlValue::Datetime(new)) = (&*old, &*new) {
+                // Datetimes of different kinds can't be compared, so the change of kind is shown
+                // in place of how far apart they are
+                let delta = datetime_delta(old, new).unwrap_or_else(|| {
+                    let (old, new) = (DatetimeKind::of(old), DatetimeKind::of(new));
+                    format!("{} -> {}", old.name(), new.name())
+                });
+                return writeln!(
+                    f,
+                    "{yellow}{changed} {}: {old} -> {new} ({delta}){reset}",
+                    format_key_path(key_path)
+                );
+            }
+            writeln!(
+                f,
+                "{}\n{}",
+                format_change(palette, render, ChangeKind::Added, key_path, &new)?,
+                format_change(palette, render, ChangeKind::Deleted, key_path, &old)?
+            )
+        }
+    }
+}
+
+/// `changes` inside the table at `key_path`, with each run of changes to the keys of one table
+/// rendered under a single unprefixed header for it, like the context lines of a string change,
+/// and their keys written relative to it
+fn fmt_table_changes(
+    f: &mut fmt::Formatter,
+    key_path: &[PathSegment],
+    changes: &[TomlChange],
+    style: &Style,
+) -> fmt::Result {
+    let indent = context_indent(&style.render);
+    let mut header: Option<Vec<PathSegment>> = None;
+    for change in changes {
+        let change = with_prefix(key_path, change);
+        let (&last, table) = change.key_path().split_last().ok_or(fmt::Error)?;
+        // Values written under headers of their own, like tables, keep their whole key path, as
+        // do values in arrays and at the top level, which have no header to go under
+        let under_header = !table.is_empty() && table.iter().all(|key| key.as_key().is_some());
+        let inline = |val: &TomlValue| {
+            under_header && format_value(&[last], val).is_ok_and(|s| !s.starts_with('['))
+        };
+        let local = match &change {
+            TomlChange::Added(_, val) if inline(val) => TomlChange::Added(vec![last], *val),
+            TomlChange::Deleted(_, val) if inline(val) => TomlChange::Deleted(vec![last], *val),
+            TomlChange::Changed(_, old, new) if inline(old) && inline(new) => {
+                TomlChange::Changed(vec![last], *old, *new)
+            }
+            _ => {
+                header = None;
+                fmt_change(f, &change, &[], style)?;
+                continue;
+            }
+        };
+        if header.as_deref() != Some(table) {
+            writeln!(f, "{indent}[{}]", format_key_path(table))?;
+            header = Some(table.to_vec());
+        }
+        fmt_change(f, &local, &[], style)?;
+    }
+    Ok(())
+}
+
+/// The indent of unprefixed lines, lining them up with changed lines after their markers
+fn context_indent(render: &RenderStyle) -> String {
+    let RenderStyle { added, deleted, .. } = render;
+    " ".repeat(added.chars().count().max(deleted.chars().count()) + 1)
+}
+
+/// `change` with `prefix` put in front of its key path
+fn with_prefix<'c>(prefix: &[PathSegment<'c>], change: &TomlChange<'c>) -> TomlChange<'c> {
+    let key_path = [prefix, change.key_path()].concat();
+    match change {
+        TomlChange::Added(_, val) => TomlChange::Added(key_path, val),
+        TomlChange::Deleted(_, val) => TomlChange::Deleted(key_path, val),
+        TomlChange::Changed(_, old, new) => TomlChange::Changed(key_path, old, new),
+        TomlChange::KeyStyleChanged(_, old_key) => TomlChange::KeyStyleChanged(key_path, old_key),
+        TomlChange::Moved(_, old_path) => TomlChange::Moved(key_path, [prefix, old_path].concat()),
+        TomlChange::Reordered(_, permutation) => {
+            TomlChange::Reordered(key_path, permutation.clone())
+        }
+    }
+}
+
+/// The elements of two arrays compared by index, with each run of elements that are the same in
+/// both written as `...`, like `[ ..., ~ [4]: 7 -> 9, ... ]`
+fn collapsed_array(render: &RenderStyle, old: &[TomlValue], new: &[TomlValue]) -> String {
+    const SAME: &str = "...";
+    let mut elements: Vec<String> = vec![];
+    for i in 0..old.len().max(new.len()) {
+        let element = match (old.get(i), new.get(i)) {
+            (Some(old), Some(new)) if old == new => {
+                if elements.last().map(String::as_str) != Some(SAME) {
+                    elements.push(SAME.to_owned());
+                }
+                continue;
+            }
+            (Some(old), Some(new)) => {
+                format!(
+                    "{} [{i}]: {} -> {}",
+                    render.changed,
+                    inline(old),
+                    inline(new)
+                )
+            }
+            (None, Some(new)) => format!("{} [{i}]: {}", render.added, inline(new)),
+            (Some(old), None) => format!("{} [{i}]: {}", render.deleted, inline(old)),
+            (None, None) => unreachable!(),
+        };
+        elements.push(element);
+    }
+    format!("[ {} ]", elements.join(", "))
+}
+
+/// Check that each run of `+` lines and each run of `-` lines in the rendering of every change
+/// of `diff(a, b)` is valid TOML once its markers are stripped, panicking with the first that
+/// isn't. Tools can then read the values back out of a rendered diff. The changed lines of a
+/// multi-line string, which are shown among its unchanged lines, are the exception, as are the
+/// changes rendered on a single `~` line.
+pub fn verify_hunks(a: &TomlValue, b: &TomlValue) {
+    for change in &TomlDiff::diff(a, b).changes {
+        if let TomlChange::Changed(_, TomlValue::String(old), TomlValue::String(new)) = change {
+            if is_multi_line(old) || is_multi_line(new) {
+                continue;
+            }
+        }
+        let rendered = format!("{change:#}");
+        let mut hunks: Vec<(char, String)> = vec![];
+        for line in rendered.lines() {
+            let Some(marker) = line.chars().next() else {
+                continue;
+            };
+            let line = &line[marker.len_utf8()..];
+            let line = line.strip_prefix(' ').unwrap_or(line);
+            match hunks.last_mut() {
+                Some((last, hunk)) if *last == marker => {
+                    hunk.push_str(line);
+                    hunk.push('\n');
+                }
+                _ => hunks.push((marker, format!("{line}\n"))),
+            }
+        }
+        for (marker, hunk) in hunks {
+            if let ('+' | '-', Err(err)) = (marker, hunk.parse::<TomlValue>()) {
+                panic!("A hunk of a rendered diff is not valid TOML: {err}\n{rendered}");
+            }
+        }
+    }
+}
+
+/// The difference between two numbers and the percent change, like `+150, +150%`
+fn numeric_delta(old: &TomlValue, new: &TomlValue) -> Option<String> {
+    let as_float = |val: &TomlValue| val.as_float().or(val.as_integer().map(|i| i as f64));
+    let delta = match (old, new) {
+        (TomlValue::Integer(old), TomlValue::Integer(new)) => {
+            format!("{:+}", i128::from(*new) - i128::from(*old))
+        }
+        _ => format!("{:+}", as_float(new)? - as_float(old)?),
+    };
+    let (old, new) = (as_float(old)?, as_float(new)?);
+    if old == 0.0 || !old.is_finite() || !new.is_finite() {
+        return Some(delta);
+    }
+    let percent = format!("{:+.1}", (new - old) / old.abs() * 100.0);
+    Some(format!("{delta}, {}%", percent.trim_end_matches(".0")))
+}
+
+/// How far `new` is from `old`, like `+152 days` or `-1 day 2h 30m`, if the two datetimes can be
+/// compared, which they can if they're of the same [`DatetimeKind`].
+fn datetime_delta(old: &Datetime, new: &Datetime) -> Option<String> {
+    // Nanoseconds since the epoch, or since midnight for local times
+    let nanos = |datetime: &Datetime| {
+        let days = datetime.date.as_ref().map_or(0, |date| {
+            days_from_civil(date.year.into(), date.month.into(), date.day.into())
+        });
+        let seconds = datetime.time.as_ref().map_or(0, |time| {
+            i64::from(time.hour) * 3600 + i64::from(time.minute) * 60 + i64::from(time.second)
+        });
+        let offset = match &datetime.offset {
+            None | Some(Offset::Z) => 0,
+            Some(Offset::Custom { hours, minutes }) => {
+                let sign = if *hours < 0 { -1 } else { 1 };
+                i64::from(*hours) * 3600 + sign * i64::from(*minutes) * 60
+            }
+        };
+        let nanosecond = datetime.time.as_ref().map_or(0, |time| time.nanosecond);
+        i128::from(days * 86400 + seconds - offset) * 1_000_000_000 + i128::from(nanosecond)
+    };
+    if DatetimeKind::of(old) != DatetimeKind::of(new) {
+        return None;
+    }
+    let delta = nanos(new) - nanos(old);
+    let sign = if delta < 0 { '-' } else { '+' };
+    let delta = delta.unsigned_abs();
+    let (seconds, nanos) = (delta / 1_000_000_000, delta % 1_000_000_000);
+    let days = seconds / 86400;
+    let mut parts = vec![];
+    match days {
+        0 => {}
+        1 => parts.push("1 day".to_owned()),
+        days => parts.push(format!("{days} days")),
+    }
+    let (hours, minutes, seconds) = (seconds % 86400 / 3600, seconds % 3600 / 60, seconds % 60);
+    if hours > 0 {
+        parts.push(format!("{hours}h"));
+    }
+    if minutes > 0 {
+        parts.push(format!("{minutes}m"));
+    }
+    if nanos > 0 {
+        let fraction = format!("{nanos:09}");
+        parts.push(format!("{seconds}.{}s", fraction.trim_end_matches('0')));
+    } else if seconds > 0 || parts.is_empty() {
+        parts.push(format!("{seconds}s"));
+    }
+    Some(format!("{sign}{}", parts.join(" ")))
+}
+
+/// The number of days from 1970-01-01 to the given date of the proleptic Gregorian calendar
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// Render the annotations of a change as comments below it
+fn fmt_annotations(f: &mut fmt::Formatter, annotations: &[(&str, &str)]) -> fmt::Result {
+    for (key, value) in annotations {
+        writeln!(f, "  # {key}: {value}")?;
+    }
+    Ok(())
+}
+
+/// Render a change between two strings, at least one of them multi-line, as a diff of their
+/// lines with the unchanged lines shown for context
+fn fmt_string_change(
+    f: &mut fmt::Formatter,
+    palette: &Palette,
+    render: &RenderStyle,
+    key_path: &[PathSegment],
+    old: &str,
+    new: &str,
+) -> fmt::Result {
+    let Palette {
+        red, green, reset, ..
+    } = palette;
+    let RenderStyle { added, deleted, .. } = render;
+    let opening = format_value(key_path, &TomlValue::String(String::new()))?;
+    let opening = opening.trim_end().strip_suffix("\"\"").ok_or(fmt::Error)?;
+    // Unchanged lines are indented to line up with the changed ones after their markers
+    let indent = context_indent(render);
+    writeln!(
+        f,
+        "{indent}{}\"\"\"",
+        opening.replace('\n', &format!("\n{indent}"))
+    )?;
+
+    // The closing quotes go on the last line, as they would in the document
+    let lines = |s: &str| {
+        let s = escape_multi_line(s) + "\"\"\"";
+        s.split('\n').map(str::to_owned).collect::<Vec<_>>()
+    };
+    let (old, new) = (lines(old), lines(new));
+    let lcs = lcs_table(&old, &new);
+    // Like other changes, each run of changed lines shows the new lines before the old ones
+    let (mut i, mut j) = (0, 0);
+    let (mut added_lines, mut deleted_lines) = (vec![], vec![]);
+    loop {
+        let same = i < old.len() && j < new.len() && old[i] == new[j];
+        if same || (i == old.len() && j == new.len()) {
+            for line in added_lines.drain(..) {
+                writeln!(f, "{green}{added} {line}{reset}")?;
+            }
+            for line in deleted_lines.drain(..) {
+                writeln!(f, "{red}{deleted} {line}{reset}")?;
+            }
+        }
+        if same {
+            writeln!(f, "{}", format!("{indent}{}", old[i]).trim_end())?;
+            i += 1;
+            j += 1;
+        } else if j < new.len() && (i == old.len() || lcs[i][j + 1] >= lcs[i + 1][j]) {
+            added_lines.push(&new[j]);
+            j += 1;
+        } else if i < old.len() {
+            deleted_lines.push(&old[i]);
+            i += 1;
+        } else {
+            return Ok(());
+        }
+    }
+}
+
+/// `new` as a quoted string, with the runs of characters only `old` has marked like `[-this-]`
+/// in red and those only `new` has like `{+this+}` in green. The marks come before the yellow of
+/// the rest of the line resumes.
+fn inline_edit(palette: &Palette, old: &str, new: &str) -> String {
+    let Palette {
+        red, green, yellow, ..
+    } = palette;
+    // Diff the escaped strings, so that every character shown is one that was compared
+    let escape = |s: &str| {
+        let mut escaped = String::new();
+        write_str(&mut escaped, s);
+        escaped[1..escaped.len() - 1].chars().collect::<Vec<_>>()
+    };
+    let (old, new) = (escape(old), escape(new));
+    let lcs = lcs_table(&old, &new);
+    let mut s = String::from('"');
+    let (mut i, mut j) = (0, 0);
+    let (mut added, mut deleted) = (String::new(), String::new());
+    loop {
+        let same = i < old.len() && j < new.len() && old[i] == new[j];
+        if same || (i == old.len() && j == new.len()) {
+            if !deleted.is_empty() {
+                write!(s, "{red}[-{}-]{yellow}", std::mem::take(&mut deleted)).unwrap();
+            }
+            if !added.is_empty() {
+                write!(s, "{green}{{+{}+}}{yellow}", std::mem::take(&mut added)).unwrap();
+            }
+        }
+        if same {
+            s.push(old[i]);
+            i += 1;
+            j += 1;
+        } else if j < new.len() && (i == old.len() || lcs[i][j + 1] >= lcs[i + 1][j]) {
+            added.push(new[j]);
+            j += 1;
+        } else if i < old.len() {
+            deleted.push(old[i]);
+            i += 1;
+        } else {
+            s.push('"');
+            return s;
         }
-        Ok(())
     }
 }
 
@@ -33,34 +1009,138 @@ enum ChangeKind {
     Deleted,
 }
 
-const RED: &str = "\u{1b}[31m";
-const GREEN: &str = "\u{1b}[32m";
-const RESET: &str = "\u{1b}[0m";
+/// The escape codes each kind of line is wrapped in
+struct Palette {
+    red: &'static str,
+    green: &'static str,
+    yellow: &'static str,
+    bold: &'static str,
+    reset: &'static str,
+}
+
+const COLORS: Palette = Palette {
+    red: "\u{1b}[31m",
+    green: "\u{1b}[32m",
+    yellow: "\u{1b}[33m",
+    bold: "\u{1b}[1m",
+    reset: "\u{1b}[0m",
+};
 
-fn format_change<'a>(
+const PLAIN: Palette = Palette {
+    red: "",
+    green: "",
+    yellow: "",
+    bold: "",
+    reset: "",
+};
+
+fn format_change(
+    palette: &Palette,
+    render: &RenderStyle,
     change_kind: ChangeKind,
-    key_path: Vec<&'a str>,
-    val: &'a TomlValue,
+    key_path: &[PathSegment],
+    val: &TomlValue,
 ) -> Result<String, fmt::Error> {
-    let s = if key_path.is_empty() {
-        toml::to_string(val)
-    } else {
-        // For each key in key_path, wrap the value in a map
-        let mut val = val.clone();
-        for &key in key_path.iter().rev() {
-            let mut map = Map::new();
-            map.insert(key.to_owned(), val.clone());
-            val = TomlValue::Table(map);
-        }
-        toml::to_string(&val)
-    }
-    .map_err(|_| fmt::Error)?;
+    let s = format_value(key_path, val)?;
+    let Palette {
+        red, green, reset, ..
+    } = palette;
     // Prepend the prefix to each line
     Ok(s.lines()
         .map(|line| match change_kind {
-            ChangeKind::Added => format!("{GREEN}+ {line}{RESET}"),
-            ChangeKind::Deleted => format!("{RED}- {line}{RESET}"),
+            ChangeKind::Added => format!("{green}{} {line}{reset}", render.added),
+            ChangeKind::Deleted => format!("{red}{} {line}{reset}", render.deleted),
         })
         .collect::<Vec<_>>()
         .join("\n"))
 }
+
+/// Render `val` as the TOML it would be written as at `key_path`.
+pub(crate) fn format_value(
+    key_path: &[PathSegment],
+    val: &TomlValue,
+) -> Result<String, fmt::Error> {
+    if let TomlValue::String(string) = val {
+        if !key_path.is_empty() && is_multi_line(string) {
+            // Render the key with an empty string, then swap it for a multi-line one
+            let s = format_value(key_path, &TomlValue::String(String::new()))?;
+            let s = s.trim_end().strip_suffix("\"\"").ok_or(fmt::Error)?;
+            return Ok(format!("{s}\"\"\"\n{}\"\"\"\n", escape_multi_line(string)));
+        }
+    }
+    // For each key in key_path, wrap the value in a map
+    let mut wrapped = val.clone();
+    for segment in key_path.iter().rev() {
+        match segment {
+            PathSegment::Key(key) => {
+                let mut map = Map::new();
+                map.insert((*key).to_owned(), wrapped);
+                wrapped = TomlValue::Table(map);
+            }
+            // A table inside an array is wrapped in an array of its own, so that it renders
+            // with a `[[double-bracket]]` header. Other elements render as if they were the
+            // value of the array's key.
+            PathSegment::Index(_) if wrapped.is_table() => {
+                wrapped = TomlValue::Array(vec![wrapped])
+            }
+            PathSegment::Index(_) => {}
+        }
+    }
+    // `toml` writes arrays holding arrays of tables as text that doesn't parse, so anything it
+    // writes is parsed back to check it. NaN isn't equal to itself, but writes the same again.
+    let rendered = toml::to_string(&wrapped).ok().filter(|s| {
+        s.parse::<TomlValue>()
+            .is_ok_and(|parsed| parsed == wrapped || toml::to_string(&parsed).as_ref() == Ok(s))
+    });
+    Ok(rendered.unwrap_or_else(|| format_inline(key_path, val, &wrapped)))
+}
+
+/// Render `val` at `key_path` as TOML with its tables and arrays inline, on a line of its own
+/// if the key path is only keys, or else on one line for the top-level key of `wrapped`, which
+/// is `val` wrapped as [`format_value`] wraps it
+fn format_inline(key_path: &[PathSegment], val: &TomlValue, wrapped: &TomlValue) -> String {
+    let keys: Option<Vec<_>> = key_path.iter().map(PathSegment::as_key).collect();
+    match (keys, wrapped) {
+        (Some(keys), _) if !keys.is_empty() => {
+            let keys: Vec<_> = keys.into_iter().map(format_key).collect();
+            format!("{} = {}\n", keys.join("."), inline(val))
+        }
+        (_, TomlValue::Table(table)) => table
+            .iter()
+            .map(|(key, val)| format!("{} = {}\n", format_key(key), inline(val)))
+            .collect(),
+        (_, val) => format!("{}\n", inline(val)),
+    }
+}
+
+fn is_multi_line(s: &str) -> bool {
+    s.contains('\n')
+}
+
+/// Escape `s` to be the contents of a multi-line basic string, opened with `\"\"\"` and a
+/// newline, keeping its newlines as they are
+fn escape_multi_line(s: &str) -> String {
+    let mut escaped = String::new();
+    let mut quotes = 0;
+    for c in s.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            // A third quote in a row would end the string
+            '"' if quotes == 2 => escaped.push_str("\\\""),
+            '\n' | '\t' => escaped.push(c),
+            c if c.is_control() => escaped.push_str(&format!("\\u{:04X}", c as u32)),
+            c => escaped.push(c),
+        }
+        quotes = if c == '"' && quotes < 2 {
+            quotes + 1
+        } else {
+            0
+        };
+    }
+    // A quote at the very end would run into the closing quotes
+    if quotes > 0 {
+        escaped.pop();
+        escaped.push_str("\\\"");
+    }
+    escaped
+}
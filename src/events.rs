@@ -0,0 +1,121 @@
+use serde::ser::{Serialize, SerializeMap, Serializer};
+use toml::Value as TomlValue;
+
+use crate::{PathSegment, TomlChange, TomlDiff};
+
+/// One step of a diff as a flat stream of events, from [`TomlDiff::into_events`], for
+/// consumers that apply the changes table by table, or pass them on to others.
+#[derive(Debug)]
+pub enum DiffEvent<'a, V = TomlValue> {
+    /// The changes up to the matching `Leave` are inside the table at the key path
+    EnterTable(Vec<PathSegment<'a>>),
+    /// The table at the key path, last entered, has no more changes in this run of them
+    Leave(Vec<PathSegment<'a>>),
+    Change(TomlChange<'a, V>),
+    /// This many more changes were left out of the diff by [`DiffOptions::max_changes`] or
+    /// [`DiffOptions::memory_budget`](crate::DiffOptions::memory_budget). Always last, and only
+    /// sent if any were, so a consumer can tell that it hasn't seen the whole diff.
+    ///
+    /// [`DiffOptions::max_changes`]: crate::DiffOptions::max_changes
+    Truncated(usize),
+}
+
+impl<'a, V> TomlDiff<'a, V> {
+    /// The changes of the diff as a flat stream of events, in the order of the changes. Tables
+    /// are entered and left the same way [`RenderSink::begin_table`] and
+    /// [`RenderSink::end_table`] are called, so every `EnterTable` is matched by a `Leave`, and
+    /// a table is entered again if the changes come back to it.
+    ///
+    /// Values are sent as they are in the documents, since redactions only take effect when
+    /// rendering, so leave out redacted changes with [`TomlDiff::select`] before sending the
+    /// events anywhere untrusted.
+    ///
+    /// [`RenderSink::begin_table`]: crate::RenderSink::begin_table
+    /// [`RenderSink::end_table`]: crate::RenderSink::end_table
+    pub fn into_events(self) -> impl Iterator<Item = DiffEvent<'a, V>> {
+        let mut events = vec![];
+        // The path of the innermost table currently entered
+        let mut open: Vec<PathSegment<'a>> = vec![];
+        for change in self.changes {
+            let key_path = change.key_path();
+            // Array elements belong to the table that holds their array
+            let table_len = key_path
+                .iter()
+                .rposition(|segment| segment.as_key().is_some())
+                .unwrap_or(0);
+            let table = &key_path[..table_len];
+            let common = open.iter().zip(table).take_while(|(x, y)| x == y).count();
+            for len in (common + 1..=open.len()).rev() {
+                events.push(DiffEvent::Leave(open[..len].to_vec()));
+            }
+            for len in common + 1..=table.len() {
+                events.push(DiffEvent::EnterTable(table[..len].to_vec()));
+            }
+            open = table.to_vec();
+            events.push(DiffEvent::Change(change));
+        }
+        for len in (1..=open.len()).rev() {
+            events.push(DiffEvent::Leave(open[..len].to_vec()));
+        }
+        if self.truncated > 0 {
+            events.push(DiffEvent::Truncated(self.truncated));
+        }
+        events.into_iter()
+    }
+}
+
+/// A key as a string and an array index as an integer.
+impl Serialize for PathSegment<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            PathSegment::Key(key) => serializer.serialize_str(key),
+            PathSegment::Index(i) => serializer.serialize_u64(*i as u64),
+        }
+    }
+}
+
+/// A map naming the event, as `enter_table`, `leave`, `change` or `truncated`, under `event`.
+/// Tables are given by their key `path`, as a list of keys and indices. A change has its
+/// [kind](TomlChange::kind) and `path`, and whichever of an `old` and a `new` value, an
+/// `old_key`, an `old_path` and a `permutation` it holds, named as in the JSON output of a
+/// diff. Truncation has the `count` of changes left out.
+impl<V: Serialize> Serialize for DiffEvent<'_, V> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(None)?;
+        match self {
+            DiffEvent::EnterTable(key_path) => {
+                map.serialize_entry("event", "enter_table")?;
+                map.serialize_entry("path", key_path)?;
+            }
+            DiffEvent::Leave(key_path) => {
+                map.serialize_entry("event", "leave")?;
+                map.serialize_entry("path", key_path)?;
+            }
+            DiffEvent::Change(change) => {
+                map.serialize_entry("event", "change")?;
+                map.serialize_entry("kind", change.kind())?;
+                map.serialize_entry("path", change.key_path())?;
+                match change {
+                    TomlChange::Added(_, new) => map.serialize_entry("new", new)?,
+                    TomlChange::Deleted(_, old) => map.serialize_entry("old", old)?,
+                    TomlChange::Changed(_, old, new) => {
+                        map.serialize_entry("old", old)?;
+                        map.serialize_entry("new", new)?;
+                    }
+                    TomlChange::KeyStyleChanged(_, old_key) => {
+                        map.serialize_entry("old_key", old_key)?
+                    }
+                    TomlChange::Moved(_, old_path) => map.serialize_entry("old_path", old_path)?,
+                    TomlChange::Reordered(_, permutation) => {
+                        map.serialize_entry("permutation", permutation)?
+                    }
+                }
+            }
+            DiffEvent::Truncated(count) => {
+                map.serialize_entry("event", "truncated")?;
+                map.serialize_entry("count", count)?;
+            }
+        }
+        map.end()
+    }
+}
@@ -0,0 +1,105 @@
+//! A C interface to the diff engine, enabled with the `ffi` feature.
+//!
+//! A diff is computed with [`toml_diff_compute`], which parses both documents and returns a
+//! [`FfiDiff`] owned by this library. [`toml_diff_render`] returns its text, and
+//! [`toml_diff_free`] releases it along with that text. A panic can't unwind into C, so
+//! [`toml_diff_compute`], which does all the diffing and rendering, returns null on a panic.
+//!
+//! The crate only builds as a Rust library by default, so the shared library C programs link
+//! against is built with
+//!
+//! ```text
+//! cargo rustc --lib --release --features ffi --crate-type cdylib
+//! ```
+
+use std::ffi::{c_char, CStr, CString};
+use std::panic;
+use std::ptr;
+
+use toml::Value as TomlValue;
+
+use crate::TomlDiff;
+
+/// The result of [`toml_diff_compute`]. The layout of this struct is part of the C interface and
+/// only ever grows new fields at the end.
+#[repr(C)]
+pub struct FfiDiff {
+    /// Number of changes between the two documents. Zero means they are equivalent.
+    pub change_count: usize,
+    /// Library-owned state. Never touched by the caller.
+    inner: *mut FfiDiffInner,
+}
+
+struct FfiDiffInner {
+    /// The diff rendered without colors, or `None` if it can't be passed to C
+    rendered: Option<CString>,
+}
+
+/// Diff the nul-terminated TOML documents `a` (new) and `b` (old).
+///
+/// Returns null if either pointer is null, either document isn't valid UTF-8 TOML or diffing them
+/// panics. The returned diff must be released with [`toml_diff_free`].
+///
+/// # Safety
+///
+/// `a` and `b` must be null or point to nul-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn toml_diff_compute(a: *const c_char, b: *const c_char) -> *mut FfiDiff {
+    let compute = || {
+        let (a, b) = (parse(a)?, parse(b)?);
+        let diff = TomlDiff::diff(&a, &b);
+        // Rendered TOML escapes control characters, so it shouldn't contain a nul byte
+        let rendered = CString::new(format!("{diff:#}")).ok();
+        Some((diff.changes.len(), rendered))
+    };
+    let Ok(Some((change_count, rendered))) = panic::catch_unwind(compute) else {
+        return ptr::null_mut();
+    };
+    let inner = Box::into_raw(Box::new(FfiDiffInner { rendered }));
+    Box::into_raw(Box::new(FfiDiff {
+        change_count,
+        inner,
+    }))
+}
+
+/// The text of `diff`, rendered by [`toml_diff_compute`] in the same format as `TomlDiff`'s
+/// `Display` implementation, without colors.
+///
+/// Returns null if `diff` is null or the text can't be passed to C. The returned string is owned
+/// by `diff` and stays valid until `diff` is passed to [`toml_diff_free`].
+///
+/// # Safety
+///
+/// `diff` must be null or a pointer returned by [`toml_diff_compute`] that hasn't been freed.
+#[no_mangle]
+pub unsafe extern "C" fn toml_diff_render(diff: *mut FfiDiff) -> *const c_char {
+    let Some(diff) = diff.as_ref() else {
+        return ptr::null();
+    };
+    (*diff.inner)
+        .rendered
+        .as_ref()
+        .map_or(ptr::null(), |text| text.as_ptr())
+}
+
+/// Release a diff returned by [`toml_diff_compute`]. Does nothing if `diff` is null.
+///
+/// # Safety
+///
+/// `diff` must be null or a pointer returned by [`toml_diff_compute`] that hasn't been freed.
+#[no_mangle]
+pub unsafe extern "C" fn toml_diff_free(diff: *mut FfiDiff) {
+    if diff.is_null() {
+        return;
+    }
+    let diff = Box::from_raw(diff);
+    drop(Box::from_raw(diff.inner));
+}
+
+unsafe fn parse(s: *const c_char) -> Option<TomlValue> {
+    if s.is_null() {
+        return None;
+    }
+    let s = CStr::from_ptr(s).to_str().ok()?;
+    toml::from_str(s).ok()
+}
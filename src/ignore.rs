@@ -0,0 +1,130 @@
+//! Ignore files, which let a repository check in how its documents should be diffed next to
+//! them. An ignore file, conventionally named `.tomldiffignore`, is itself TOML:
+//!
+//! ```toml
+//! # Keys left out of the diff entirely, as path patterns
+//! ignore = ["**.generated_at", "build"]
+//!
+//! # Other ways of comparing the values of keys matching `path`
+//! [[compare]]
+//! path = "**.timeout"
+//! ignore_below = 5
+//!
+//! [[compare]]
+//! path = "**.url"
+//! case_insensitive = true
+//! ```
+//!
+//! Each `[[compare]]` sets one of `ignore_below`, which ignores numbers that changed by less
+//! than it, `ignore_below_relative`, which ignores numbers that changed by less than that
+//! fraction of their old value, or `case_insensitive`, which compares strings regardless of
+//! letter case. See [`DiffOptions::ignore`], [`DiffOptions::ignore_below`] and
+//! [`DiffOptions::compare`] for how each of them works.
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use toml::Value as TomlValue;
+
+use crate::{DiffOptions, Threshold};
+
+/// The name ignore files conventionally have
+pub const IGNORE_FILE_NAME: &str = ".tomldiffignore";
+
+/// An error from reading an [ignore file](self).
+#[derive(Debug)]
+pub enum IgnoreFileError {
+    /// The file couldn't be read
+    Io(io::Error),
+    /// The file isn't valid TOML
+    Toml(toml::de::Error),
+    /// The file is valid TOML, but not a valid ignore file
+    Invalid(&'static str),
+}
+
+impl fmt::Display for IgnoreFileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            IgnoreFileError::Io(err) => write!(f, "{err}"),
+            IgnoreFileError::Toml(err) => write!(f, "{err}"),
+            IgnoreFileError::Invalid(message) => write!(f, "{message}"),
+        }
+    }
+}
+
+impl std::error::Error for IgnoreFileError {}
+
+impl DiffOptions {
+    /// Add the rules of the [ignore file](crate::ignore) at `path` to these options.
+    pub fn load_ignore_file(self, path: impl AsRef<Path>) -> Result<Self, IgnoreFileError> {
+        let text = fs::read_to_string(path).map_err(IgnoreFileError::Io)?;
+        self.ignore_rules(&text)
+    }
+
+    /// Add the rules in `text`, the contents of an [ignore file](crate::ignore), to these
+    /// options.
+    pub fn ignore_rules(mut self, text: &str) -> Result<Self, IgnoreFileError> {
+        let invalid = IgnoreFileError::Invalid;
+        let doc: TomlValue = text.parse().map_err(IgnoreFileError::Toml)?;
+        let doc = doc.as_table().unwrap();
+        if doc.keys().any(|key| key != "ignore" && key != "compare") {
+            return Err(invalid("Unknown key at the top level"));
+        }
+        if let Some(ignore) = doc.get("ignore") {
+            let patterns = ignore
+                .as_array()
+                .ok_or(invalid("`ignore` is not an array"))?;
+            for pattern in patterns {
+                let pattern = pattern
+                    .as_str()
+                    .ok_or(invalid("Invalid pattern in `ignore`"))?;
+                self = self.ignore(pattern);
+            }
+        }
+        let Some(compare) = doc.get("compare") else {
+            return Ok(self);
+        };
+        let compare = compare
+            .as_array()
+            .ok_or(invalid("`compare` is not an array of tables"))?;
+        for rule in compare {
+            let rule = rule
+                .as_table()
+                .ok_or(invalid("`compare` is not an array of tables"))?;
+            let path = rule
+                .get("path")
+                .ok_or(invalid("Missing `path` in `compare`"))?;
+            let path = path
+                .as_str()
+                .ok_or(invalid("Invalid `path` in `compare`"))?;
+            let mut overrides = rule.iter().filter(|(key, _)| *key != "path");
+            let (Some((key, val)), None) = (overrides.next(), overrides.next()) else {
+                return Err(invalid(
+                    "Each `compare` must set exactly one way to compare",
+                ));
+            };
+            let number = || {
+                let number = val.as_float().or(val.as_integer().map(|i| i as f64));
+                number.ok_or(invalid("Invalid threshold in `compare`"))
+            };
+            self = match key.as_str() {
+                "ignore_below" => self.ignore_below(path, Threshold::Absolute(number()?)),
+                "ignore_below_relative" => self.ignore_below(path, Threshold::Relative(number()?)),
+                "case_insensitive" => match val.as_bool() {
+                    Some(true) => self.compare(path, |old, new| {
+                        let (Some(old), Some(new)) = (old.as_str(), new.as_str()) else {
+                            return false;
+                        };
+                        old.to_lowercase() == new.to_lowercase()
+                    }),
+                    Some(false) => self,
+                    None => return Err(invalid("Invalid `case_insensitive` in `compare`")),
+                },
+                _ => return Err(invalid("Unknown way to compare in `compare`")),
+            };
+        }
+        Ok(self)
+    }
+}
@@ -0,0 +1,64 @@
+use toml::Value as TomlValue;
+
+/// Expand the `${VAR}` and `{{ var }}` interpolations in every string of `value`, looking up
+/// each variable's value with `lookup`, so that a rendered document can be diffed against the
+/// unrendered one it came from. Spaces inside the braces are ignored. Interpolations of
+/// variables `lookup` returns `None` for are left as they are, and keys are never expanded.
+pub fn interpolate(value: &mut TomlValue, lookup: impl Fn(&str) -> Option<String>) {
+    interpolate_value(value, &lookup);
+}
+
+fn interpolate_value(value: &mut TomlValue, lookup: &dyn Fn(&str) -> Option<String>) {
+    match value {
+        TomlValue::String(s) => {
+            if let Some(expanded) = expand(s, lookup) {
+                *s = expanded;
+            }
+        }
+        TomlValue::Array(array) => {
+            for elem in array {
+                interpolate_value(elem, lookup);
+            }
+        }
+        TomlValue::Table(table) => {
+            for (_, val) in table.iter_mut() {
+                interpolate_value(val, lookup);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// `s` with its interpolations expanded, or `None` if it has none that could be
+fn expand(s: &str, lookup: &dyn Fn(&str) -> Option<String>) -> Option<String> {
+    let mut expanded = String::new();
+    let mut changed = false;
+    let mut rest = s;
+    loop {
+        let start = match (rest.find("${"), rest.find("{{")) {
+            (Some(x), Some(y)) => x.min(y),
+            (Some(i), None) | (None, Some(i)) => i,
+            (None, None) => break,
+        };
+        let close = if rest[start..].starts_with("${") {
+            "}"
+        } else {
+            "}}"
+        };
+        let Some(len) = rest[start + 2..].find(close) else {
+            break;
+        };
+        let end = start + 2 + len + close.len();
+        expanded.push_str(&rest[..start]);
+        match lookup(rest[start + 2..start + 2 + len].trim()) {
+            Some(val) => {
+                expanded.push_str(&val);
+                changed = true;
+            }
+            None => expanded.push_str(&rest[start..end]),
+        }
+        rest = &rest[end..];
+    }
+    expanded.push_str(rest);
+    changed.then_some(expanded)
+}
@@ -0,0 +1,265 @@
+//! An append-only journal of diffs, for keeping the history of a configuration and auditing
+//! who changed what and when. Each line of a journal file is a JSON object recording one diff:
+//!
+//! ```json
+//! {"time":"2026-10-15T09:30:00Z","source":"deploy","fields":{"user":"ana"},"patch":"..."}
+//! ```
+//!
+//! `time` is when the diff was made, in UTC to the second, `source` says where it came from,
+//! `fields` holds any other details as strings, and `patch` holds the diff as a TOML
+//! [patch](crate::patch). Since every diff is kept whole, [`Journal::replay`] can rebuild the
+//! document as it was after any of them.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::ops::RangeBounds;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use toml::{map::Map, Value as TomlValue};
+
+use crate::json::{write_str, write_value};
+use crate::{parse_json, Patch, TomlDiff};
+
+/// The details recorded alongside a diff in a [`Journal`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Metadata {
+    /// When the diff was made, which [`Journal::append`] takes to be now unless it's set
+    pub time: Option<SystemTime>,
+    /// Where the diff came from, like a file path, a host or a commit
+    pub source: String,
+    /// Any other details, like who made the change and why
+    pub fields: BTreeMap<String, String>,
+}
+
+impl Metadata {
+    pub fn new(source: impl Into<String>) -> Self {
+        Self {
+            time: None,
+            source: source.into(),
+            fields: BTreeMap::new(),
+        }
+    }
+
+    /// Record the diff as made at `time`, rather than when it's appended
+    pub fn time(mut self, time: SystemTime) -> Self {
+        self.time = Some(time);
+        self
+    }
+
+    /// Record `value` under `key` alongside the diff
+    pub fn field(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.fields.insert(key.into(), value.into());
+        self
+    }
+}
+
+/// A diff read back from a [`Journal`], along with the details it was recorded with.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub time: SystemTime,
+    pub source: String,
+    pub fields: BTreeMap<String, String>,
+    pub patch: Patch,
+}
+
+/// The diffs recorded in a journal file, in the order they were appended. See the
+/// [module docs](self) for the file's format.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Journal {
+    pub entries: Vec<Entry>,
+}
+
+/// An error from reading a [`Journal`].
+#[derive(Debug)]
+pub enum JournalError {
+    Io(io::Error),
+    /// The entry on `line`, counted from 1, is malformed
+    Entry {
+        line: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for JournalError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            JournalError::Io(err) => write!(f, "{err}"),
+            JournalError::Entry { line, message } => write!(f, "{message} on line {line}"),
+        }
+    }
+}
+
+impl std::error::Error for JournalError {}
+
+impl Journal {
+    /// Append `diff` to the journal file at `path`, recorded with `metadata`, creating the file
+    /// if it doesn't exist. The entry is written with a single write, so entries appended by
+    /// several processes at once don't interleave. The diff's real values are recorded, since
+    /// they're needed to replay it, so redactions don't apply. A truncated diff is refused,
+    /// since replaying it would leave out the changes it's missing.
+    pub fn append(path: impl AsRef<Path>, diff: &TomlDiff, metadata: &Metadata) -> io::Result<()> {
+        if diff.truncated > 0 {
+            let message = "A truncated diff can't be journaled";
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, message));
+        }
+        let time = metadata.time.unwrap_or_else(SystemTime::now);
+        let mut line = String::from("{\"time\":");
+        write_str(&mut line, &format_time(time));
+        line.push_str(",\"source\":");
+        write_str(&mut line, &metadata.source);
+        line.push_str(",\"fields\":");
+        let fields = metadata
+            .fields
+            .iter()
+            .map(|(key, value)| (key.clone(), TomlValue::String(value.clone())));
+        write_value(&mut line, &TomlValue::Table(Map::from_iter(fields)));
+        line.push_str(",\"patch\":");
+        write_str(&mut line, &diff.to_patch());
+        line.push_str("}\n");
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        file.write_all(line.as_bytes())
+    }
+
+    /// Read the journal file at `path`. A missing file is an empty journal.
+    pub fn read(path: impl AsRef<Path>) -> Result<Self, JournalError> {
+        match fs::read_to_string(path) {
+            Ok(journal) => Self::parse(&journal),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(JournalError::Io(err)),
+        }
+    }
+
+    /// Parse the lines of a journal file. Blank lines are skipped.
+    pub fn parse(journal: &str) -> Result<Self, JournalError> {
+        let entries = journal
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(i, line)| {
+                parse_entry(line).map_err(|message| JournalError::Entry {
+                    line: i + 1,
+                    message,
+                })
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self { entries })
+    }
+
+    /// The document after each entry made in `range`, along with the entry, rebuilt by applying
+    /// every entry up to it in turn to an empty document. A journal that starts with the diff
+    /// from an empty document to the first version of a document so rebuilds every version of
+    /// it since.
+    pub fn replay(&self, range: impl RangeBounds<SystemTime>) -> Vec<(&Entry, TomlValue)> {
+        let mut doc = TomlValue::Table(Map::new());
+        let mut states = vec![];
+        for entry in &self.entries {
+            entry.patch.apply(&mut doc);
+            if range.contains(&entry.time) {
+                states.push((entry, doc.clone()));
+            }
+        }
+        states
+    }
+}
+
+fn parse_entry(line: &str) -> Result<Entry, String> {
+    let entry = parse_json(line).map_err(|err| err.to_string())?;
+    let field = |key| {
+        entry
+            .get(key)
+            .and_then(TomlValue::as_str)
+            .ok_or(format!("`{key}` is not a string"))
+    };
+    let time = field("time")?;
+    let time = parse_time(time).ok_or(format!("Invalid `time` `{time}`"))?;
+    let fields = match entry.get("fields") {
+        Some(TomlValue::Table(fields)) => fields
+            .iter()
+            .map(|(key, value)| Some((key.clone(), value.as_str()?.to_owned())))
+            .collect::<Option<_>>()
+            .ok_or("Invalid `fields`")?,
+        Some(_) => return Err("Invalid `fields`".to_owned()),
+        None => BTreeMap::new(),
+    };
+    Ok(Entry {
+        time,
+        source: field("source")?.to_owned(),
+        fields,
+        patch: Patch::parse(field("patch")?).map_err(|err| format!("Invalid `patch`: {err}"))?,
+    })
+}
+
+/// `time` in UTC to the second, like `2026-10-15T09:30:00Z`. Times before 1970 are written as
+/// 1970.
+fn format_time(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let (days, secs) = (secs / 86400, secs % 86400);
+    let (year, month, day) = civil_from_days(days as i64);
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+/// The time written as [`format_time`] writes it
+fn parse_time(time: &str) -> Option<SystemTime> {
+    let b = time.as_bytes();
+    let separators = [
+        (4, b'-'),
+        (7, b'-'),
+        (10, b'T'),
+        (13, b':'),
+        (16, b':'),
+        (19, b'Z'),
+    ];
+    if b.len() != 20 || separators.iter().any(|&(i, c)| b[i] != c) {
+        return None;
+    }
+    let number = |range: std::ops::Range<usize>| -> Option<u64> {
+        let digits = &time[range];
+        digits
+            .bytes()
+            .all(|c| c.is_ascii_digit())
+            .then(|| digits.parse().ok())?
+    };
+    let (year, month, day) = (number(0..4)?, number(5..7)?, number(8..10)?);
+    let (hour, minute, second) = (number(11..13)?, number(14..16)?, number(17..19)?);
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) || hour > 23 || minute > 59 {
+        return None;
+    }
+    let days = u64::try_from(days_from_civil(year as i64, month, day)).ok()?;
+    let secs = days * 86400 + hour * 3600 + minute * 60 + second;
+    Some(UNIX_EPOCH + Duration::from_secs(secs))
+}
+
+/// The year, month and day `days` days after 1970-01-01, by Howard Hinnant's algorithm
+fn civil_from_days(days: i64) -> (i64, u64, u64) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u64;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u64;
+    (yoe + era * 400 + i64::from(month <= 2), month, day)
+}
+
+/// The number of days from 1970-01-01 to the given day, the inverse of [`civil_from_days`]
+fn days_from_civil(year: i64, month: u64, day: u64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = (month + 9) % 12;
+    let doy = ((153 * mp + 2) / 5 + day - 1) as i64;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
@@ -0,0 +1,529 @@
+#[cfg(feature = "render")]
+use std::convert::Infallible;
+use std::fmt;
+#[cfg(feature = "render")]
+use std::fmt::Write;
+
+use toml::{map::Map, Value as TomlValue};
+
+#[cfg(feature = "render")]
+use crate::value::raw;
+#[cfg(feature = "render")]
+use crate::{path::format_key_path, KeyPath, PathSegment, RenderSink, TomlChange, TomlDiff};
+
+#[cfg(feature = "render")]
+impl<'a> TomlDiff<'a> {
+    /// Render the diff as JSON Lines, with one JSON object per change, e.g.
+    /// `{"kind":"changed","path":"a.b","old":1,"new":2}`.
+    ///
+    /// `kind` is the change's [kind](TomlChange::kind), one of `added`, `deleted`, `changed`,
+    /// `key_style_changed`, `moved` or `reordered`, and `path` is the change's key path written
+    /// as TOML dotted keys. Added and deleted values are
+    /// held in `value`, the old spelling of a key whose style changed in `old_key`, the old key
+    /// path of a moved key in `old_path`, and the permutation of a reordered array in
+    /// `permutation`. Changes with
+    /// [annotations](TomlDiff::annotate) hold them in an `annotations` object. Changes left out
+    /// for the [memory budget](crate::DiffOptions::memory_budget) are counted by
+    /// `{"kind":"summary","path":"huge_table","count":N}` lines, with a `path` of `""` for the
+    /// top level, and if any other changes were left out, the last line is
+    /// `{"kind":"truncated","count":N}`.
+    pub fn to_jsonl(&self) -> String {
+        let mut sink = JsonlSink(String::new());
+        let Ok(()) = self.render_to(&mut sink);
+        sink.0
+    }
+
+    /// Render the diff as a JSON Patch (RFC 6902), which applies to the old document as JSON to
+    /// produce the new one, in the same order [`TomlDiff::apply`] would apply it. Keys that
+    /// moved or whose spelling changed are moved, and reordered arrays have their elements
+    /// moved into place. Redactions don't apply, since a patch needs the real values to be
+    /// applied, and changes left out by truncation are missing from the patch.
+    pub fn to_json_patch(&self) -> String {
+        let mut ops = vec![];
+        for change in self.apply_order() {
+            let key_path = change.key_path();
+            let mut op = String::new();
+            match change {
+                TomlChange::Added(_, val) => {
+                    write!(op, "{{\"op\":\"add\",\"path\":").unwrap();
+                    write_str(&mut op, &key_path.to_json_pointer());
+                    op.push_str(",\"value\":");
+                    write_value(&mut op, val);
+                }
+                TomlChange::Deleted(..) => {
+                    write!(op, "{{\"op\":\"remove\",\"path\":").unwrap();
+                    write_str(&mut op, &key_path.to_json_pointer());
+                }
+                TomlChange::Changed(_, _, new) => {
+                    write!(op, "{{\"op\":\"replace\",\"path\":").unwrap();
+                    write_str(&mut op, &key_path.to_json_pointer());
+                    op.push_str(",\"value\":");
+                    write_value(&mut op, new);
+                }
+                TomlChange::KeyStyleChanged(_, old_key) => {
+                    let mut old_path = key_path.to_vec();
+                    if let Some(last) = old_path.last_mut() {
+                        *last = PathSegment::Key(old_key);
+                    }
+                    write!(op, "{{\"op\":\"move\",\"from\":").unwrap();
+                    write_str(&mut op, &old_path.to_json_pointer());
+                    op.push_str(",\"path\":");
+                    write_str(&mut op, &key_path.to_json_pointer());
+                }
+                TomlChange::Moved(_, old_path) => {
+                    write!(op, "{{\"op\":\"move\",\"from\":").unwrap();
+                    write_str(&mut op, &old_path.to_json_pointer());
+                    op.push_str(",\"path\":");
+                    write_str(&mut op, &key_path.to_json_pointer());
+                }
+                TomlChange::Reordered(_, permutation) => {
+                    // The old index of the element currently at each position
+                    let mut current: Vec<_> = (0..permutation.len()).collect();
+                    for (i, &old_i) in permutation.iter().enumerate() {
+                        // A permutation built by hand can name elements that aren't there
+                        let Some(j) = current.iter().position(|&c| c == old_i) else {
+                            continue;
+                        };
+                        if j == i {
+                            continue;
+                        }
+                        current.remove(j);
+                        current.insert(i, old_i);
+                        let elem_path = |i| {
+                            let mut elem_path = key_path.to_vec();
+                            elem_path.push(PathSegment::Index(i));
+                            elem_path.to_json_pointer()
+                        };
+                        let mut op = String::from("{\"op\":\"move\",\"from\":");
+                        write_str(&mut op, &elem_path(j));
+                        op.push_str(",\"path\":");
+                        write_str(&mut op, &elem_path(i));
+                        op.push('}');
+                        ops.push(op);
+                    }
+                    continue;
+                }
+            }
+            op.push('}');
+            ops.push(op);
+        }
+        if ops.is_empty() {
+            return "[]\n".to_owned();
+        }
+        format!("[\n  {}\n]\n", ops.join(",\n  "))
+    }
+}
+
+/// The [`RenderSink`] behind [`TomlDiff::to_jsonl`], writing one line per change
+#[cfg(feature = "render")]
+pub(crate) struct JsonlSink(pub(crate) String);
+
+#[cfg(feature = "render")]
+impl JsonlSink {
+    /// Start the line of a change, which `end_change` ends
+    fn begin_change(&mut self, kind: &str, key_path: &[PathSegment]) {
+        self.0.push_str("{\"kind\":");
+        write_str(&mut self.0, kind);
+        write_path(&mut self.0, key_path);
+    }
+
+    fn end_change(&mut self) -> Result<(), Infallible> {
+        self.0.push_str("}\n");
+        Ok(())
+    }
+}
+
+#[cfg(feature = "render")]
+impl RenderSink for JsonlSink {
+    type Error = Infallible;
+
+    fn emit_added(&mut self, key_path: &[PathSegment], val: &TomlValue) -> Result<(), Infallible> {
+        self.begin_change("added", key_path);
+        self.0.push_str(",\"value\":");
+        write_value(&mut self.0, val);
+        self.end_change()
+    }
+    fn emit_deleted(
+        &mut self,
+        key_path: &[PathSegment],
+        val: &TomlValue,
+    ) -> Result<(), Infallible> {
+        self.begin_change("deleted", key_path);
+        self.0.push_str(",\"value\":");
+        write_value(&mut self.0, val);
+        self.end_change()
+    }
+    fn emit_changed(
+        &mut self,
+        key_path: &[PathSegment],
+        old: &TomlValue,
+        new: &TomlValue,
+    ) -> Result<(), Infallible> {
+        self.begin_change("changed", key_path);
+        self.0.push_str(",\"old\":");
+        write_value(&mut self.0, old);
+        self.0.push_str(",\"new\":");
+        write_value(&mut self.0, new);
+        self.end_change()
+    }
+    fn emit_key_style_changed(
+        &mut self,
+        key_path: &[PathSegment],
+        old_key: &str,
+    ) -> Result<(), Infallible> {
+        self.begin_change("key_style_changed", key_path);
+        self.0.push_str(",\"old_key\":");
+        write_str(&mut self.0, old_key);
+        self.end_change()
+    }
+    fn emit_moved(
+        &mut self,
+        key_path: &[PathSegment],
+        old_path: &[PathSegment],
+    ) -> Result<(), Infallible> {
+        self.begin_change("moved", key_path);
+        self.0.push_str(",\"old_path\":");
+        write_str(&mut self.0, &format_key_path(old_path));
+        self.end_change()
+    }
+    fn emit_reordered(
+        &mut self,
+        key_path: &[PathSegment],
+        permutation: &[usize],
+    ) -> Result<(), Infallible> {
+        self.begin_change("reordered", key_path);
+        write!(self.0, ",\"permutation\":{permutation:?}").unwrap();
+        self.end_change()
+    }
+    fn emit_annotations(
+        &mut self,
+        _key_path: &[PathSegment],
+        annotations: &[(&str, &str)],
+    ) -> Result<(), Infallible> {
+        // The annotations go inside the object of the change, before the `}` ending its line
+        self.0.truncate(self.0.len() - "}\n".len());
+        self.0.push_str(",\"annotations\":{");
+        for (i, (key, value)) in annotations.iter().enumerate() {
+            if i > 0 {
+                self.0.push(',');
+            }
+            write_str(&mut self.0, key);
+            self.0.push(':');
+            write_str(&mut self.0, value);
+        }
+        self.0.push('}');
+        self.end_change()
+    }
+    fn emit_summary(&mut self, key_path: &[PathSegment], count: usize) -> Result<(), Infallible> {
+        self.begin_change("summary", key_path);
+        write!(self.0, ",\"count\":{count}").unwrap();
+        self.end_change()
+    }
+    fn emit_truncated(&mut self, count: usize) -> Result<(), Infallible> {
+        writeln!(self.0, "{{\"kind\":\"truncated\",\"count\":{count}}}").unwrap();
+        Ok(())
+    }
+}
+
+#[cfg(feature = "render")]
+fn write_path(s: &mut String, key_path: &[PathSegment]) {
+    s.push_str(",\"path\":");
+    write_str(s, &format_key_path(key_path));
+}
+
+/// Write `val` as JSON. Floats JSON can't represent (`nan`, `inf` and `-inf`) are written as
+/// strings in their TOML form, as are datetimes and any other kind of value JSON has no type for.
+#[cfg(feature = "render")]
+pub(crate) fn write_value(s: &mut String, val: &TomlValue) {
+    match val {
+        TomlValue::String(string) => write_str(s, string),
+        TomlValue::Integer(i) => write!(s, "{i}").unwrap(),
+        TomlValue::Float(f) if f.is_finite() => write!(s, "{f:?}").unwrap(),
+        TomlValue::Float(f) if f.is_nan() => write_str(s, "nan"),
+        TomlValue::Float(f) => write_str(s, if *f > 0.0 { "inf" } else { "-inf" }),
+        TomlValue::Boolean(b) => write!(s, "{b}").unwrap(),
+        TomlValue::Array(array) => {
+            s.push('[');
+            for (i, elem) in array.iter().enumerate() {
+                if i > 0 {
+                    s.push(',');
+                }
+                write_value(s, elem);
+            }
+            s.push(']');
+        }
+        TomlValue::Table(table) => {
+            // Keys are written in sorted order, whichever order the map keeps them in
+            let mut entries: Vec<_> = table.iter().collect();
+            entries.sort_by_key(|(key, _)| *key);
+            s.push('{');
+            for (i, (key, val)) in entries.into_iter().enumerate() {
+                if i > 0 {
+                    s.push(',');
+                }
+                write_str(s, key);
+                s.push(':');
+                write_value(s, val);
+            }
+            s.push('}');
+        }
+        val => write_str(s, &raw(val)),
+    }
+}
+
+/// Write `string` as a JSON string literal.
+#[cfg(feature = "render")]
+pub(crate) fn write_str(s: &mut String, string: &str) {
+    s.push('"');
+    for c in string.chars() {
+        match c {
+            '"' => s.push_str("\\\""),
+            '\\' => s.push_str("\\\\"),
+            '\n' => s.push_str("\\n"),
+            '\r' => s.push_str("\\r"),
+            '\t' => s.push_str("\\t"),
+            c if c.is_control() => write!(s, "\\u{:04x}", c as u32).unwrap(),
+            c => s.push(c),
+        }
+    }
+    s.push('"');
+}
+
+/// An error from parsing JSON, with the byte offset it was found at.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JsonError {
+    pub offset: usize,
+    pub message: &'static str,
+}
+
+impl fmt::Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at byte {}", self.message, self.offset)
+    }
+}
+
+impl std::error::Error for JsonError {}
+
+/// How deeply objects and arrays can nest in parsed JSON, so that a document of nothing but `[`
+/// is an error rather than a stack overflow
+const MAX_DEPTH: usize = 128;
+
+/// Parse a JSON document into the equivalent TOML value, for tooling that receives documents or
+/// schemas as JSON. TOML has no null, so object members that are null are left out, and null
+/// anywhere else is an error, as are objects and arrays nested over 128 deep.
+pub fn parse_json(json: &str) -> Result<TomlValue, JsonError> {
+    let mut parser = Parser {
+        json,
+        pos: 0,
+        depth: 0,
+    };
+    let val = parser.value()?;
+    parser.whitespace();
+    if parser.pos != json.len() {
+        return Err(parser.error("Trailing characters"));
+    }
+    val.ok_or_else(|| parser.error("Unexpected null"))
+}
+
+struct Parser<'j> {
+    json: &'j str,
+    pos: usize,
+    /// The number of objects and arrays the parser is inside
+    depth: usize,
+}
+
+impl<'j> Parser<'j> {
+    fn error(&self, message: &'static str) -> JsonError {
+        JsonError {
+            offset: self.pos,
+            message,
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.json.as_bytes().get(self.pos).copied()
+    }
+
+    fn whitespace(&mut self) {
+        while matches!(self.peek(), Some(b' ' | b'\t' | b'\n' | b'\r')) {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, literal: &str) -> Result<(), JsonError> {
+        if self.json[self.pos..].starts_with(literal) {
+            self.pos += literal.len();
+            Ok(())
+        } else {
+            Err(self.error("Unexpected character"))
+        }
+    }
+
+    /// Parse any value, returning `None` for null
+    fn value(&mut self) -> Result<Option<TomlValue>, JsonError> {
+        self.whitespace();
+        let val = match self.peek() {
+            Some(b'{') => self.nested(Self::object)?,
+            Some(b'[') => self.nested(Self::array)?,
+            Some(b'"') => TomlValue::String(self.string()?),
+            Some(b't') => {
+                self.expect("true")?;
+                TomlValue::Boolean(true)
+            }
+            Some(b'f') => {
+                self.expect("false")?;
+                TomlValue::Boolean(false)
+            }
+            Some(b'n') => {
+                self.expect("null")?;
+                return Ok(None);
+            }
+            Some(b'-' | b'0'..=b'9') => self.number()?,
+            Some(_) => return Err(self.error("Unexpected character")),
+            None => return Err(self.error("Unexpected end of input")),
+        };
+        Ok(Some(val))
+    }
+
+    /// Parse an object or array with `parse`, unless it's nested too deeply
+    fn nested(
+        &mut self,
+        parse: fn(&mut Self) -> Result<TomlValue, JsonError>,
+    ) -> Result<TomlValue, JsonError> {
+        if self.depth == MAX_DEPTH {
+            return Err(self.error("Nested too deeply"));
+        }
+        self.depth += 1;
+        let val = parse(self);
+        self.depth -= 1;
+        val
+    }
+
+    fn object(&mut self) -> Result<TomlValue, JsonError> {
+        self.expect("{")?;
+        let mut map = Map::new();
+        self.whitespace();
+        if self.peek() == Some(b'}') {
+            self.pos += 1;
+            return Ok(TomlValue::Table(map));
+        }
+        loop {
+            self.whitespace();
+            let key = self.string()?;
+            self.whitespace();
+            self.expect(":")?;
+            if let Some(val) = self.value()? {
+                map.insert(key, val);
+            }
+            self.whitespace();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b'}') => {
+                    self.pos += 1;
+                    return Ok(TomlValue::Table(map));
+                }
+                _ => return Err(self.error("Expected `,` or `}`")),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<TomlValue, JsonError> {
+        self.expect("[")?;
+        let mut array = vec![];
+        self.whitespace();
+        if self.peek() == Some(b']') {
+            self.pos += 1;
+            return Ok(TomlValue::Array(array));
+        }
+        loop {
+            let val = self.value()?.ok_or_else(|| self.error("Unexpected null"))?;
+            array.push(val);
+            self.whitespace();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b']') => {
+                    self.pos += 1;
+                    return Ok(TomlValue::Array(array));
+                }
+                _ => return Err(self.error("Expected `,` or `]`")),
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, JsonError> {
+        self.expect("\"")?;
+        let mut s = String::new();
+        loop {
+            let rest = &self.json[self.pos..];
+            let Some(c) = rest.chars().next() else {
+                return Err(self.error("Unterminated string"));
+            };
+            self.pos += c.len_utf8();
+            match c {
+                '"' => return Ok(s),
+                '\\' => {
+                    let Some(escape) = self.peek() else {
+                        return Err(self.error("Unterminated string"));
+                    };
+                    self.pos += 1;
+                    match escape {
+                        b'"' => s.push('"'),
+                        b'\\' => s.push('\\'),
+                        b'/' => s.push('/'),
+                        b'b' => s.push('\u{8}'),
+                        b'f' => s.push('\u{c}'),
+                        b'n' => s.push('\n'),
+                        b'r' => s.push('\r'),
+                        b't' => s.push('\t'),
+                        b'u' => s.push(self.unicode_escape()?),
+                        _ => return Err(self.error("Invalid escape")),
+                    }
+                }
+                c => s.push(c),
+            }
+        }
+    }
+
+    fn unicode_escape(&mut self) -> Result<char, JsonError> {
+        let high = self.hex4()?;
+        if !(0xd800..0xdc00).contains(&high) {
+            return char::from_u32(high).ok_or_else(|| self.error("Invalid escape"));
+        }
+        // A high surrogate must be followed by an escaped low surrogate
+        self.expect("\\u")?;
+        let low = self.hex4()?;
+        if !(0xdc00..0xe000).contains(&low) {
+            return Err(self.error("Invalid escape"));
+        }
+        let c = 0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00);
+        char::from_u32(c).ok_or_else(|| self.error("Invalid escape"))
+    }
+
+    fn hex4(&mut self) -> Result<u32, JsonError> {
+        let hex = self
+            .json
+            .get(self.pos..self.pos + 4)
+            .ok_or_else(|| self.error("Invalid escape"))?;
+        let n = u32::from_str_radix(hex, 16).map_err(|_| self.error("Invalid escape"))?;
+        self.pos += 4;
+        Ok(n)
+    }
+
+    fn number(&mut self) -> Result<TomlValue, JsonError> {
+        let start = self.pos;
+        while matches!(
+            self.peek(),
+            Some(b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9')
+        ) {
+            self.pos += 1;
+        }
+        let number = &self.json[start..self.pos];
+        if let Ok(i) = number.parse() {
+            return Ok(TomlValue::Integer(i));
+        }
+        number
+            .parse()
+            .map(TomlValue::Float)
+            .map_err(|_| self.error("Invalid number"))
+    }
+}
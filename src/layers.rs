@@ -0,0 +1,183 @@
+use std::collections::HashMap;
+
+use toml::{map::Map, Value as TomlValue};
+
+use crate::{PathSegment, TomlChange};
+
+/// A configuration assembled from several files laid over each other, like a `base.toml` and
+/// an `override.toml`, which remembers the file each value came from. Diffing the
+/// [merged](Layers::merged) documents of two configurations gives changes whose
+/// [sources](TomlChange::sources) say which file set the old and new values.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Layers {
+    merged: TomlValue,
+    sources: Vec<String>,
+    /// The index in `sources` of the last file to set the value at each key path
+    origins: HashMap<Vec<String>, usize>,
+}
+
+impl Default for Layers {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Layers {
+    pub fn new() -> Self {
+        Self {
+            merged: TomlValue::Table(Map::new()),
+            sources: vec![],
+            origins: HashMap::new(),
+        }
+    }
+
+    /// Lay `doc`, read from the file named `source`, over the layers so far. Tables are merged
+    /// key by key, and any other value replaces the one beneath it, arrays included.
+    pub fn layer(mut self, source: impl Into<String>, doc: TomlValue) -> Self {
+        self.sources.push(source.into());
+        let source = self.sources.len() - 1;
+        let mut key_path = vec![];
+        lay(
+            &mut self.merged,
+            doc,
+            &mut key_path,
+            source,
+            &mut self.origins,
+        );
+        self
+    }
+
+    /// Lay the values of a layer given as dotted key paths, like the `server.port` keys the
+    /// `config` crate collects its sources into, over the layers so far. Each value is laid as
+    /// if it were a document with nothing but that key path in it, in the order given. Keys with
+    /// dots in them can't be told apart from key paths, so they're always taken as key paths.
+    pub fn layer_flat<K: AsRef<str>>(
+        mut self,
+        source: impl Into<String>,
+        entries: impl IntoIterator<Item = (K, TomlValue)>,
+    ) -> Self {
+        self.sources.push(source.into());
+        let source = self.sources.len() - 1;
+        for (key, val) in entries {
+            // `server.port = 80` is laid as a `[server]` table holding `port = 80`
+            let doc = key.as_ref().rsplit('.').fold(val, |doc, key| {
+                TomlValue::Table(Map::from_iter([(key.to_owned(), doc)]))
+            });
+            lay(
+                &mut self.merged,
+                doc,
+                &mut vec![],
+                source,
+                &mut self.origins,
+            );
+        }
+        self
+    }
+
+    /// The layers of a configuration assembled by another crate, so that it's diffed the same
+    /// way as configurations assembled from files, with the name of each layer as the source of
+    /// its values.
+    pub fn from_source(source: &impl LayeredSource) -> Self {
+        source
+            .layers()
+            .into_iter()
+            .fold(Self::new(), |layers, (name, entries)| {
+                layers.layer_flat(name, entries)
+            })
+    }
+
+    /// The document all the layers merge into
+    pub fn merged(&self) -> &TomlValue {
+        &self.merged
+    }
+
+    /// The file that set the value at `key_path` of the merged document. A table holding keys
+    /// from several files is from the last of them to set any key in it, and anything inside an
+    /// array is from the file that set the array.
+    pub fn source(&self, key_path: &[PathSegment]) -> Option<&str> {
+        let mut keys: Vec<_> = key_path
+            .iter()
+            .filter_map(|segment| Some(segment.as_key()?.to_owned()))
+            .collect();
+        loop {
+            if let Some(&source) = self.origins.get(&keys) {
+                return Some(&self.sources[source]);
+            }
+            keys.pop()?;
+        }
+    }
+}
+
+/// A configuration made of layers by another crate, like a `config::Config` along with the
+/// sources it was built from, which can be diffed once it's turned into [`Layers`] with
+/// [`Layers::from_source`]. A configuration without a record of its layers can give all its
+/// values as one layer.
+pub trait LayeredSource {
+    /// The name and values of each layer, lowest first, with values keyed by dotted key paths
+    /// as for [`Layers::layer_flat`]
+    fn layers(&self) -> Vec<(String, Vec<(String, TomlValue)>)>;
+}
+
+fn lay(
+    merged: &mut TomlValue,
+    doc: TomlValue,
+    key_path: &mut Vec<String>,
+    source: usize,
+    origins: &mut HashMap<Vec<String>, usize>,
+) {
+    match (merged, doc) {
+        (TomlValue::Table(merged), TomlValue::Table(doc)) => {
+            origins.insert(key_path.clone(), source);
+            for (key, val) in doc {
+                key_path.push(key.clone());
+                match merged.get_mut(&key) {
+                    Some(merged) => lay(merged, val, key_path, source, origins),
+                    None => {
+                        record(&val, key_path, source, origins);
+                        merged.insert(key, val);
+                    }
+                }
+                key_path.pop();
+            }
+        }
+        (merged, doc) => {
+            // Whatever was beneath is gone, along with where it came from
+            origins.retain(|path, _| path.len() <= key_path.len() || !path.starts_with(key_path));
+            record(&doc, key_path, source, origins);
+            *merged = doc;
+        }
+    }
+}
+
+/// Record that everything in the tables of `val` is from `source`
+fn record(
+    val: &TomlValue,
+    key_path: &mut Vec<String>,
+    source: usize,
+    origins: &mut HashMap<Vec<String>, usize>,
+) {
+    origins.insert(key_path.clone(), source);
+    if let TomlValue::Table(table) = val {
+        for (key, val) in table {
+            key_path.push(key.clone());
+            record(val, key_path, source, origins);
+            key_path.pop();
+        }
+    }
+}
+
+impl<'a> TomlChange<'a> {
+    /// The files the old and new values of the change came from, given the configurations the
+    /// change is between. Either is `None` if that side has no value, as for an added or deleted
+    /// key, or if it's a document that isn't made of layers.
+    pub fn sources<'l>(
+        &self,
+        old: &'l Layers,
+        new: &'l Layers,
+    ) -> (Option<&'l str>, Option<&'l str>) {
+        let key_path = self.key_path();
+        let old = self.old_value().and_then(|_| old.source(key_path));
+        let new = self.new_value().and_then(|_| new.source(key_path));
+        (old, new)
+    }
+}
@@ -1,152 +1,1195 @@
 use std::cmp::Ordering;
-use std::mem::discriminant;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::rc::Rc;
 
+use serde::de::DeserializeOwned;
 use toml::Value as TomlValue;
 
+use annotate::Annotation;
+use path::SharedPath;
+use progress::Progress;
+use redact::Redaction;
+
+mod alias;
+mod analyze;
+mod annotate;
+mod apply;
+#[cfg(feature = "test-helpers")]
+mod assert;
+mod batch;
+mod canonical;
+mod classify;
+mod compact;
+#[cfg(feature = "render")]
 mod display;
+mod events;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod ignore;
+mod interpolate;
+#[cfg(feature = "journal")]
+pub mod journal;
+mod json;
+mod layers;
+#[cfg(feature = "render")]
+pub mod lockfile;
+pub mod merge;
+#[cfg(feature = "render")]
+pub mod migration;
+mod options;
+mod order;
+#[cfg(feature = "parallel")]
+mod parallel;
+pub mod patch;
+mod path;
+mod pattern;
+mod progress;
+mod redact;
+#[cfg(feature = "render")]
+mod render;
+#[cfg(feature = "render")]
+mod report;
+#[cfg(feature = "schema")]
+pub mod schema;
+mod serialize;
+mod similarity;
+#[cfg(feature = "render")]
+mod snapshot;
+mod span;
+mod suppress;
 #[cfg(test)]
 mod test;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+mod unicode;
+mod unicode_tables;
+mod update;
+mod value;
+mod visit;
+#[cfg(feature = "watch")]
+pub mod watch;
+#[cfg(feature = "render")]
+pub mod workspace;
+
+pub use analyze::{find_duplicates, key_matrix, Duplicate, KeyMatrix, KeyRow};
+pub use apply::{verify_roundtrip, ApplyConflict, ApplyMode, ApplyReport, ConflictKind};
+#[cfg(feature = "test-helpers")]
+#[doc(hidden)]
+pub use assert::assert_toml_eq_failed;
+pub use batch::{BatchReport, ChangeCounts, ChangeSize};
+pub use canonical::{canonicalize, content_hash};
+pub use classify::ValueClass;
+pub use compact::{CompactChange, CompactDiff, PathArena, PathId};
+#[cfg(feature = "render")]
+pub use display::{verify_hunks, Grouped, Label, RenderStyle};
+pub use events::DiffEvent;
+pub use ignore::IgnoreFileError;
+pub use interpolate::interpolate;
+pub use json::{parse_json, JsonError};
+pub use layers::{LayeredSource, Layers};
+pub use options::{DiffOptions, IgnoreRule, Profile, Threshold};
+pub use order::ChangeOrder;
+pub use patch::{Patch, PatchError};
+pub use path::{KeyPath, PathSegment};
+pub use pattern::PathPattern;
+pub use redact::REDACTED;
+#[cfg(feature = "render")]
+pub use render::{Format, RenderSink};
+pub use serialize::{diff_serialize, Serialized};
+#[cfg(feature = "render")]
+pub use snapshot::CANONICAL_FORMAT_VERSION;
+pub use span::{style_changes, ChangeSpans, Position, Span, StyleChange, TableStyle};
+// For wrapping lines in the command line tool
+#[doc(hidden)]
+pub use unicode::char_width;
+pub use unicode::UnicodeForm;
+pub use value::{DatetimeKind, DiffValue, ValueKind, ValueType};
+pub use visit::DiffVisitor;
 
-pub struct TomlDiff<'a> {
-    pub changes: Vec<TomlChange<'a>>,
+/// The changes between two documents. Documents are usually [`toml::Value`]s, but can be of any
+/// [`DiffValue`] model.
+pub struct TomlDiff<'a, V = TomlValue> {
+    pub changes: Vec<TomlChange<'a, V>>,
+    /// Number of changes left out of `changes` because of [`DiffOptions::max_changes`] or
+    /// [`DiffOptions::memory_budget`]
+    pub truncated: usize,
+    /// The number of changes left out once the [memory budget](DiffOptions::memory_budget) ran
+    /// out, under each top-level table or array, or under the document itself for changes to
+    /// top-level keys, in the order they were first left out. These count towards `truncated`.
+    pub summaries: Vec<(Vec<PathSegment<'a>>, usize)>,
+    /// The key paths whose values are the same in both documents, along with the new value,
+    /// recorded only with [`DiffOptions::record_unchanged`]
+    pub unchanged: Vec<(Vec<PathSegment<'a>>, &'a V)>,
+    /// The changes left out by each ignore rule, recorded only with
+    /// [`DiffOptions::record_suppressed`]
+    suppressed: Vec<(IgnoreRule, usize)>,
+    /// Whether the diff was made with [`DiffOptions::natural_key_order`]
+    natural_key_order: bool,
+    redactions: Vec<Redaction>,
+    annotations: Vec<Annotation>,
+    #[cfg(feature = "render")]
+    style: display::Style,
+    /// The new and old documents, for [`TomlDiff::update`], unless the diff was made from a
+    /// list of changes
+    documents: Option<(&'a V, &'a V)>,
 }
 
 #[derive(Debug)]
-pub enum TomlChange<'a> {
-    Added(Vec<&'a str>, &'a TomlValue),
-    Deleted(Vec<&'a str>, &'a TomlValue),
+pub enum TomlChange<'a, V = TomlValue> {
+    Added(Vec<PathSegment<'a>>, &'a V),
+    Deleted(Vec<PathSegment<'a>>, &'a V),
+    /// The value of a key present in both documents changed. Holds the old value, then the new.
+    Changed(Vec<PathSegment<'a>>, &'a V, &'a V),
+    /// A key is spelled differently in the two documents, but is the same key once normalized
+    /// according to [`DiffOptions`]. The key path uses the new spelling, and the old spelling of
+    /// the last key is held alongside. Any changes to the key's value are reported separately.
+    KeyStyleChanged(Vec<PathSegment<'a>>, &'a str),
+    /// A key moved from the old key path held alongside to the new key path, as declared by
+    /// [`DiffOptions::alias`]. Any changes to the key's value are reported separately.
+    Moved(Vec<PathSegment<'a>>, Vec<PathSegment<'a>>),
+    /// The elements an array holds in both documents are in a different order. Only reported
+    /// with [`DiffOptions::report_reorders`]. Element `i` of the old array reordered is element
+    /// `permutation[i]` of the old one, so that once the elements deleted from it, which keep
+    /// their places, are deleted and the added ones are added, it's the new array. If no
+    /// elements were added or deleted, element `i` of the new array is element `permutation[i]`
+    /// of the old one.
+    Reordered(Vec<PathSegment<'a>>, Vec<usize>),
 }
 
-impl<'a> TomlDiff<'a> {
+// Not derived, since that would require `V: Clone`
+impl<V> Clone for TomlChange<'_, V> {
+    fn clone(&self) -> Self {
+        match self {
+            TomlChange::Added(key_path, val) => TomlChange::Added(key_path.clone(), val),
+            TomlChange::Deleted(key_path, val) => TomlChange::Deleted(key_path.clone(), val),
+            TomlChange::Changed(key_path, old, new) => {
+                TomlChange::Changed(key_path.clone(), old, new)
+            }
+            TomlChange::KeyStyleChanged(key_path, old_key) => {
+                TomlChange::KeyStyleChanged(key_path.clone(), old_key)
+            }
+            TomlChange::Moved(key_path, old_path) => {
+                TomlChange::Moved(key_path.clone(), old_path.clone())
+            }
+            TomlChange::Reordered(key_path, permutation) => {
+                TomlChange::Reordered(key_path.clone(), permutation.clone())
+            }
+        }
+    }
+}
+
+impl<'a, V> TomlChange<'a, V> {
+    /// The key path of the value that changed
+    pub fn key_path(&self) -> &[PathSegment<'a>] {
+        match self {
+            TomlChange::Added(key_path, _)
+            | TomlChange::Deleted(key_path, _)
+            | TomlChange::Changed(key_path, _, _)
+            | TomlChange::KeyStyleChanged(key_path, _)
+            | TomlChange::Moved(key_path, _)
+            | TomlChange::Reordered(key_path, _) => key_path,
+        }
+    }
+
+    /// The kind of change, as one of `added`, `deleted`, `changed`, `key_style_changed`, `moved`
+    /// or `reordered`. These are the names used for it wherever a diff is rendered for machines.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            TomlChange::Added(..) => "added",
+            TomlChange::Deleted(..) => "deleted",
+            TomlChange::Changed(..) => "changed",
+            TomlChange::KeyStyleChanged(..) => "key_style_changed",
+            TomlChange::Moved(..) => "moved",
+            TomlChange::Reordered(..) => "reordered",
+        }
+    }
+
+    /// The value before the change, for deleted and changed values
+    pub fn old_value(&self) -> Option<&'a V> {
+        match self {
+            TomlChange::Deleted(_, old) | TomlChange::Changed(_, old, _) => Some(old),
+            _ => None,
+        }
+    }
+
+    /// The value after the change, for added and changed values
+    pub fn new_value(&self) -> Option<&'a V> {
+        match self {
+            TomlChange::Added(_, new) | TomlChange::Changed(_, _, new) => Some(new),
+            _ => None,
+        }
+    }
+}
+
+impl<'a, V: DiffValue> TomlChange<'a, V> {
+    /// Whether this is a changed value whose type changed too, as decided by
+    /// [`DiffValue::same_type`]
+    pub fn changes_type(&self) -> bool {
+        matches!(self, TomlChange::Changed(_, old, new) if !old.same_type(new))
+    }
+}
+
+impl<'a> TomlChange<'a> {
+    /// Deserialize the [old value](TomlChange::old_value) into a `T`, so that code reacting to
+    /// a change of configuration can use it with its proper type. Returns `Ok(None)` if the
+    /// change has no old value.
+    pub fn old_as<T: DeserializeOwned>(&self) -> Result<Option<T>, toml::de::Error> {
+        self.old_value()
+            .cloned()
+            .map(TomlValue::try_into)
+            .transpose()
+    }
+
+    /// Like [`TomlChange::old_as`], but deserializes the [new value](TomlChange::new_value).
+    pub fn new_as<T: DeserializeOwned>(&self) -> Result<Option<T>, toml::de::Error> {
+        self.new_value()
+            .cloned()
+            .map(TomlValue::try_into)
+            .transpose()
+    }
+}
+
+impl<'a, V: DiffValue> TomlDiff<'a, V> {
     /// Return a list of differences between `a` and `b`. A is considered "new" and `b` is
     /// considered "old", so items missing from `a` are considdered "deletions", while items
     /// missing from `b` are considered "additions".
     ///
     /// Changes in table keys are always considdered either "deletions" or "additions", while
     /// changes in the value of a key are considdered "changes".
-    pub fn diff(a: &'a TomlValue, b: &'a TomlValue) -> Self {
-        if !matches!((a, b), (TomlValue::Table(_), TomlValue::Table(_))) {
-            panic!("Expected a table at the top level");
+    pub fn diff(a: &'a V, b: &'a V) -> Self {
+        Self::diff_with_options(a, b, &DiffOptions::default())
+    }
+
+    /// Like [`TomlDiff::diff`], but with the comparison tuned by `options`.
+    ///
+    /// Changes come in the same order every time, whatever order the documents' tables keep
+    /// their keys in, including when `toml`'s `preserve_order` feature is enabled. The changes
+    /// inside a table come first, in key order, followed by the changes inside each of its
+    /// nested tables and arrays, in reverse key order, where keys are compared as strings unless
+    /// [`DiffOptions::natural_key_order`] is set. The changes inside an array are ordered
+    /// by the TOML rendering of the elements, with keys sorted.
+    ///
+    /// Panics if `a` or `b` isn't a table, nests values deeper than [`DiffOptions::max_depth`],
+    /// or holds a construct refused by [`DiffOptions::error_on_unsupported`].
+    pub fn diff_with_options(a: &'a V, b: &'a V, options: &DiffOptions<V>) -> Self {
+        Self::try_diff_with_options(a, b, options).unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    /// Like [`TomlDiff::diff_with_options`], but returns an error instead of panicking if `a`
+    /// or `b` isn't a table, nests values deeper than [`DiffOptions::max_depth`] or holds a
+    /// construct refused by [`DiffOptions::error_on_unsupported`], so that documents from
+    /// untrusted sources can be diffed safely.
+    pub fn try_diff_with_options(
+        a: &'a V,
+        b: &'a V,
+        options: &DiffOptions<V>,
+    ) -> Result<Self, DiffError<'a>> {
+        if (a.kind(), b.kind()) != (ValueKind::Table, ValueKind::Table) {
+            return Err(DiffError::NotATable);
+        }
+        if let Some(max_depth) = options.max_depth {
+            for doc in [b, a] {
+                if let Some(key_path) = too_deep(doc, max_depth) {
+                    return Err(DiffError::MaxDepthExceeded {
+                        key_path,
+                        max_depth,
+                    });
+                }
+            }
         }
-        let mut changes = vec![];
+        let mut changes = ChangeList::new(options);
         // Tracks nested Tables and Arrays that are currently being processed.
-        // The third element of the tuple is a list of keys that represent the "path" to the
-        // current Table or Array.
-        let mut stack = vec![(a, b, vec![])];
+        // The third element of the tuple is the "path" of keys to the current Table or Array.
+        let mut stack = vec![(a, b, SharedPath::default())];
+        let progress = options
+            .on_progress
+            .as_ref()
+            .map(|callback| Progress::new(a, b, &*callback.0));
+        let progress = progress.as_ref();
+        #[cfg(feature = "parallel")]
+        if options.parallel {
+            // Diff the top level here, leaving its nested Tables and Arrays on the stack
+            let (a, b, key_path) = stack.pop().unwrap();
+            diff_level(a, b, key_path, options, progress, &mut changes, &mut stack);
+            parallel::diff_subtrees(&mut stack, options, progress, &mut changes);
+        }
         while let Some((a, b, key_path)) = stack.pop() {
-            match (a, b) {
-                (TomlValue::Array(a), TomlValue::Array(b)) => {
-                    // Get each value's toml representation and store it alongside
-                    let mut a: Vec<_> =
-                        a.iter().map(|e| (e, toml::to_string(e).unwrap())).collect();
-                    let mut b: Vec<_> =
-                        b.iter().map(|e| (e, toml::to_string(e).unwrap())).collect();
-                    // Sort the lists by their toml representations
-                    a.sort_by(|x, y| x.1.cmp(&y.1));
-                    b.sort_by(|x, y| x.1.cmp(&y.1));
-                    let mut a = a.into_iter().peekable();
-                    let mut b = b.into_iter().peekable();
-
-                    while let (Some((&ref a_elem, a_toml)), Some((&ref b_elem, b_toml))) =
-                        (a.peek(), b.peek())
-                    {
-                        // Toml values are sorted low to high, so if the values are different, that
-                        // means that the lesser value is missing from the other array.
-                        match a_toml.cmp(b_toml) {
-                            Ordering::Less => {
-                                // Elements missing from `b` are considered "added" in `a`
-                                changes.push(TomlChange::Added(key_path.clone(), a_elem));
-                                a.next();
-                            }
-                            Ordering::Greater => {
-                                // Elements missing from `a` are considered "deleted" from `b`
-                                changes.push(TomlChange::Deleted(key_path.clone(), b_elem));
-                                b.next();
-                            }
-                            Ordering::Equal => {
-                                a.next();
-                                b.next();
+            diff_level(a, b, key_path, options, progress, &mut changes, &mut stack);
+        }
+        if let Some((key_path, construct)) = changes.unsupported {
+            return Err(DiffError::Unsupported {
+                key_path,
+                construct,
+            });
+        }
+        for (old, new) in &options.aliases {
+            alias::apply(old, new, options, &mut changes.changes);
+        }
+        if let Some(progress) = progress {
+            progress.finish();
+        }
+        Ok(Self {
+            summaries: changes.summaries,
+            unchanged: changes.unchanged.unwrap_or_default(),
+            suppressed: changes.suppressed.unwrap_or_default(),
+            natural_key_order: options.natural_key_order,
+            #[cfg(feature = "render")]
+            style: display::Style::with_options(),
+            documents: Some((a, b)),
+            ..Self::from_changes(changes.changes, changes.truncated)
+        })
+    }
+
+    /// A diff of `changes`, with no redactions, annotations or unchanged key paths
+    pub(crate) fn from_changes(changes: Vec<TomlChange<'a, V>>, truncated: usize) -> Self {
+        Self {
+            changes,
+            truncated,
+            summaries: vec![],
+            unchanged: vec![],
+            suppressed: vec![],
+            natural_key_order: false,
+            redactions: vec![],
+            #[cfg(feature = "render")]
+            style: display::Style::default(),
+            annotations: vec![],
+            documents: None,
+        }
+    }
+
+    /// The number of changes each ignore rule left out of the diff, in the order the rules first
+    /// left one out, for the rules that did. Only recorded with
+    /// [`DiffOptions::record_suppressed`], and kept as the diff is made partial or sorted.
+    pub fn suppressed(&self) -> &[(IgnoreRule, usize)] {
+        &self.suppressed
+    }
+
+    /// Keep only the changes for which `keep` returns `true`, producing a partial diff that can
+    /// be rendered or [applied](TomlDiff::apply) like any other. `keep` is called once for each
+    /// change, in order. Changes left out by [`DiffOptions::max_changes`] or
+    /// [`DiffOptions::memory_budget`] can't be selected, so the partial diff is never truncated.
+    pub fn select(mut self, keep: impl FnMut(&TomlChange<'a, V>) -> bool) -> Self {
+        self.changes.retain(keep);
+        self.truncated = 0;
+        self.summaries.clear();
+        // A partial diff can't be updated, since the changes left out would stay out
+        self.documents = None;
+        self
+    }
+
+    /// Split the diff into a diff for each top-level key with changes under it, so that changes
+    /// to different areas of a document, like `[database]` and `[features]`, can be routed to
+    /// different reviewers. Each diff is redacted and annotated like this one, and renders the
+    /// same way. Changes left out by [`DiffOptions::max_changes`] can't be split up, so the
+    /// diffs are never truncated, and a change to the document as a whole, which only a
+    /// [patch](crate::patch) can hold, is left out.
+    pub fn split_by_top_level(mut self) -> BTreeMap<&'a str, Self> {
+        let mut diffs = BTreeMap::new();
+        for change in std::mem::take(&mut self.changes) {
+            let Some(key) = change.key_path().first().and_then(PathSegment::as_key) else {
+                continue;
+            };
+            let diff = diffs.entry(key).or_insert_with(|| TomlDiff {
+                natural_key_order: self.natural_key_order,
+                redactions: self.redactions.clone(),
+                annotations: self.annotations.clone(),
+                #[cfg(feature = "render")]
+                style: self.style.clone(),
+                ..TomlDiff::from_changes(vec![], 0)
+            });
+            diff.changes.push(change);
+        }
+        diffs
+    }
+
+    /// Keep only the changes that touch key paths matching `pattern`, like `servers[*].port`,
+    /// as decided by [`PathPattern::selects`]. The partial diff is like one from
+    /// [`TomlDiff::select`].
+    pub fn select_path(self, pattern: impl Into<PathPattern>) -> Self {
+        let pattern = pattern.into();
+        self.select(|change| pattern.selects(change.key_path()))
+    }
+}
+
+impl<'a> TomlDiff<'a> {
+    /// Render values under any key path matching `pattern` as [`REDACTED`].
+    ///
+    /// Redacted values are still compared as normal, so a changed secret still shows up as a
+    /// change, but its old and new values are hidden in every rendered form of the diff.
+    pub fn redact(mut self, pattern: impl Into<PathPattern>) -> Self {
+        self.redactions.push(Redaction::Pattern(pattern.into()));
+        self
+    }
+
+    /// Like [`TomlDiff::redact`], but redacts every key path for which `predicate` returns `true`.
+    pub fn redact_with(mut self, predicate: impl Fn(&[&str]) -> bool + 'static) -> Self {
+        self.redactions
+            .push(Redaction::Predicate(Rc::new(predicate)));
+        self
+    }
+
+    /// Keep only the changes to values of one of `types`, so that hand-edited strings can be
+    /// reviewed apart from numbers an auto-tuner rewrites. A changed value is kept if either its
+    /// old or new value is of one of them, and a reordered array if [`ValueType::Array`] is.
+    /// Changes are made to the values that differ, so a table only counts for added and
+    /// deleted tables, not for the changes inside one. Renamed and moved keys are left out,
+    /// since no value of theirs changed. The partial diff is like one from
+    /// [`TomlDiff::select`].
+    pub fn retain_types(self, types: &[ValueType]) -> Self {
+        self.select(|change| match change {
+            TomlChange::Added(_, val) | TomlChange::Deleted(_, val) => {
+                types.contains(&ValueType::of(val))
+            }
+            TomlChange::Changed(_, old, new) => {
+                types.contains(&ValueType::of(old)) || types.contains(&ValueType::of(new))
+            }
+            TomlChange::Reordered(..) => types.contains(&ValueType::Array),
+            TomlChange::KeyStyleChanged(..) | TomlChange::Moved(..) => false,
+        })
+    }
+}
+
+/// An error from [`TomlDiff::try_diff_with_options`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum DiffError<'a> {
+    /// One of the documents isn't a table
+    NotATable,
+    /// One of the documents has a value at `key_path`, which is more than `max_depth` keys and
+    /// indices long. Only the first `max_depth + 1` segments of the path are kept.
+    MaxDepthExceeded {
+        key_path: Vec<PathSegment<'a>>,
+        max_depth: usize,
+    },
+    /// One of the documents holds a construct at `key_path` that can't be diffed precisely,
+    /// with [`DiffOptions::error_on_unsupported`] set
+    Unsupported {
+        key_path: Vec<PathSegment<'a>>,
+        construct: UnsupportedConstruct,
+    },
+}
+
+/// Something [`DiffOptions::error_on_unsupported`] refuses to diff, since its diff would be
+/// approximated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnsupportedConstruct {
+    /// A value that isn't equal to itself, like a NaN float, which is reported as changed even
+    /// when both documents hold it
+    UnequalToItself,
+    /// Tables or arrays in an array that aren't in the other document's array, when keys or
+    /// values are compared with options that don't apply to array elements, like
+    /// [`DiffOptions::ignore`] and [`DiffOptions::key_case_insensitive`]. Elements are matched
+    /// up exactly, so they may be reported as added and deleted even though the options would
+    /// have them the same.
+    NestedInArray,
+}
+
+impl fmt::Display for DiffError<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DiffError::NotATable => write!(f, "Expected a table at the top level"),
+            DiffError::MaxDepthExceeded {
+                key_path,
+                max_depth,
+            } => {
+                // The path is cut short, since it's longer than the limit, which may be huge
+                let shown = &key_path[..key_path.len().min(8)];
+                let ellipsis = if shown.len() < key_path.len() {
+                    "..."
+                } else {
+                    ""
+                };
+                write!(
+                    f,
+                    "`{}{ellipsis}` is nested deeper than the limit of {max_depth}",
+                    path::format_key_path(shown),
+                )
+            }
+            DiffError::Unsupported {
+                key_path,
+                construct,
+            } => {
+                let key_path = path::format_key_path(key_path);
+                match construct {
+                    UnsupportedConstruct::UnequalToItself => {
+                        write!(f, "`{key_path}` holds a value that isn't equal to itself")
+                    }
+                    UnsupportedConstruct::NestedInArray => write!(
+                        f,
+                        "`{key_path}` holds tables or arrays that can't be matched up with \
+                         the options given"
+                    ),
+                }
+            }
+        }
+    }
+}
+
+impl std::error::Error for DiffError<'_> {}
+
+/// The key path of a value of `doc` that's nested more than `max_depth` keys and indices deep,
+/// if it has any. Since the path stops at the first value too deep, it's `max_depth + 1` long.
+fn too_deep<V: DiffValue>(doc: &V, max_depth: usize) -> Option<Vec<PathSegment<'_>>> {
+    let mut stack = vec![(doc, SharedPath::default())];
+    while let Some((val, key_path)) = stack.pop() {
+        let children: Vec<_> = match val.kind() {
+            ValueKind::Table => val
+                .entries()
+                .map(|(key, child)| (child, PathSegment::Key(key)))
+                .collect(),
+            ValueKind::Array => val
+                .elements()
+                .enumerate()
+                .map(|(i, child)| (child, PathSegment::Index(i)))
+                .collect(),
+            ValueKind::Scalar => continue,
+        };
+        for (child, segment) in children {
+            let child_path = key_path.child(segment);
+            if child_path.len() > max_depth {
+                return Some(child_path.to_vec());
+            }
+            stack.push((child, child_path));
+        }
+    }
+    None
+}
+
+/// A pair of nested Tables or Arrays waiting to be diffed, along with their key path
+type StackItem<'a, V> = (&'a V, &'a V, SharedPath<'a>);
+
+/// Diff one level of a pair of Tables or Arrays, pushing any nested Tables and Arrays that need
+/// diffing in turn onto `stack`.
+fn diff_level<'a, V: DiffValue>(
+    a: &'a V,
+    b: &'a V,
+    path: SharedPath<'a>,
+    options: &DiffOptions<V>,
+    progress: Option<&Progress>,
+    changes: &mut ChangeList<'a, V>,
+    stack: &mut Vec<StackItem<'a, V>>,
+) {
+    let key_path = path.to_vec();
+    // Keys that only exist on one side are skipped entirely if this returns `true`
+    let ignore_missing =
+        |val: &V| options.values_only || (options.empty_equals_missing && is_empty(val));
+    match (a.kind(), b.kind()) {
+        (ValueKind::Array, ValueKind::Array) if options.values_only => {
+            // Without additions and deletions, an array can only change as a whole
+            let sort_keys = |array: &V| {
+                let mut keys: Vec<_> = array.elements().map(|e| options.sort_key(e)).collect();
+                keys.sort();
+                if options.arrays_as_sets {
+                    keys.dedup();
+                }
+                keys
+            };
+            if sort_keys(a) != sort_keys(b) {
+                changes.push(TomlChange::Changed(key_path, b, a));
+            }
+        }
+        (ValueKind::Array, ValueKind::Array) => {
+            let (new_array, b_array) = (a, b);
+            let a: Vec<_> = a.elements().collect();
+            let b: Vec<_> = b.elements().collect();
+            let (a_len, b_len) = (a.len(), b.len());
+            if let Some(progress) = progress {
+                progress.visit(a_len + b_len);
+            }
+            // Elements at the same place from the start or the end of both arrays match without
+            // having their sort keys made, so appending to a huge array is cheap to diff. Arrays
+            // compared as sets could hold an element both here and elsewhere, so they're exempt.
+            let (prefix, suffix) = if options.arrays_as_sets {
+                (0, 0)
+            } else {
+                common_ends(&a, &b)
+            };
+            // Arrays too long to match up element by element change as a whole
+            let longest = (a_len - prefix - suffix).max(b_len - prefix - suffix);
+            if options.max_array_len.is_some_and(|max| longest > max) {
+                changes.push(TomlChange::Changed(key_path, b_array, new_array));
+                return;
+            }
+            // Get each value's sort key (its toml representation, for toml values) and store it
+            // alongside, along with the value's index in its array
+            let mut a: Vec<_> = a[prefix..a_len - suffix]
+                .iter()
+                .enumerate()
+                .map(|(i, e)| (prefix + i, *e, options.sort_key(*e)))
+                .collect();
+            let mut b: Vec<_> = b[prefix..b_len - suffix]
+                .iter()
+                .enumerate()
+                .map(|(i, e)| (prefix + i, *e, options.sort_key(*e)))
+                .collect();
+            // Sort the lists by their sort keys
+            a.sort_by(|x, y| x.2.cmp(&y.2));
+            b.sort_by(|x, y| x.2.cmp(&y.2));
+            if options.arrays_as_sets {
+                // The sort is stable, so the first of each run of equal elements is kept
+                a.dedup_by(|x, y| x.2 == y.2);
+                b.dedup_by(|x, y| x.2 == y.2);
+            }
+            let mut a = a.into_iter().peekable();
+            let mut b = b.into_iter().peekable();
+            let elem_path = |i| {
+                let mut key_path = key_path.clone();
+                key_path.push(PathSegment::Index(i));
+                key_path
+            };
+
+            // The old index of each element of `a` that `b` also has
+            let mut matched: Vec<_> = (0..prefix)
+                .map(|i| (i, i))
+                .chain((1..=suffix).map(|i| (a_len - i, b_len - i)))
+                .collect();
+            // The additions and deletions of elements, before any are paired up by similarity
+            let mut unmatched = vec![];
+            while let (Some(&(a_i, a_elem, ref a_toml)), Some(&(b_i, b_elem, ref b_toml))) =
+                (a.peek(), b.peek())
+            {
+                // Sort keys are sorted low to high, so if the values are different, that
+                // means that the lesser value is missing from the other array.
+                match a_toml.cmp(b_toml) {
+                    Ordering::Less => {
+                        // Elements missing from `b` are considered "added" in `a`
+                        unmatched.push(TomlChange::Added(elem_path(a_i), a_elem));
+                        a.next();
+                    }
+                    Ordering::Greater => {
+                        // Elements missing from `a` are considered "deleted" from `b`
+                        unmatched.push(TomlChange::Deleted(elem_path(b_i), b_elem));
+                        b.next();
+                    }
+                    Ordering::Equal => {
+                        matched.push((a_i, b_i));
+                        a.next();
+                        b.next();
+                    }
+                }
+            }
+            // Anything left over in `a` is an addition (doesn't exist in `b`) and vice versa
+            unmatched.extend(a.map(|(i, a_elem, _)| TomlChange::Added(elem_path(i), a_elem)));
+            unmatched.extend(b.map(|(i, b_elem, _)| TomlChange::Deleted(elem_path(i), b_elem)));
+            if let Some(keys) = options.match_keys(&path::keys(&key_path)) {
+                let pairs = pair_by_keys(&unmatched, keys);
+                diff_pairs(&mut unmatched, &pairs, &path, stack, &mut matched);
+            }
+            if options.match_similar {
+                let pairs = pair_similar(&unmatched);
+                diff_pairs(&mut unmatched, &pairs, &path, stack, &mut matched);
+            }
+            if options.error_on_unsupported && options.tunes_nested_values() {
+                let nested = unmatched.iter().any(|change| match change {
+                    TomlChange::Added(_, val) | TomlChange::Deleted(_, val) => {
+                        val.kind() != ValueKind::Scalar
+                    }
+                    _ => false,
+                });
+                if nested {
+                    changes.unsupported(&key_path, UnsupportedConstruct::NestedInArray);
+                }
+            }
+            let same = unmatched.is_empty();
+            changes.extend(unmatched);
+            // The elements both arrays hold moved if they come in a different order in each
+            matched.sort_by_key(|&(_, b_i)| b_i);
+            let moved = matched.windows(2).any(|pair| pair[0].0 > pair[1].0);
+            if options.report_reorders && moved {
+                // The old elements both arrays hold are put in the new array's order in the
+                // places they already take up, so deleted elements keep their old indices
+                let slots: Vec<_> = matched.iter().map(|&(_, b_i)| b_i).collect();
+                matched.sort();
+                let mut permutation: Vec<_> = (0..b_len).collect();
+                for (slot, (_, b_i)) in slots.into_iter().zip(matched) {
+                    permutation[slot] = b_i;
+                }
+                changes.push(TomlChange::Reordered(key_path, permutation));
+            } else if same && !key_path.is_empty() {
+                changes.same(&key_path, new_array);
+            }
+        }
+        (ValueKind::Table, ValueKind::Table) => {
+            let mut a_pairs: Vec<_> = a.entries().collect();
+            let mut b_pairs: Vec<_> = b.entries().collect();
+            if let Some(progress) = progress {
+                progress.visit(a_pairs.len() + b_pairs.len());
+            }
+            if !options.ignored.is_empty() {
+                let mut keys = path::keys(&key_path);
+                let mut ignored_by = |key| {
+                    keys.push(key);
+                    let pattern = options.ignored_by(&keys);
+                    keys.pop();
+                    pattern
+                };
+                if changes.suppressed.is_some() {
+                    // An ignored key hides a change unless it's the same in both documents
+                    let b_values: HashMap<_, _> = b_pairs.iter().copied().collect();
+                    for &(key, a_val) in &a_pairs {
+                        if let Some(pattern) = ignored_by(key) {
+                            if b_values.get(key) != Some(&a_val) {
+                                changes.suppress(IgnoreRule::Ignore, pattern);
                             }
                         }
                     }
-                    // Anything left over in `a` is an addition (doesn't exist in `b`) and vice versa
-                    changes
-                        .extend(a.map(|(a_elem, _)| TomlChange::Added(key_path.clone(), a_elem)));
-                    changes
-                        .extend(b.map(|(b_elem, _)| TomlChange::Deleted(key_path.clone(), b_elem)));
+                    let a_keys: HashSet<_> = a_pairs.iter().map(|&(key, _)| key).collect();
+                    for &(key, _) in &b_pairs {
+                        if let Some(pattern) = ignored_by(key).filter(|_| !a_keys.contains(key)) {
+                            changes.suppress(IgnoreRule::Ignore, pattern);
+                        }
+                    }
                 }
-                (TomlValue::Table(a), TomlValue::Table(b)) => {
-                    let mut a_pairs: Vec<_> = a.iter().collect();
-                    let mut b_pairs: Vec<_> = b.iter().collect();
-                    a_pairs.sort_by_key(|e| e.0);
-                    b_pairs.sort_by_key(|e| e.0);
-                    let mut a_pairs_it = a_pairs.into_iter().peekable();
-                    let mut b_pairs_it = b_pairs.into_iter().peekable();
-
-                    while let (Some((&ref a_key, &ref a_val)), Some((&ref b_key, &ref b_val))) =
-                        (a_pairs_it.peek(), b_pairs_it.peek())
-                    {
-                        // Keys are sorted low to high, so if the keys are different, that means
-                        // that the lesser key is missing from the other table.
-                        match a_key.cmp(b_key) {
-                            Ordering::Less => {
-                                // Keys missing from `b` are considdered "added" in `a`
-                                let mut key_path = key_path.clone();
-                                key_path.push(a_key);
-                                changes.push(TomlChange::Added(key_path, a_val));
-                                a_pairs_it.next();
-                                continue;
+                a_pairs.retain(|(key, _)| ignored_by(key).is_none());
+                b_pairs.retain(|(key, _)| ignored_by(key).is_none());
+            }
+            // Keys are matched up by their normalized form, which is the key itself unless
+            // key normalization is enabled. Keys that normalize to the same form as another key
+            // of the same table can't be told apart that way, so all keys of that form are
+            // matched exactly.
+            let mut collisions = options.colliding_keys(a_pairs.iter().map(|&(key, _)| key));
+            collisions.extend(options.colliding_keys(b_pairs.iter().map(|&(key, _)| key)));
+            let cmp = |x, y| options.compare_keys(x, y, &collisions);
+            a_pairs.sort_by(|x, y| cmp(x.0, y.0));
+            b_pairs.sort_by(|x, y| cmp(x.0, y.0));
+            let mut a_pairs_it = a_pairs.into_iter().peekable();
+            let mut b_pairs_it = b_pairs.into_iter().peekable();
+
+            while let (Some(&(a_key, a_val)), Some(&(b_key, b_val))) =
+                (a_pairs_it.peek(), b_pairs_it.peek())
+            {
+                // Keys are sorted low to high, so if the keys are different, that means
+                // that the lesser key is missing from the other table.
+                match cmp(a_key, b_key) {
+                    Ordering::Less if ignore_missing(a_val) => {
+                        a_pairs_it.next();
+                    }
+                    Ordering::Greater if ignore_missing(b_val) => {
+                        b_pairs_it.next();
+                    }
+                    Ordering::Less => {
+                        // Keys missing from `b` are considdered "added" in `a`
+                        let mut key_path = key_path.clone();
+                        key_path.push(PathSegment::Key(a_key));
+                        changes.push(TomlChange::Added(key_path, a_val));
+                        a_pairs_it.next();
+                        continue;
+                    }
+                    Ordering::Greater => {
+                        // Keys missing from `a` are considered "deleted" from `b`
+                        let mut key_path = key_path.clone();
+                        key_path.push(PathSegment::Key(b_key));
+                        changes.push(TomlChange::Deleted(key_path, b_val));
+                        b_pairs_it.next();
+                        continue;
+                    }
+                    Ordering::Equal => {
+                        a_pairs_it.next();
+                        b_pairs_it.next();
+
+                        let mut key_path = key_path.clone();
+                        key_path.push(PathSegment::Key(a_key));
+                        // Keys are the same once normalized, but are spelled differently, which
+                        // doesn't count if only their Unicode normalization form differs
+                        let spelled_differently =
+                            options.normalize_text(a_key) != options.normalize_text(b_key);
+                        if spelled_differently && !options.values_only {
+                            changes.push(TomlChange::KeyStyleChanged(key_path.clone(), b_key));
+                        }
+
+                        // Keys are the same
+                        let custom_eq = || {
+                            let (pattern, eq) = options.comparator(&path::keys(&key_path))?;
+                            eq(b_val, a_val).then_some(pattern)
+                        };
+                        if a_val == b_val
+                            || options.same_string(a_val, b_val)
+                            || options.is_placeholder(b_val)
+                        {
+                            changes.same(&key_path, a_val);
+                            continue;
+                        }
+                        // The values only count as the same by a custom equality, which hides a
+                        // change
+                        if let Some(pattern) = custom_eq() {
+                            changes.suppress(IgnoreRule::Compare, pattern);
+                            changes.same(&key_path, a_val);
+                            continue;
+                        }
+                        // Values are different
+
+                        // Tables with most of their keys changed are reported as a whole
+                        let coalesce = || {
+                            let changed = 1.0 - similarity(a_val, b_val);
+                            options
+                                .coalesce_tables
+                                .is_some_and(|threshold| changed > threshold)
+                                && !options.ignores_within(&path::keys(&key_path))
+                        };
+                        match (a_val.kind(), b_val.kind()) {
+                            (ValueKind::Table, ValueKind::Table) if coalesce() => {
+                                changes.push(TomlChange::Changed(key_path, b_val, a_val))
                             }
-                            Ordering::Greater => {
-                                // Keys missing from `a` are considered "deleted" from `b`
-                                let mut key_path = key_path.clone();
-                                key_path.push(b_key);
-                                changes.push(TomlChange::Deleted(key_path, b_val));
-                                b_pairs_it.next();
-                                continue;
+                            (ValueKind::Table, ValueKind::Table) => {
+                                stack.push((a_val, b_val, path.child(PathSegment::Key(a_key))))
                             }
-                            Ordering::Equal => {
-                                a_pairs_it.next();
-                                b_pairs_it.next();
-
-                                // Keys are the same
-                                if a_val == b_val {
-                                    continue;
-                                }
-                                // Values are different
-                                let mut key_path = key_path.clone();
-                                key_path.push(a_key);
-
-                                if discriminant(a_val) != discriminant(b_val) {
-                                    // Values have different types
-                                    changes.push(TomlChange::Added(key_path.clone(), a_val));
-                                    changes.push(TomlChange::Deleted(key_path, b_val));
-                                    continue;
-                                }
-                                if a_val.is_table() || a_val.is_array() {
-                                    stack.push((a_val, b_val, key_path));
-                                    continue;
+                            (ValueKind::Array, ValueKind::Array) if !options.structure_only => {
+                                stack.push((a_val, b_val, path.child(PathSegment::Key(a_key))))
+                            }
+                            // Only a change of type counts as a change of structure
+                            _ if options.structure_only && a_val.same_type(b_val) => {}
+                            // Values are scalars, or have different types
+                            _ => {
+                                if options.error_on_unsupported
+                                    && a_val.sort_key() == b_val.sort_key()
+                                {
+                                    changes.unsupported(
+                                        &key_path,
+                                        UnsupportedConstruct::UnequalToItself,
+                                    );
                                 }
-                                changes.push(TomlChange::Added(key_path.clone(), a_val));
-                                changes.push(TomlChange::Deleted(key_path, b_val));
+                                changes.push(TomlChange::Changed(key_path, b_val, a_val))
                             }
                         }
                     }
-                    // Anything left over in `a_pairs_it` is an addition (doesn't exist in `b`) and vice versa
-                    changes.extend(a_pairs_it.map(|(k, v)| {
-                        let mut key_path = key_path.clone();
-                        key_path.push(k);
-                        TomlChange::Added(key_path, v)
-                    }));
-                    changes.extend(b_pairs_it.map(|(k, v)| {
-                        let mut key_path = key_path.clone();
-                        key_path.push(k);
-                        TomlChange::Deleted(key_path, v)
-                    }))
                 }
-                _ => unreachable!("We only ever push `Array`s and `Table`s to `stack`"),
+            }
+            // Anything left over in `a_pairs_it` is an addition (doesn't exist in `b`) and vice versa
+            let a_pairs_it = a_pairs_it.filter(|(_, v)| !ignore_missing(v));
+            let b_pairs_it = b_pairs_it.filter(|(_, v)| !ignore_missing(v));
+            changes.extend(a_pairs_it.map(|(k, v)| {
+                let mut key_path = key_path.clone();
+                key_path.push(PathSegment::Key(k));
+                TomlChange::Added(key_path, v)
+            }));
+            changes.extend(b_pairs_it.map(|(k, v)| {
+                let mut key_path = key_path.clone();
+                key_path.push(PathSegment::Key(k));
+                TomlChange::Deleted(key_path, v)
+            }))
+        }
+        _ => unreachable!("We only ever push `Array`s and `Table`s to `stack`"),
+    }
+}
+
+/// The number of elements `a` and `b` have in common at their starts, then at their ends, not
+/// counting any element twice
+fn common_ends<V: PartialEq>(a: &[&V], b: &[&V]) -> (usize, usize) {
+    let prefix = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+    let (a, b) = (&a[prefix..], &b[prefix..]);
+    let suffix = a
+        .iter()
+        .rev()
+        .zip(b.iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    (prefix, suffix)
+}
+
+/// Diff each pair of an added and a deleted element of `unmatched` like tables at the old
+/// element's index, leaving the paired elements out of `unmatched` and recording their new and
+/// old indices in `matched`
+fn diff_pairs<'a, V: DiffValue>(
+    unmatched: &mut Vec<TomlChange<'a, V>>,
+    pairs: &[(usize, usize)],
+    path: &SharedPath<'a>,
+    stack: &mut Vec<StackItem<'a, V>>,
+    matched: &mut Vec<(usize, usize)>,
+) {
+    let mut paired = vec![false; unmatched.len()];
+    for &(added, deleted) in pairs {
+        if let (TomlChange::Added(a_path, a_elem), TomlChange::Deleted(b_path, b_elem)) =
+            (&unmatched[added], &unmatched[deleted])
+        {
+            let index = *b_path.last().unwrap();
+            stack.push((*a_elem, *b_elem, path.child(index)));
+            let a_i = a_path.last().and_then(PathSegment::as_index);
+            if let (Some(a_i), Some(b_i)) = (a_i, index.as_index()) {
+                matched.push((a_i, b_i));
+            }
+        }
+        paired[added] = true;
+        paired[deleted] = true;
+    }
+    let mut paired = paired.into_iter();
+    unmatched.retain(|_| !paired.next().unwrap());
+}
+
+/// Pair up the added and deleted tables among `unmatched` that hold the same values under
+/// `keys`, in the order of the added tables. Each pair holds the index in `unmatched` of the
+/// added table, then of the deleted one.
+fn pair_by_keys<V: DiffValue>(unmatched: &[TomlChange<V>], keys: &[String]) -> Vec<(usize, usize)> {
+    // The deleted tables of each identity, in order, looked up by the sort keys of its values
+    let sort_keys = |identity: Vec<Option<&V>>| -> Vec<_> {
+        identity
+            .into_iter()
+            .map(|val| val.map(V::sort_key))
+            .collect()
+    };
+    let mut deleted: HashMap<_, VecDeque<usize>> = HashMap::new();
+    for (i, change) in unmatched.iter().enumerate() {
+        if let TomlChange::Deleted(_, b) = change {
+            if let Some(b) = identity(*b, keys) {
+                deleted.entry(sort_keys(b)).or_default().push_back(i);
             }
         }
-        Self { changes }
+    }
+    let mut pairs = vec![];
+    for (added, a) in unmatched.iter().enumerate() {
+        let TomlChange::Added(_, a) = a else { continue };
+        let Some(a) = identity(*a, keys) else {
+            continue;
+        };
+        if let Some(deleted) = deleted.get_mut(&sort_keys(a)).and_then(VecDeque::pop_front) {
+            pairs.push((added, deleted));
+        }
+    }
+    pairs
+}
+
+/// The values a table holds under `keys`, unless it isn't a table or holds none of them
+fn identity<'v, V: DiffValue>(val: &'v V, keys: &[String]) -> Option<Vec<Option<&'v V>>> {
+    if val.kind() != ValueKind::Table {
+        return None;
+    }
+    let values: Vec<_> = keys
+        .iter()
+        .map(|key| val.entries().find(|(k, _)| k == key).map(|(_, v)| v))
+        .collect();
+    values.iter().any(Option::is_some).then_some(values)
+}
+
+/// The most pairs of tables [`pair_similar`] compares in one array. Past it, only the tables
+/// sharing an entry that no other table holds are compared.
+const MAX_SIMILARITY_CHECKS: usize = 1 << 16;
+
+/// Pair up the added and deleted tables among `unmatched` that hold the same values under at
+/// least half of their keys, most similar first. Each pair holds the index in `unmatched` of the
+/// added table, then of the deleted one.
+fn pair_similar<V: DiffValue>(unmatched: &[TomlChange<V>]) -> Vec<(usize, usize)> {
+    let tables = |added: bool| -> Vec<_> {
+        unmatched
+            .iter()
+            .enumerate()
+            .filter_map(move |(i, change)| match change {
+                TomlChange::Added(_, val) if added => Some((i, *val)),
+                TomlChange::Deleted(_, val) if !added => Some((i, *val)),
+                _ => None,
+            })
+            .filter(|(_, val)| val.kind() == ValueKind::Table)
+            .collect()
+    };
+    let (added, deleted) = (tables(true), tables(false));
+    // Tables without an entry in common aren't similar at all, so only those sharing an entry
+    // are compared, found by each entry's key and the sort key of its value. The added, then the
+    // deleted tables holding each entry:
+    let mut holders: HashMap<_, (Vec<usize>, Vec<usize>)> = HashMap::new();
+    for (n, (_, a)) in added.iter().enumerate() {
+        for (key, val) in a.entries() {
+            holders.entry((key, val.sort_key())).or_default().0.push(n);
+        }
+    }
+    for (n, (_, b)) in deleted.iter().enumerate() {
+        for (key, val) in b.entries() {
+            holders.entry((key, val.sort_key())).or_default().1.push(n);
+        }
+    }
+    let checks: usize = holders.values().map(|(a, b)| a.len() * b.len()).sum();
+    let mut candidates = vec![];
+    for (a, b) in holders.values() {
+        // Entries shared by many tables would have each of them compared with all the others,
+        // so past the limit, tables are only paired by the entries unique to them, like the
+        // anchors of a patience diff
+        if checks > MAX_SIMILARITY_CHECKS && (a.len() != 1 || b.len() != 1) {
+            continue;
+        }
+        for &a in a {
+            candidates.extend(b.iter().map(|&b| (a, b)));
+        }
+    }
+    // In the order the tables come in, whatever order the entries were visited in
+    candidates.sort();
+    candidates.dedup();
+    let mut candidates: Vec<_> = candidates
+        .into_iter()
+        .filter_map(|(a, b)| {
+            let ((added, a), (deleted, b)) = (added[a], deleted[b]);
+            let score = similarity(a, b);
+            (score >= 0.5).then_some((score, added, deleted))
+        })
+        .collect();
+    // The sort is stable, so equally similar pairs stay in the order their elements came in
+    candidates.sort_by(|x, y| y.0.total_cmp(&x.0));
+    let mut paired = vec![false; unmatched.len()];
+    let mut pairs = vec![];
+    for (_, added, deleted) in candidates {
+        if !paired[added] && !paired[deleted] {
+            paired[added] = true;
+            paired[deleted] = true;
+            pairs.push((added, deleted));
+        }
+    }
+    pairs
+}
+
+/// The fraction of the keys of two tables under which both hold the same value
+fn similarity<V: DiffValue>(a: &V, b: &V) -> f64 {
+    let b_entries: HashMap<&str, &V> = b.entries().collect();
+    let (mut keys, mut same) = (b_entries.len(), 0);
+    for (key, a_val) in a.entries() {
+        match b_entries.get(key) {
+            Some(&b_val) if b_val == a_val => same += 1,
+            Some(_) => {}
+            None => keys += 1,
+        }
+    }
+    same as f64 / keys.max(1) as f64
+}
+
+/// Collects changes up to an optional maximum and memory budget, counting any changes past
+/// them.
+struct ChangeList<'a, V> {
+    changes: Vec<TomlChange<'a, V>>,
+    max: Option<usize>,
+    truncated: usize,
+    /// The memory budget, how much of it the changes use, and the changes left out once it ran
+    /// out
+    budget: Option<usize>,
+    used: usize,
+    summaries: Vec<(Vec<PathSegment<'a>>, usize)>,
+    /// The rules of [`DiffOptions::ignore_change`]
+    ignored_changes: Vec<options::ChangeRule<V>>,
+    /// The unchanged key paths, if they're being recorded
+    unchanged: Option<Vec<(Vec<PathSegment<'a>>, &'a V)>>,
+    /// The changes left out by each ignore rule, if they're being recorded
+    suppressed: Option<Vec<(IgnoreRule, usize)>>,
+    /// Whether to record constructs that can't be diffed precisely, and the first one found
+    error_on_unsupported: bool,
+    unsupported: Option<(Vec<PathSegment<'a>>, UnsupportedConstruct)>,
+}
+
+impl<'a, V> ChangeList<'a, V> {
+    fn new(options: &DiffOptions<V>) -> Self {
+        Self {
+            changes: vec![],
+            max: options.max_changes,
+            truncated: 0,
+            budget: options.memory_budget,
+            used: 0,
+            summaries: vec![],
+            ignored_changes: options.ignored_changes.clone(),
+            unchanged: options.record_unchanged.then(Vec::new),
+            suppressed: options.record_suppressed.then(Vec::new),
+            error_on_unsupported: options.error_on_unsupported,
+            unsupported: None,
+        }
+    }
+
+    /// Record that the value at `key_path` is a construct that can't be diffed precisely, if
+    /// it's the first one found and they're being refused
+    fn unsupported(&mut self, key_path: &[PathSegment<'a>], construct: UnsupportedConstruct) {
+        if self.error_on_unsupported && self.unsupported.is_none() {
+            self.unsupported = Some((key_path.to_vec(), construct));
+        }
+    }
+
+    /// Count a change left out by the ignore rule `rule` made with `pattern`, if they're being
+    /// recorded
+    fn suppress(&mut self, rule: fn(PathPattern) -> IgnoreRule, pattern: &PathPattern) {
+        if self.suppressed.is_some() {
+            self.count_suppressed(rule(pattern.clone()), 1);
+        }
+    }
+
+    fn count_suppressed(&mut self, rule: IgnoreRule, n: usize) {
+        let Some(suppressed) = &mut self.suppressed else {
+            return;
+        };
+        match suppressed.iter_mut().find(|(r, _)| *r == rule) {
+            Some((_, count)) => *count += n,
+            None => suppressed.push((rule, n)),
+        }
+    }
+
+    /// Record that the value at `key_path` is the same in both documents
+    fn same(&mut self, key_path: &[PathSegment<'a>], val: &'a V) {
+        if let Some(unchanged) = &mut self.unchanged {
+            unchanged.push((key_path.to_vec(), val));
+        }
+    }
+
+    fn push(&mut self, change: TomlChange<'a, V>) {
+        if let Some(i) = self
+            .ignored_changes
+            .iter()
+            .position(|rule| rule.ignores(&change))
+        {
+            if self.suppressed.is_some() {
+                let rule = IgnoreRule::IgnoreChange(self.ignored_changes[i].pattern.clone());
+                self.count_suppressed(rule, 1);
+            }
+            return;
+        }
+        if self.max.is_some_and(|max| self.changes.len() >= max) {
+            self.truncated += 1;
+            return;
+        }
+        let size = change_size(&change);
+        if self
+            .budget
+            .is_some_and(|budget| self.used.saturating_add(size) > budget)
+        {
+            // Use up the budget, so that no smaller change after this one is kept either
+            self.used = usize::MAX;
+            let key_path = match change.key_path() {
+                [first, _, ..] => vec![*first],
+                _ => vec![],
+            };
+            self.summarize(key_path, 1);
+        } else {
+            self.used += size;
+            self.changes.push(change);
+        }
+    }
+
+    /// Count `n` changes under `key_path` as left out for the memory budget
+    fn summarize(&mut self, key_path: Vec<PathSegment<'a>>, n: usize) {
+        self.truncated += n;
+        match self
+            .summaries
+            .iter_mut()
+            .find(|(path, _)| *path == key_path)
+        {
+            Some((_, count)) => *count += n,
+            None => self.summaries.push((key_path, n)),
+        }
+    }
+}
+
+impl<'a, V> Extend<TomlChange<'a, V>> for ChangeList<'a, V> {
+    fn extend<I: IntoIterator<Item = TomlChange<'a, V>>>(&mut self, iter: I) {
+        for change in iter {
+            self.push(change);
+        }
+    }
+}
+
+/// Roughly how many bytes of memory `change` takes up
+fn change_size<V>(change: &TomlChange<V>) -> usize {
+    let key_path = std::mem::size_of_val(change.key_path());
+    let extra = match change {
+        TomlChange::Reordered(_, permutation) => std::mem::size_of_val(&permutation[..]),
+        TomlChange::Moved(_, old_path) => std::mem::size_of_val(&old_path[..]),
+        _ => 0,
+    };
+    std::mem::size_of::<TomlChange<V>>() + key_path + extra
+}
+
+/// Returns `true` for empty arrays, and for tables containing nothing but empty values.
+fn is_empty<V: DiffValue>(val: &V) -> bool {
+    match val.kind() {
+        ValueKind::Array => val.elements().next().is_none(),
+        ValueKind::Table => val.entries().all(|(_, val)| is_empty(val)),
+        ValueKind::Scalar => false,
     }
 }
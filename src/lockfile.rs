@@ -0,0 +1,222 @@
+//! Diffs of `Cargo.lock` files by package. A [`LockfileDiff`] lists each package that was
+//! added, removed, upgraded or downgraded, one per line, and how many of them the workspace
+//! depends on directly:
+//!
+//! ```text
+//! + itoa 1.0.9
+//! ↓ rand 0.8.5 → 0.7.3
+//! ↑ serde 1.0.190 → 1.0.203
+//! - winapi 0.3.9
+//! 4 packages changed (1 direct, 3 transitive): 1 upgraded, 1 downgraded, 1 added, 1 removed
+//! ```
+//!
+//! The generic diff of two lockfiles is best taken with [`Profile::CargoLock`](crate::Profile),
+//! which matches up `[[package]]` entries the same way.
+
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+
+use toml::Value as TomlValue;
+
+/// The changes to the packages of a lockfile, from [`diff`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct LockfileDiff<'a> {
+    /// The packages whose version changed, ordered by name and then by source
+    pub packages: Vec<PackageChange<'a>>,
+}
+
+/// A package of a [`LockfileDiff`], at one version in the old lockfile or the new one, or both.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PackageChange<'a> {
+    pub name: &'a str,
+    /// Where the package comes from, like `registry+https://github.com/rust-lang/crates.io-index`,
+    /// or `None` for the packages of the workspace itself
+    pub source: Option<&'a str>,
+    /// The version in the old lockfile, or `None` if the package was added
+    pub old_version: Option<&'a str>,
+    /// The version in the new lockfile, or `None` if the package was removed
+    pub new_version: Option<&'a str>,
+    /// Whether the package is one of the workspace's own or a dependency of one, rather than
+    /// only a dependency of other dependencies
+    pub direct: bool,
+}
+
+/// What happened to a [`PackageChange`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PackageChangeKind {
+    Upgraded,
+    Downgraded,
+    Added,
+    Removed,
+}
+
+impl PackageChange<'_> {
+    pub fn kind(&self) -> PackageChangeKind {
+        match (self.old_version, self.new_version) {
+            (None, _) => PackageChangeKind::Added,
+            (_, None) => PackageChangeKind::Removed,
+            (Some(old), Some(new)) if version_key(new) < version_key(old) => {
+                PackageChangeKind::Downgraded
+            }
+            _ => PackageChangeKind::Upgraded,
+        }
+    }
+}
+
+/// Diff the packages of two `Cargo.lock` files. As with [`TomlDiff::diff`](crate::TomlDiff::diff),
+/// `a` is the new lockfile and `b` the old one.
+///
+/// Packages are matched up by their name and source, so a package at a git source that moved
+/// to another commit shows as removed and added again. When a lockfile holds several versions
+/// of a package, the versions only in the old lockfile are paired with those only in the new
+/// one, lowest first, and any left over are removed or added. Packages whose version stayed
+/// the same aren't listed, whatever else about them changed.
+pub fn diff<'a>(a: &'a TomlValue, b: &'a TomlValue) -> LockfileDiff<'a> {
+    // The old, then the new versions of each package
+    let mut versions: BTreeMap<_, (Vec<&str>, Vec<&str>)> = BTreeMap::new();
+    for (name, source, version) in packages(b) {
+        versions.entry((name, source)).or_default().0.push(version);
+    }
+    for (name, source, version) in packages(a) {
+        versions.entry((name, source)).or_default().1.push(version);
+    }
+    let direct: HashSet<&str> = direct_dependencies(a)
+        .chain(direct_dependencies(b))
+        .collect();
+
+    let mut changes = vec![];
+    for ((name, source), (old, new)) in versions {
+        let only = |xs: &[&'a str], ys: &[&str]| {
+            let mut only: Vec<&'a str> = xs.iter().copied().filter(|x| !ys.contains(x)).collect();
+            only.sort_by(|x, y| version_key(x).cmp(&version_key(y)));
+            only
+        };
+        let (old, new) = (only(&old, &new), only(&new, &old));
+        for i in 0..old.len().max(new.len()) {
+            changes.push(PackageChange {
+                name,
+                source,
+                old_version: old.get(i).copied(),
+                new_version: new.get(i).copied(),
+                direct: direct.contains(name),
+            });
+        }
+    }
+    LockfileDiff { packages: changes }
+}
+
+/// The name, source and version of each `[[package]]` entry in `lockfile`
+fn packages(lockfile: &TomlValue) -> impl Iterator<Item = (&str, Option<&str>, &str)> {
+    let packages = lockfile.get("package").and_then(TomlValue::as_array);
+    packages.into_iter().flatten().filter_map(|package| {
+        let name = package.get("name")?.as_str()?;
+        let version = package.get("version")?.as_str()?;
+        let source = package.get("source").and_then(TomlValue::as_str);
+        Some((name, source, version))
+    })
+}
+
+/// The names of the workspace's own packages, which have no source, and of the packages they
+/// depend on
+fn direct_dependencies(lockfile: &TomlValue) -> impl Iterator<Item = &str> {
+    let packages = lockfile.get("package").and_then(TomlValue::as_array);
+    let members = packages
+        .into_iter()
+        .flatten()
+        .filter(|package| package.get("source").is_none());
+    members.flat_map(|member| {
+        let name = member.get("name").and_then(TomlValue::as_str);
+        let dependencies = member.get("dependencies").and_then(TomlValue::as_array);
+        // A dependency is written as its name, followed by its version and source if the
+        // lockfile has several of the package
+        let dependencies = dependencies
+            .into_iter()
+            .flatten()
+            .filter_map(|dependency| dependency.as_str()?.split(' ').next());
+        name.into_iter().chain(dependencies)
+    })
+}
+
+/// `version`'s numbers, then whether it isn't a pre-release, then its pre-release, which
+/// orders versions as semver does for all but unusual pre-releases. Build metadata is left out.
+fn version_key(version: &str) -> (Vec<u64>, bool, &str) {
+    let version = version.split('+').next().unwrap_or_default();
+    let (release, pre) = match version.split_once('-') {
+        Some((release, pre)) => (release, Some(pre)),
+        None => (version, None),
+    };
+    let numbers = release.split('.').map(|n| n.parse().unwrap_or(0)).collect();
+    (numbers, pre.is_none(), pre.unwrap_or_default())
+}
+
+impl LockfileDiff<'_> {
+    /// The number of packages of each kind of change, in the order of [`PackageChangeKind`]'s
+    /// variants
+    pub fn counts(&self) -> [(PackageChangeKind, usize); 4] {
+        [
+            PackageChangeKind::Upgraded,
+            PackageChangeKind::Downgraded,
+            PackageChangeKind::Added,
+            PackageChangeKind::Removed,
+        ]
+        .map(|kind| {
+            let count = self.packages.iter().filter(|p| p.kind() == kind).count();
+            (kind, count)
+        })
+    }
+}
+
+/// Renders a line for each package, marked with `+` if it was added, `-` if it was removed, `↑`
+/// if it was upgraded and `↓` if it was downgraded, then a line summarizing them. The source of
+/// a package is shown after it unless it's a registry. Nothing is rendered if no package changed.
+impl fmt::Display for LockfileDiff<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.packages.is_empty() {
+            return Ok(());
+        }
+        for package in &self.packages {
+            let name = package.name;
+            match (package.kind(), package.old_version, package.new_version) {
+                (PackageChangeKind::Added, _, Some(new)) => write!(f, "+ {name} {new}")?,
+                (PackageChangeKind::Removed, Some(old), _) => write!(f, "- {name} {old}")?,
+                (kind, Some(old), Some(new)) => {
+                    let marker = if kind == PackageChangeKind::Upgraded {
+                        '↑'
+                    } else {
+                        '↓'
+                    };
+                    write!(f, "{marker} {name} {old} → {new}")?
+                }
+                _ => unreachable!(),
+            }
+            match package.source {
+                Some(source) if !source.starts_with("registry+") => writeln!(f, " ({source})")?,
+                _ => writeln!(f)?,
+            }
+        }
+
+        let total = self.packages.len();
+        let direct = self.packages.iter().filter(|p| p.direct).count();
+        let plural = if total == 1 { "package" } else { "packages" };
+        write!(
+            f,
+            "{total} {plural} changed ({direct} direct, {} transitive):",
+            total - direct
+        )?;
+        let counts: Vec<_> = self
+            .counts()
+            .iter()
+            .filter(|(_, count)| *count > 0)
+            .map(|(kind, count)| {
+                let kind = match kind {
+                    PackageChangeKind::Added => "added",
+                    PackageChangeKind::Removed => "removed",
+                    PackageChangeKind::Upgraded => "upgraded",
+                    PackageChangeKind::Downgraded => "downgraded",
+                };
+                format!("{count} {kind}")
+            })
+            .collect();
+        writeln!(f, " {}", counts.join(", "))
+    }
+}
@@ -0,0 +1,1236 @@
+use std::env;
+use std::fmt::Write as _;
+use std::fs;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitCode, Stdio};
+
+use toml::Value as TomlValue;
+use toml_diff::ignore::IGNORE_FILE_NAME;
+use toml_diff::{
+    char_width, content_hash, find_duplicates, interpolate, key_matrix, lockfile, merge::merge,
+    parse_json, workspace, ChangeOrder, DiffOptions, PathPattern, Profile, Span, TomlChange,
+    TomlDiff, ValueClass, ValueType,
+};
+
+const USAGE: &str = "\
+Usage: toml-diff [--output <format>] [--write <file>] [--group] [--deltas] [--flags]
+                 [--inline-edits] [--collapse-arrays] [--env] [--suppress <file>]
+                 [--path <selector>]... [--only-type <type>]...
+                 [--fail-on <kind>]... [--ignore-file <file>] [--audit-ignores]
+                 [--profile <name>] [--progress] [--sort <order>] [--natural-order]
+                 [--classify] [--no-pager]
+                 [--ignore-whitespace-and-comments] <old.toml> <new.toml>
+       toml-diff --interactive <old.toml> <new.toml>
+       toml-diff --serve
+       toml-diff merge <base.toml> <ours.toml> <theirs.toml>
+       toml-diff analyze <file.toml>
+       toml-diff hash <file.toml>...
+       toml-diff matrix [--csv] <file.toml>...
+       toml-diff batch [--whole-files] <old-dir> <new-dir>
+       toml-diff workspace <old-root> <new-root>
+       toml-diff completions <shell>
+       toml-diff man
+
+--output picks how the diff is rendered, as one of:
+
+    color        +/- lines colored for a terminal (the default when printing to one)
+    text         +/- lines without colors (the default with --write, or through a pipe)
+    unified      like text, under ---/+++ headers naming the two files
+    markdown     like text, in a fenced diff block
+    html         like text, in a <pre> block with a class on each line
+    side-by-side old and new values in two columns
+    json         one JSON object per change
+    json-patch   a JSON Patch (RFC 6902) from the old document to the new one
+    toml-patch   a patch in TOML, see the toml_diff::patch docs
+    suppressions a suppression file listing every change, for --suppress
+    annotated    both documents as one TOML document, with a comment on each change
+    canonical    one line per change in a stable, versioned format, for snapshot tests
+    lockfile     one line per package whose version changed, for Cargo.lock files (the
+                 default with --profile cargo-lock)
+
+--write writes the rendered diff to <file> instead of printing it. --group puts the +/- lines of
+the color, text, unified, markdown and html formats under a header for each top-level table.
+--deltas shows how much each changed number went up or down by in those formats. --flags shows
+each flipped boolean on one line, in bold, like `~ feature.enabled: false -> true`, in those
+formats.
+--inline-edits shows a changed string that's mostly the same as before as an edit of it, like
+`~ host: \"db[-1-]{+2+}.internal\"`, in those formats. --collapse-arrays shows an array that
+changed as a whole by the elements that changed, like `~ ports: [ ..., ~ [4]: 7 -> 9, ... ]`,
+in those formats. --env expands ${VAR} and {{ VAR }} in the strings of both documents from
+the environment before diffing.
+--classify marks each change to a string that looks like a secret, a URL or an email address
+with a `kind: secret-like`, `kind: url` or `kind: email` comment, and shows the secrets as
+`***`.
+--suppress leaves out the changes listed in <file>, which is in the format of the suppressions
+output, with one change ID at the start of each line and `#` comments.
+--path keeps only the changes touching keys matched by <selector>, like `servers[*].port`, made
+of dotted keys, `*` for any key, `**` for any number of keys and `[i]` or `[*]` for array
+elements. Changes to keys inside a matched table are kept too, so `--path database` keeps a
+change to `database.port`. Changes matching any of several --path flags are kept.
+--only-type keeps only the changes to values of <type>, which is one of string, integer, float,
+boolean, datetime, array or table, so `--only-type string` leaves out numbers an auto-tuner
+rewrote. A changed value is kept if its old or new value is of <type>, and renamed and moved
+keys are left out. Changes to values of any of several --only-type flags are kept.
+--fail-on exits with 1 if the diff has a change of <kind>, which is one of added, deleted,
+changed, type-changed (a changed value that changed type too), key-style-changed, moved,
+reordered or any. Several --fail-on flags fail on any of their kinds.
+--ignore-file reads the keys to leave out and how to compare others from <file>, see the
+toml_diff::ignore docs. Without it, .tomldiffignore in the current directory is read if there is
+one, by the batch subcommand too.
+--audit-ignores counts the changes that the ignore file, --profile and the like left out, and
+says how many there were and how many rules left them out at the end of the diff, like
+`... 17 changes suppressed by 3 ignore rules`, in the color, text, unified, markdown, html and
+side-by-side formats.
+--profile compares the documents the way suits a kind of file, as one of:
+
+    strict       every difference counts, down to the spelling of keys and the order of arrays
+    lenient      keys match regardless of case and - or _, and arrays are compared as sets
+    cargo        for Cargo.toml, where `serde = \"1\"` is the same as `serde = { version = \"1\" }`
+    pyproject    for pyproject.toml, the same as cargo for Poetry's dependencies
+    cargo-lock   for Cargo.lock, where [[package]] entries match up by name and source
+
+--progress shows how far along the diff is on stderr, for huge documents.
+--ignore-whitespace-and-comments first says whether the files differ as text at all, then
+whether they differ as documents, before the diff. A file whose bytes changed but whose
+document didn't, such as one only reformatted or with only its comments edited, is reported
+as a formatting-only change.
+--sort lists the changes by <order> instead of in document order: kind puts deletions first,
+then additions, changed values, renamed keys, moved keys and reordered arrays, path sorts them
+by key path, and impact puts the changes touching the most values first.
+--natural-order orders keys with numbers in them by those numbers, so the changes to keys \"1\",
+\"2\" and \"10\" are listed in that order rather than as \"1\", \"10\", \"2\", in the diff, its
+--group headers and --sort path alike.
+When printing to a terminal, the diff is shown through $PAGER, or less if it isn't set, and the
+lines of the color, text and unified formats are wrapped to the width in $COLUMNS, or 80
+columns, with a \\ ending each line that goes on below. The side-by-side format fills the same
+width. --no-pager prints the diff directly, still wrapped.
+
+With --interactive, each change is shown in turn with a prompt: `y` applies it to <old.toml>,
+`n` skips it, and `q` skips it and every change after it. The changes picked are written to
+<old.toml> once all changes have been seen, which reformats it and drops its comments. If no
+change is picked, <old.toml> is left as it was.
+
+With --serve, requests are read from stdin and responses written to stdout until stdin is
+closed, so that editors can keep one process running. Each message is a JSON object after a
+`Content-Length: <bytes>` header and a blank line, as in the Language Server Protocol, and
+requests of over 256 MiB are refused. A request looks like
+`{\"a\": \"<new TOML>\", \"b\": \"<old TOML>\", \"options\": {\"values_only\": true}}`,
+where `a` and `b` can also be named `new` and `old`, the options are named after the
+DiffOptions methods and `options` can be left out. The response is
+`{\"changes\": [...], \"truncated\": <count>}`, with each change as in the json format plus
+its `old_span` and `new_span` in the two documents, or `{\"error\": \"<message>\"}`.
+
+The analyze subcommand lists the tables and arrays that appear identically under several keys,
+one line of key paths per duplicate, and exits with 1 if it found any.
+
+The hash subcommand prints a hash of each file's content followed by its path, one per line.
+Files hash the same if they hold the same document, however its keys are ordered and formatted,
+so comparing hashes finds the copies of a file that drifted without diffing every one.
+
+The matrix subcommand lists every key path any of the files holds, with a column for each file,
+numbered in the order given. A column holds a letter for the file's value there, the same letter
+for files that agree, or - if the file lacks the key path. --csv prints the values themselves
+instead, as CSV with a column named after each file.
+
+The batch subcommand diffs every .toml file under <old-dir> against the file at the same path
+under <new-dir>, printing a line summarizing the changes to each one and a line totalling them.
+A file missing from one of the directories is diffed as if it were empty. --whole-files follows
+the summary with the content of each file in only one of the directories, under a +++ header
+with every line marked + for a new file, or a --- header with every line marked - for a file
+that's gone.
+
+The workspace subcommand diffs the Cargo.toml of every member of the Cargo workspace at
+<old-root> against the same member's under <new-root>, as listed by `workspace.members` and
+`workspace.exclude` in each root's Cargo.toml, and the root's own. It prints each dependency
+whose declaration changed, with a change made the same way in several members listed once,
+followed by the members it was made in.
+
+The completions subcommand prints a completion script for <shell>, one of bash, zsh or fish,
+and the man subcommand prints a man page, for installing alongside the binary.
+
+The merge subcommand implements git's merge driver protocol: it writes the merged document to
+<ours.toml> and exits with 1 if there were conflicts. Each conflict is marked where it is in the
+file, with our side kept and the base and their side in comments, as in git's diff3 style.
+To use it, add this to your git config:
+
+    [merge \"toml\"]
+        name = TOML-aware merge
+        driver = toml-diff merge %O %A %B
+
+and `*.toml merge=toml` to .gitattributes.";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let result = match args[..] {
+        ["merge", base, ours, theirs] => run_merge(base, ours, theirs),
+        ["analyze", file] => run_analyze(file),
+        ["hash", ref files @ ..] if !files.is_empty() => run_hash(files),
+        ["matrix", "--csv", ref files @ ..] if !files.is_empty() => run_matrix(files, true),
+        ["matrix", ref files @ ..] if !files.is_empty() => run_matrix(files, false),
+        ["batch", old, new] => run_batch(old, new, false),
+        ["batch", "--whole-files", old, new] => run_batch(old, new, true),
+        ["workspace", old, new] => run_workspace(old, new),
+        ["completions", shell] => run_completions(shell),
+        ["man"] => run_man(),
+        ["-i" | "--interactive", old, new] => run_interactive(old, new),
+        ["--serve"] => run_serve(),
+        ["-h" | "--help"] => {
+            println!("{USAGE}");
+            Ok(ExitCode::SUCCESS)
+        }
+        _ => parse_diff_args(&args).and_then(run_diff),
+    };
+    result.unwrap_or_else(|err| {
+        eprintln!("{err}");
+        ExitCode::from(2)
+    })
+}
+
+struct DiffArgs<'s> {
+    old: &'s str,
+    new: &'s str,
+    output: Option<&'s str>,
+    write: Option<&'s str>,
+    group: bool,
+    deltas: bool,
+    flags: bool,
+    inline_edits: bool,
+    collapse_arrays: bool,
+    env: bool,
+    classify: bool,
+    suppress: Option<&'s str>,
+    paths: Vec<&'s str>,
+    only_types: Vec<ValueType>,
+    fail_on: Vec<&'s str>,
+    ignore_file: Option<&'s str>,
+    audit_ignores: bool,
+    profile: Option<Profile>,
+    progress: bool,
+    sort: Option<ChangeOrder>,
+    natural_order: bool,
+    baseline: bool,
+    pager: bool,
+}
+
+/// The flags of a diff, for completion scripts and the man page: the long form, any short
+/// form, the name of any value it takes, and a summary
+const FLAGS: [(&str, Option<char>, Option<&str>, &str); 24] = [
+    (
+        "output",
+        Some('o'),
+        Some("format"),
+        "How the diff is rendered",
+    ),
+    (
+        "write",
+        Some('w'),
+        Some("file"),
+        "Write the rendered diff to a file",
+    ),
+    ("group", None, None, "Group the changes by top-level table"),
+    (
+        "deltas",
+        None,
+        None,
+        "Show how much changed numbers went up or down by",
+    ),
+    ("flags", None, None, "Highlight flipped booleans"),
+    (
+        "inline-edits",
+        None,
+        None,
+        "Show changes to similar strings as edits",
+    ),
+    (
+        "collapse-arrays",
+        None,
+        None,
+        "Show arrays by the elements that changed",
+    ),
+    ("env", None, None, "Expand environment variables in strings"),
+    (
+        "classify",
+        None,
+        None,
+        "Mark secrets, URLs and emails, hiding secrets",
+    ),
+    (
+        "suppress",
+        None,
+        Some("file"),
+        "Leave out the changes listed in a file",
+    ),
+    (
+        "path",
+        None,
+        Some("selector"),
+        "Keep only the changes touching matching keys",
+    ),
+    (
+        "only-type",
+        None,
+        Some("type"),
+        "Keep only the changes to values of a type",
+    ),
+    (
+        "fail-on",
+        None,
+        Some("kind"),
+        "Exit with 1 if the diff has a change of a kind",
+    ),
+    (
+        "ignore-file",
+        None,
+        Some("file"),
+        "Read keys to leave out from a file",
+    ),
+    (
+        "audit-ignores",
+        None,
+        None,
+        "Count the changes ignore rules left out",
+    ),
+    (
+        "profile",
+        None,
+        Some("name"),
+        "Compare the way that suits a kind of file",
+    ),
+    ("progress", None, None, "Show how far along the diff is"),
+    (
+        "sort",
+        None,
+        Some("order"),
+        "List the changes in another order",
+    ),
+    (
+        "natural-order",
+        None,
+        None,
+        "Order keys with numbers in them by those numbers",
+    ),
+    (
+        "ignore-whitespace-and-comments",
+        None,
+        None,
+        "Say first whether the files differ as text and as documents",
+    ),
+    ("no-pager", None, None, "Print the diff without a pager"),
+    (
+        "interactive",
+        Some('i'),
+        None,
+        "Pick the changes to apply one by one",
+    ),
+    ("serve", None, None, "Serve diffs over stdin and stdout"),
+    ("help", Some('h'), None, "Print help"),
+];
+
+/// The formats --output accepts
+const OUTPUTS: [&str; 13] = [
+    "color",
+    "text",
+    "unified",
+    "markdown",
+    "html",
+    "side-by-side",
+    "json",
+    "json-patch",
+    "toml-patch",
+    "suppressions",
+    "annotated",
+    "canonical",
+    "lockfile",
+];
+
+/// The subcommands, which come before any flags
+const SUBCOMMANDS: [&str; 8] = [
+    "merge",
+    "analyze",
+    "hash",
+    "matrix",
+    "batch",
+    "workspace",
+    "completions",
+    "man",
+];
+
+/// How alike two strings must be for --inline-edits to show a change between them as an edit
+const INLINE_EDIT_THRESHOLD: f64 = 0.5;
+
+/// The width lines are wrapped to on a terminal when $COLUMNS isn't set
+const DEFAULT_WIDTH: usize = 80;
+
+/// The longest `--serve` request read, so that a bad `Content-Length` can't exhaust memory
+const MAX_MESSAGE_LEN: usize = 256 << 20;
+
+/// The kinds of change --fail-on accepts
+const FAIL_ON: [&str; 8] = [
+    "added",
+    "deleted",
+    "changed",
+    "type-changed",
+    "key-style-changed",
+    "moved",
+    "reordered",
+    "any",
+];
+
+fn parse_diff_args<'s>(args: &[&'s str]) -> Result<DiffArgs<'s>, String> {
+    let mut output = None;
+    let mut write = None;
+    let mut group = false;
+    let mut deltas = false;
+    let mut flags = false;
+    let mut inline_edits = false;
+    let mut collapse_arrays = false;
+    let mut env = false;
+    let mut classify = false;
+    let mut suppress = None;
+    let mut paths = vec![];
+    let mut only_types = vec![];
+    let mut fail_on = vec![];
+    let mut ignore_file = None;
+    let mut audit_ignores = false;
+    let mut profile = None;
+    let mut progress = false;
+    let mut sort = None;
+    let mut natural_order = false;
+    let mut baseline = false;
+    let mut pager = true;
+    let mut files = vec![];
+    let mut args = args.iter();
+    while let Some(&arg) = args.next() {
+        match arg {
+            "-o" | "--output" => output = Some(*args.next().ok_or(USAGE)?),
+            "-w" | "--write" => write = Some(*args.next().ok_or(USAGE)?),
+            "--group" => group = true,
+            "--deltas" => deltas = true,
+            "--flags" => flags = true,
+            "--inline-edits" => inline_edits = true,
+            "--collapse-arrays" => collapse_arrays = true,
+            "--env" => env = true,
+            "--classify" => classify = true,
+            "--suppress" => suppress = Some(*args.next().ok_or(USAGE)?),
+            "--path" => paths.push(*args.next().ok_or(USAGE)?),
+            "--only-type" => {
+                let name = *args.next().ok_or(USAGE)?;
+                let unknown = || format!("Unknown type `{name}`\n\n{USAGE}");
+                only_types.push(ValueType::from_name(name).ok_or_else(unknown)?);
+            }
+            "--ignore-file" => ignore_file = Some(*args.next().ok_or(USAGE)?),
+            "--audit-ignores" => audit_ignores = true,
+            "--fail-on" => match *args.next().ok_or(USAGE)? {
+                kind if FAIL_ON.contains(&kind) => fail_on.push(kind),
+                kind => return Err(format!("Unknown kind of change `{kind}`\n\n{USAGE}")),
+            },
+            "--profile" => {
+                let name = *args.next().ok_or(USAGE)?;
+                let unknown = || format!("Unknown profile `{name}`\n\n{USAGE}");
+                profile = Some(Profile::from_name(name).ok_or_else(unknown)?);
+            }
+            "--progress" => progress = true,
+            "--sort" => {
+                let name = *args.next().ok_or(USAGE)?;
+                let unknown = || format!("Unknown order `{name}`\n\n{USAGE}");
+                sort = Some(ChangeOrder::from_name(name).ok_or_else(unknown)?);
+            }
+            "--natural-order" => natural_order = true,
+            "--ignore-whitespace-and-comments" => baseline = true,
+            "--no-pager" => pager = false,
+            _ if arg.starts_with('-') => return Err(USAGE.to_owned()),
+            _ => files.push(arg),
+        }
+    }
+    let [old, new] = files[..] else {
+        return Err(USAGE.to_owned());
+    };
+    Ok(DiffArgs {
+        old,
+        new,
+        output,
+        write,
+        group,
+        deltas,
+        flags,
+        inline_edits,
+        collapse_arrays,
+        env,
+        classify,
+        suppress,
+        paths,
+        only_types,
+        fail_on,
+        ignore_file,
+        audit_ignores,
+        profile,
+        progress,
+        sort,
+        natural_order,
+        baseline,
+        pager,
+    })
+}
+
+fn run_diff(args: DiffArgs) -> Result<ExitCode, String> {
+    let mut old = read_toml(args.old)?;
+    let mut new = read_toml(args.new)?;
+    if args.env {
+        interpolate(&mut old, |name| env::var(name).ok());
+        interpolate(&mut new, |name| env::var(name).ok());
+    }
+    let mut options = load_options(args.ignore_file)?;
+    if let Some(profile) = args.profile {
+        options = options.profile(profile);
+    }
+    if args.natural_order {
+        options = options.natural_key_order(true);
+    }
+    if args.audit_ignores {
+        options = options.record_suppressed(true);
+    }
+    if args.progress {
+        options = options.on_progress(|visited, total| {
+            let percent = visited * 100 / total.max(1);
+            eprint!("\rDiffing... {percent}%");
+            if visited == total {
+                eprintln!();
+            }
+        });
+    }
+    let mut diff = TomlDiff::diff_with_options(&new, &old, &options)
+        .numeric_deltas(args.deltas)
+        .highlight_flags(args.flags)
+        .collapse_arrays(args.collapse_arrays);
+    if args.inline_edits {
+        diff = diff.inline_edits(INLINE_EDIT_THRESHOLD);
+    }
+    // Whether the files differ as text and as documents, for --ignore-whitespace-and-comments,
+    // said before anything is left out of the diff
+    let mut baseline = String::new();
+    if args.baseline {
+        let read = |path: &str| fs::read(path).map_err(|err| format!("{path}: {err}"));
+        let changes = diff.changes.len() + diff.truncated;
+        baseline = if read(args.old)? == read(args.new)? {
+            "Textual diff: none, the files are identical\n".to_owned()
+        } else if changes == 0 {
+            "Textual diff: the files differ\nSemantic diff: none, formatting-only change\n"
+                .to_owned()
+        } else {
+            let plural = if changes == 1 { "change" } else { "changes" };
+            format!("Textual diff: the files differ\nSemantic diff: {changes} {plural}\n")
+        };
+    }
+    if let Some(path) = args.suppress {
+        let suppressions = fs::read_to_string(path).map_err(|err| format!("{path}: {err}"))?;
+        diff = diff.suppress(&suppressions);
+    }
+    if !args.paths.is_empty() {
+        let patterns: Vec<_> = args
+            .paths
+            .iter()
+            .map(|&path| PathPattern::new(path))
+            .collect();
+        diff = diff.select(|change| {
+            let key_path = change.key_path();
+            patterns.iter().any(|pattern| pattern.selects(key_path))
+        });
+    }
+    if !args.only_types.is_empty() {
+        diff = diff.retain_types(&args.only_types);
+    }
+    if args.classify {
+        let classes = [ValueClass::Secret, ValueClass::Url, ValueClass::Email];
+        diff = diff.classify_values(&classes).redact_secrets();
+    }
+    if let Some(order) = args.sort {
+        diff = diff.sort_by(order);
+    }
+    let terminal = args.write.is_none() && io::stdout().is_terminal();
+    let width = env::var("COLUMNS")
+        .ok()
+        .and_then(|columns| columns.parse().ok())
+        .unwrap_or(DEFAULT_WIDTH);
+    let default = if args.profile == Some(Profile::CargoLock) {
+        "lockfile"
+    } else if terminal {
+        "color"
+    } else {
+        "text"
+    };
+    let (color, text) = if args.group {
+        (diff.grouped().to_string(), format!("{:#}", diff.grouped()))
+    } else {
+        (diff.to_string(), format!("{diff:#}"))
+    };
+    let output = args.output.unwrap_or(default);
+    let rendered = match output {
+        "color" => color,
+        "text" => text,
+        "unified" => format!("--- {}\n+++ {}\n{text}", args.old, args.new),
+        "markdown" => format!("```diff\n{text}```\n"),
+        "html" => to_html(&text),
+        "side-by-side" if terminal => diff.to_side_by_side(width),
+        "side-by-side" => diff.to_side_by_side(DEFAULT_WIDTH),
+        "json" => diff.to_jsonl(),
+        "json-patch" => diff.to_json_patch(),
+        "toml-patch" => diff.to_patch(),
+        "suppressions" => diff.to_suppressions(),
+        "annotated" => diff.to_annotated_toml(&new),
+        "canonical" => diff.to_canonical_string(),
+        "lockfile" => lockfile::diff(&new, &old).to_string(),
+        output => return Err(format!("Unknown output format `{output}`\n\n{USAGE}")),
+    };
+    let rendered = match output {
+        "color" | "text" | "unified" if terminal => wrap(&rendered, width),
+        _ => rendered,
+    };
+    match args.write {
+        Some(path) => {
+            print!("{baseline}");
+            fs::write(path, rendered).map_err(|err| format!("{path}: {err}"))?
+        }
+        None if terminal && args.pager => page(&format!("{baseline}{rendered}"))?,
+        None => print!("{baseline}{rendered}"),
+    }
+    let failed = diff
+        .changes
+        .iter()
+        .filter(|change| args.fail_on.iter().any(|&kind| is_kind(change, kind)))
+        .count();
+    if failed > 0 {
+        eprintln!("{failed} changes matched --fail-on");
+        return Ok(ExitCode::FAILURE);
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// `rendered` with each line wider than `width` broken into lines that fit, all but the last
+/// ending with `\` and all but the first indented to line up with the value after a `+ `
+/// marker. Color escapes take up no width, so a colored line breaks where the plain one would,
+/// and wide characters take up two columns and combining marks none, as in a terminal.
+fn wrap(rendered: &str, width: usize) -> String {
+    const CONTINUED: &str = "\\";
+    const INDENT: &str = "  ";
+    // Room for at least one wide character besides the indent and the marker
+    let width = width.max(INDENT.len() + CONTINUED.len() + 2);
+    let mut s = String::new();
+    for line in rendered.lines() {
+        let mut column = 0;
+        let mut chars = line.chars().peekable();
+        while let Some(c) = chars.next() {
+            if c == '\x1b' {
+                // An escape like `\x1b[32m`, up to and including its final letter
+                s.push(c);
+                for c in chars.by_ref() {
+                    s.push(c);
+                    if c.is_ascii_alphabetic() {
+                        break;
+                    }
+                }
+                continue;
+            }
+            // The last character can take the place of the marker, and a combining mark stays on
+            // the line of the character it goes on
+            let c_width = char_width(c);
+            let fits = match chars.peek() {
+                Some(_) => column + c_width + CONTINUED.len() <= width,
+                None => column + c_width <= width,
+            };
+            if c_width > 0 && !fits {
+                writeln!(s, "{CONTINUED}").unwrap();
+                s.push_str(INDENT);
+                column = INDENT.len();
+            }
+            s.push(c);
+            column += c_width;
+        }
+        s.push('\n');
+    }
+    s
+}
+
+/// Print `rendered` through $PAGER, or less, falling back to printing it directly if the pager
+/// can't be started. Unless $LESS is set, less is told to quit at once if the output fits on
+/// the screen, so short diffs print as if there were no pager.
+fn page(rendered: &str) -> Result<(), String> {
+    let pager = env::var("PAGER").unwrap_or_else(|_| "less".to_owned());
+    let mut words = pager.split_whitespace();
+    let Some(program) = words.next() else {
+        print!("{rendered}");
+        return Ok(());
+    };
+    let mut command = Command::new(program);
+    command.args(words).stdin(Stdio::piped());
+    if env::var_os("LESS").is_none() {
+        command.env("LESS", "FRX");
+    }
+    let Ok(mut child) = command.spawn() else {
+        print!("{rendered}");
+        return Ok(());
+    };
+    let written = child.stdin.take().unwrap().write_all(rendered.as_bytes());
+    // Quitting the pager before reading everything isn't an error
+    match written {
+        Err(err) if err.kind() != io::ErrorKind::BrokenPipe => {
+            return Err(format!("{program}: {err}"));
+        }
+        _ => {}
+    }
+    child.wait().map_err(|err| format!("{program}: {err}"))?;
+    Ok(())
+}
+
+/// Options from the ignore file at `path`, or from the one in the current directory if there is
+/// one and `path` isn't given
+fn load_options(path: Option<&str>) -> Result<DiffOptions, String> {
+    let path = match path {
+        Some(path) => path,
+        None if Path::new(IGNORE_FILE_NAME).exists() => IGNORE_FILE_NAME,
+        None => return Ok(DiffOptions::new()),
+    };
+    DiffOptions::new()
+        .load_ignore_file(path)
+        .map_err(|err| format!("{path}: {err}"))
+}
+
+/// Whether `change` is of `kind`, as given to --fail-on
+fn is_kind(change: &TomlChange, kind: &str) -> bool {
+    match kind {
+        "any" => true,
+        "type-changed" => change.changes_type(),
+        kind => change.kind().replace('_', "-") == kind,
+    }
+}
+
+/// Wrap a diff rendered without colors in a `<pre>` block, classing each line by its kind
+fn to_html(diff: &str) -> String {
+    let mut html = String::from("<pre class=\"toml-diff\">\n");
+    for line in diff.lines() {
+        let class = match line.chars().next() {
+            Some('+') => "added",
+            Some('-') => "deleted",
+            Some('~') => "moved",
+            _ => "note",
+        };
+        let line = line
+            .replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;");
+        html.push_str(&format!("<span class=\"{class}\">{line}</span>\n"));
+    }
+    html.push_str("</pre>\n");
+    html
+}
+
+fn run_interactive(old_path: &str, new: &str) -> Result<ExitCode, String> {
+    let old = read_toml(old_path)?;
+    let new = read_toml(new)?;
+    let mut quit = false;
+    let diff = TomlDiff::diff(&new, &old).select(|change| {
+        if quit {
+            return false;
+        }
+        print!("{change}");
+        loop {
+            print!("Apply this change [y,n,q]? ");
+            io::stdout().flush().ok();
+            let mut answer = String::new();
+            // Treat the end of input like `q`
+            if io::stdin().lock().read_line(&mut answer).unwrap_or(0) == 0 {
+                quit = true;
+                return false;
+            }
+            match answer.trim() {
+                "y" => return true,
+                "n" => return false,
+                "q" => {
+                    quit = true;
+                    return false;
+                }
+                _ => println!("y - apply this change\nn - skip this change\nq - quit, skipping this change and all later ones"),
+            }
+        }
+    });
+    // Writing the file would reformat it, so it's only written if something changes
+    if diff.changes.is_empty() {
+        return Ok(ExitCode::SUCCESS);
+    }
+    let mut patched = old.clone();
+    diff.apply(&mut patched);
+    let text = toml::to_string(&patched).map_err(|err| format!("{old_path}: {err}"))?;
+    fs::write(old_path, text).map_err(|err| format!("{old_path}: {err}"))?;
+    Ok(ExitCode::SUCCESS)
+}
+
+fn run_serve() -> Result<ExitCode, String> {
+    let mut stdin = io::stdin().lock();
+    let mut stdout = io::stdout().lock();
+    while let Some(request) = read_message(&mut stdin)? {
+        let response = serve(&request).unwrap_or_else(|err| {
+            let mut response = String::from("{\"error\":");
+            push_json_str(&mut response, &err);
+            response.push('}');
+            response
+        });
+        write!(
+            stdout,
+            "Content-Length: {}\r\n\r\n{response}",
+            response.len()
+        )
+        .and_then(|()| stdout.flush())
+        .map_err(|err| format!("stdout: {err}"))?;
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Read one `Content-Length` framed message, or `None` at the end of input
+fn read_message(input: &mut impl BufRead) -> Result<Option<String>, String> {
+    let mut len = None;
+    loop {
+        let mut line = String::new();
+        let read = input
+            .read_line(&mut line)
+            .map_err(|err| format!("stdin: {err}"))?;
+        if read == 0 {
+            return match len {
+                None => Ok(None),
+                Some(_) => Err("stdin: Unexpected end of input".to_owned()),
+            };
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, val)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                len = val.trim().parse::<usize>().ok();
+            }
+        }
+    }
+    let len = len.ok_or("stdin: Missing `Content-Length` header")?;
+    if len > MAX_MESSAGE_LEN {
+        return Err(format!(
+            "stdin: A `Content-Length` of {len} is over the limit of {MAX_MESSAGE_LEN} bytes"
+        ));
+    }
+    let mut body = vec![0; len];
+    input
+        .read_exact(&mut body)
+        .map_err(|err| format!("stdin: {err}"))?;
+    String::from_utf8(body)
+        .map(Some)
+        .map_err(|err| format!("stdin: {err}"))
+}
+
+/// Answer one `--serve` request
+fn serve(request: &str) -> Result<String, String> {
+    let request = parse_json(request).map_err(|err| format!("Invalid request: {err}"))?;
+    // As elsewhere in the crate, `a` is the new document and `b` the old one, which can also be
+    // named `new` and `old`
+    let source = |key, alias| {
+        request
+            .get(key)
+            .or_else(|| request.get(alias))
+            .and_then(TomlValue::as_str)
+            .ok_or(format!("Invalid request: `{key}` is not a string"))
+    };
+    let (new_source, old_source) = (source("a", "new")?, source("b", "old")?);
+    let old: TomlValue = toml::from_str(old_source).map_err(|err| format!("old: {err}"))?;
+    let new: TomlValue = toml::from_str(new_source).map_err(|err| format!("new: {err}"))?;
+
+    let mut options = DiffOptions::new();
+    let empty = toml::map::Map::new();
+    let given = match request.get("options") {
+        Some(TomlValue::Table(given)) => given,
+        Some(_) => return Err("Invalid request: `options` is not an object".to_owned()),
+        None => &empty,
+    };
+    for (name, val) in given {
+        if name == "max_changes" {
+            let n = val.as_integer().and_then(|n| usize::try_from(n).ok());
+            options = options.max_changes(n.ok_or("Invalid option `max_changes`")?);
+            continue;
+        }
+        let yes = val.as_bool().ok_or(format!("Invalid option `{name}`"))?;
+        options = match name.as_str() {
+            "arrays_as_sets" => options.arrays_as_sets(yes),
+            "empty_equals_missing" => options.empty_equals_missing(yes),
+            "key_case_insensitive" => options.key_case_insensitive(yes),
+            "key_normalization" => options.key_normalization(yes),
+            "match_similar" => options.match_similar(yes),
+            "natural_key_order" => options.natural_key_order(yes),
+            "record_suppressed" => options.record_suppressed(yes),
+            "report_reorders" => options.report_reorders(yes),
+            "structure_only" => options.structure_only(yes),
+            "values_only" => options.values_only(yes),
+            _ => return Err(format!("Unknown option `{name}`")),
+        };
+    }
+
+    let diff = TomlDiff::diff_with_options(&new, &old, &options);
+    let spans = diff.spans(old_source, new_source);
+    let mut response = String::from("{\"changes\":[");
+    // The lines of the json format, minus the one counting truncated changes
+    for (i, (change, spans)) in diff.to_jsonl().lines().zip(spans).enumerate() {
+        if i > 0 {
+            response.push(',');
+        }
+        response.push_str(change.strip_suffix('}').unwrap());
+        response.push_str(",\"old_span\":");
+        push_json_span(&mut response, spans.old);
+        response.push_str(",\"new_span\":");
+        push_json_span(&mut response, spans.new);
+        response.push('}');
+    }
+    response.push_str(&format!("],\"truncated\":{}}}", diff.truncated));
+    Ok(response)
+}
+
+fn push_json_span(s: &mut String, span: Option<Span>) {
+    match span {
+        Some(Span { start, end }) => s.push_str(&format!(
+            "{{\"start\":{{\"line\":{},\"column\":{}}},\"end\":{{\"line\":{},\"column\":{}}}}}",
+            start.line, start.column, end.line, end.column
+        )),
+        None => s.push_str("null"),
+    }
+}
+
+fn push_json_str(s: &mut String, string: &str) {
+    s.push('"');
+    for c in string.chars() {
+        match c {
+            '"' => s.push_str("\\\""),
+            '\\' => s.push_str("\\\\"),
+            '\n' => s.push_str("\\n"),
+            c if c.is_control() => s.push_str(&format!("\\u{:04x}", c as u32)),
+            c => s.push(c),
+        }
+    }
+    s.push('"');
+}
+
+/// The values a flag's value can be completed from, by the name of the value, or `None` if it's
+/// a file
+fn flag_values(value: &str) -> Option<Vec<&'static str>> {
+    match value {
+        "format" => Some(OUTPUTS.to_vec()),
+        "kind" => Some(FAIL_ON.to_vec()),
+        "name" => Some(Profile::ALL.iter().map(|profile| profile.name()).collect()),
+        "order" => Some(ChangeOrder::ALL.iter().map(|order| order.name()).collect()),
+        "type" => Some(ValueType::ALL.iter().map(|ty| ty.name()).collect()),
+        "file" => None,
+        _ => Some(vec![]),
+    }
+}
+
+fn run_completions(shell: &str) -> Result<ExitCode, String> {
+    let mut s = String::new();
+    match shell {
+        "bash" => {
+            s.push_str("_toml_diff() {\n");
+            s.push_str("    local cur=\"${COMP_WORDS[COMP_CWORD]}\" prev=\"${COMP_WORDS[COMP_CWORD-1]}\"\n");
+            s.push_str("    case \"$prev\" in\n");
+            for (long, short, value, _) in FLAGS {
+                let Some(value) = value else { continue };
+                let short = short.map(|c| format!("|-{c}")).unwrap_or_default();
+                let reply = match flag_values(value) {
+                    Some(values) => format!("$(compgen -W \"{}\" -- \"$cur\")", values.join(" ")),
+                    None => "$(compgen -f -- \"$cur\")".to_owned(),
+                };
+                writeln!(s, "        --{long}{short}) COMPREPLY=({reply}); return ;;").unwrap();
+            }
+            s.push_str("    esac\n");
+            let flags: Vec<_> = FLAGS.iter().map(|(long, ..)| format!("--{long}")).collect();
+            writeln!(s, "    if [[ \"$cur\" == -* ]]; then").unwrap();
+            writeln!(
+                s,
+                "        COMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))",
+                flags.join(" ")
+            )
+            .unwrap();
+            s.push_str("    elif [[ $COMP_CWORD -eq 1 ]]; then\n");
+            writeln!(
+                s,
+                "        COMPREPLY=($(compgen -W \"{}\" -- \"$cur\") $(compgen -f -- \"$cur\"))",
+                SUBCOMMANDS.join(" ")
+            )
+            .unwrap();
+            s.push_str("    else\n");
+            s.push_str("        COMPREPLY=($(compgen -f -- \"$cur\"))\n");
+            s.push_str("    fi\n");
+            s.push_str("}\n");
+            s.push_str("complete -F _toml_diff toml-diff\n");
+        }
+        "zsh" => {
+            s.push_str("#compdef toml-diff\n\n_arguments \\\n");
+            for (long, short, value, help) in FLAGS {
+                let names = match short {
+                    Some(c) => format!("'(-{c} --{long})'{{-{c},--{long}}}'"),
+                    None => format!("'--{long}"),
+                };
+                let value = match value.map(|value| (value, flag_values(value))) {
+                    Some((value, Some(values))) => format!(":{value}:({})", values.join(" ")),
+                    Some((value, None)) => format!(":{value}:_files"),
+                    None => String::new(),
+                };
+                writeln!(s, "    {names}[{help}]{value}' \\").unwrap();
+            }
+            let subcommands = SUBCOMMANDS.join(" ");
+            let first =
+                format!("_alternative 'commands:command:({subcommands})' 'files:file:_files'");
+            writeln!(s, "    \"1:command or file:{first}\" \\").unwrap();
+            s.push_str("    '*:file:_files'\n");
+        }
+        "fish" => {
+            let subcommands = SUBCOMMANDS.join(" ");
+            writeln!(
+                s,
+                "complete -c toml-diff -n __fish_use_subcommand -a '{subcommands}'"
+            )
+            .unwrap();
+            for (long, short, value, help) in FLAGS {
+                write!(s, "complete -c toml-diff -l {long}").unwrap();
+                if let Some(c) = short {
+                    write!(s, " -s {c}").unwrap();
+                }
+                match value.map(flag_values) {
+                    Some(Some(values)) => write!(s, " -x -a '{}'", values.join(" ")).unwrap(),
+                    Some(None) => s.push_str(" -r -F"),
+                    None => {}
+                }
+                writeln!(s, " -d '{help}'").unwrap();
+            }
+        }
+        shell => return Err(format!("Unknown shell `{shell}`\n\n{USAGE}")),
+    }
+    print!("{s}");
+    Ok(ExitCode::SUCCESS)
+}
+
+fn run_man() -> Result<ExitCode, String> {
+    // Dashes are escaped so that they're rendered as the hyphens people type
+    let escape = |line: &str| {
+        let line = line.replace('\\', "\\e").replace('-', "\\-");
+        if line.starts_with(['.', '\'']) {
+            format!("\\&{line}")
+        } else {
+            line
+        }
+    };
+    let (synopsis, details) = USAGE.split_once("\n\n").unwrap();
+    let mut s = String::new();
+    s.push_str(".TH TOML\\-DIFF 1\n");
+    s.push_str(".SH NAME\ntoml\\-diff \\- diff TOML documents by their meaning\n");
+    s.push_str(".SH SYNOPSIS\n.nf\n");
+    for line in synopsis.lines() {
+        // Every line is indented past `Usage: `, which is left out of the man page
+        writeln!(s, "{}", escape(&line["Usage: ".len()..])).unwrap();
+    }
+    s.push_str(".fi\n.SH OPTIONS\n");
+    for (long, short, value, help) in FLAGS {
+        s.push_str(".TP\n");
+        if let Some(c) = short {
+            write!(s, "\\fB\\-{c}\\fR, ").unwrap();
+        }
+        write!(s, "\\fB\\-\\-{}\\fR", escape(long)).unwrap();
+        if let Some(value) = value {
+            write!(s, " \\fI<{value}>\\fR").unwrap();
+        }
+        writeln!(s, "\n{}", escape(help)).unwrap();
+    }
+    s.push_str(".SH DESCRIPTION\n.nf\n");
+    for line in details.lines() {
+        writeln!(s, "{}", escape(line)).unwrap();
+    }
+    s.push_str(".fi\n");
+    print!("{s}");
+    Ok(ExitCode::SUCCESS)
+}
+
+fn run_analyze(file: &str) -> Result<ExitCode, String> {
+    let val = read_toml(file)?;
+    let duplicates = find_duplicates(&val);
+    for duplicate in &duplicates {
+        println!("{duplicate}");
+    }
+    Ok(if duplicates.is_empty() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    })
+}
+
+fn run_hash(files: &[&str]) -> Result<ExitCode, String> {
+    for file in files {
+        let hash = content_hash(&read_toml(file)?, &DiffOptions::new());
+        println!("{hash}  {file}");
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn run_matrix(files: &[&str], csv: bool) -> Result<ExitCode, String> {
+    let docs = files
+        .iter()
+        .map(|file| read_toml(file))
+        .collect::<Result<Vec<_>, _>>()?;
+    let matrix = key_matrix(&docs);
+    if csv {
+        print!("{}", matrix.to_csv(files));
+    } else {
+        print!("{matrix}");
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn run_batch(old_dir: &str, new_dir: &str, whole_files: bool) -> Result<ExitCode, String> {
+    let mut names = vec![];
+    find_toml_files(Path::new(old_dir), Path::new(""), &mut names)?;
+    find_toml_files(Path::new(new_dir), Path::new(""), &mut names)?;
+    names.sort();
+    names.dedup();
+    let exists = |dir: &str, name: &str| Path::new(dir).join(name).exists();
+    let read = |dir: &str, name: &Path| {
+        let path = Path::new(dir).join(name);
+        if path.exists() {
+            read_toml(&path.to_string_lossy())
+        } else {
+            Ok(TomlValue::Table(Default::default()))
+        }
+    };
+    let mut docs = vec![];
+    for name in &names {
+        docs.push((
+            name.to_string_lossy(),
+            read(old_dir, name)?,
+            read(new_dir, name)?,
+        ));
+    }
+    let pairs: Vec<_> = docs
+        .iter()
+        .map(|(name, old, new)| (&**name, new, old))
+        .collect();
+    let report = TomlDiff::diff_batch(&pairs, &load_options(None)?);
+    print!("{report}");
+    if whole_files {
+        // A file in only one directory is diffed against nothing, so its diff is all of it
+        let terminal = io::stdout().is_terminal();
+        for (name, diff) in &report.diffs {
+            let header = match (exists(old_dir, name), exists(new_dir, name)) {
+                (false, true) => "+++",
+                (true, false) => "---",
+                _ => continue,
+            };
+            println!("\n{header} {name}");
+            if terminal {
+                print!("{diff}");
+            } else {
+                print!("{diff:#}");
+            }
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Add the paths of the .toml files under `root.join(dir)` to `names`, relative to `root`
+fn find_toml_files(root: &Path, dir: &Path, names: &mut Vec<PathBuf>) -> Result<(), String> {
+    let path = root.join(dir);
+    let entries = fs::read_dir(&path).map_err(|err| format!("{}: {err}", path.display()))?;
+    for entry in entries {
+        let entry = entry.map_err(|err| format!("{}: {err}", path.display()))?;
+        let name = dir.join(entry.file_name());
+        if entry.path().is_dir() {
+            find_toml_files(root, &name, names)?;
+        } else if name.extension().is_some_and(|ext| ext == "toml") {
+            names.push(name);
+        }
+    }
+    Ok(())
+}
+
+fn run_workspace(old_root: &str, new_root: &str) -> Result<ExitCode, String> {
+    let read = |root: &str| -> Result<Vec<(String, TomlValue)>, String> {
+        let manifest = read_toml(&Path::new(root).join("Cargo.toml").to_string_lossy())?;
+        let mut members = vec![];
+        for name in workspace_members(Path::new(root), &manifest)? {
+            let path = Path::new(root).join(&name).join("Cargo.toml");
+            members.push((name, read_toml(&path.to_string_lossy())?));
+        }
+        members.push((".".to_owned(), manifest));
+        Ok(members)
+    };
+    let (old, new) = (read(old_root)?, read(new_root)?);
+    fn members(members: &[(String, TomlValue)]) -> Vec<(&str, &TomlValue)> {
+        members
+            .iter()
+            .map(|(name, manifest)| (name.as_str(), manifest))
+            .collect()
+    }
+    print!("{}", workspace::diff(&members(&new), &members(&old)));
+    Ok(ExitCode::SUCCESS)
+}
+
+/// The directories of the members of the workspace whose root manifest is `manifest`, relative
+/// to `root`, with the globs in `workspace.members` expanded and `workspace.exclude` left out.
+/// Only directories holding a Cargo.toml are members.
+fn workspace_members(root: &Path, manifest: &TomlValue) -> Result<Vec<String>, String> {
+    let paths = |key| {
+        let paths = manifest.get("workspace").and_then(|w| w.get(key));
+        let paths = paths.and_then(TomlValue::as_array).into_iter().flatten();
+        paths.filter_map(TomlValue::as_str).collect::<Vec<_>>()
+    };
+    let exclude = paths("exclude");
+    let mut members = vec![];
+    for pattern in paths("members") {
+        let mut dirs = vec![PathBuf::new()];
+        for segment in pattern.split('/').filter(|s| !s.is_empty() && *s != ".") {
+            if !segment.contains('*') {
+                dirs.iter_mut().for_each(|dir| dir.push(segment));
+                continue;
+            }
+            let mut matches = vec![];
+            for dir in dirs {
+                let path = root.join(&dir);
+                let entries =
+                    fs::read_dir(&path).map_err(|err| format!("{}: {err}", path.display()))?;
+                for entry in entries {
+                    let entry = entry.map_err(|err| format!("{}: {err}", path.display()))?;
+                    let name = entry.file_name();
+                    let name = name.to_string_lossy();
+                    if entry.path().is_dir() && PathPattern::new(segment).matches(&[&name]) {
+                        matches.push(dir.join(&*name));
+                    }
+                }
+            }
+            dirs = matches;
+        }
+        for dir in dirs {
+            let name = dir.to_string_lossy().replace('\\', "/");
+            if root.join(&dir).join("Cargo.toml").is_file() && !exclude.contains(&&*name) {
+                members.push(name);
+            }
+        }
+    }
+    members.sort();
+    members.dedup();
+    Ok(members)
+}
+
+fn run_merge(base: &str, ours: &str, theirs: &str) -> Result<ExitCode, String> {
+    let merge = merge(&read_toml(base)?, &read_toml(ours)?, &read_toml(theirs)?);
+    fs::write(ours, merge.to_diff3_string()).map_err(|err| format!("{ours}: {err}"))?;
+    if merge.conflicts.is_empty() {
+        Ok(ExitCode::SUCCESS)
+    } else {
+        eprintln!("{ours}: {} conflict(s)", merge.conflicts.len());
+        Ok(ExitCode::FAILURE)
+    }
+}
+
+fn read_toml(path: &str) -> Result<TomlValue, String> {
+    let text = fs::read_to_string(path).map_err(|err| format!("{path}: {err}"))?;
+    toml::from_str(&text).map_err(|err| format!("{path}: {err}"))
+}
@@ -0,0 +1,270 @@
+#[cfg(feature = "render")]
+use std::fmt::Write;
+
+use toml::{map::Map, Value as TomlValue};
+
+#[cfg(feature = "render")]
+use crate::{display::format_value, span::Spans, PathSegment};
+
+/// The result of a three-way [`merge`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Merge {
+    /// The merged document. Wherever there is a conflict, it holds "our" side.
+    pub merged: TomlValue,
+    pub conflicts: Vec<Conflict>,
+}
+
+/// A key that both sides of a [`merge`] changed in different ways. A side that deleted the key
+/// holds `None`, as does `base` if both sides added the key.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Conflict {
+    pub key_path: Vec<String>,
+    pub base: Option<TomlValue>,
+    pub ours: Option<TomlValue>,
+    pub theirs: Option<TomlValue>,
+}
+
+/// Merge the changes made from `base` to `ours` with those made from `base` to `theirs`.
+///
+/// Tables are merged key by key. Arrays are merged as unordered collections: elements `theirs`
+/// added are appended to `ours`, and elements `theirs` removed are removed from `ours`, so
+/// arrays never conflict. Any other key changed differently on both sides is a conflict.
+pub fn merge(base: &TomlValue, ours: &TomlValue, theirs: &TomlValue) -> Merge {
+    let mut conflicts = vec![];
+    let merged = merge_values(
+        &mut vec![],
+        Some(base),
+        Some(ours),
+        Some(theirs),
+        &mut conflicts,
+    )
+    .unwrap_or_else(|| TomlValue::Table(Map::new()));
+    Merge { merged, conflicts }
+}
+
+fn merge_values(
+    key_path: &mut Vec<String>,
+    base: Option<&TomlValue>,
+    ours: Option<&TomlValue>,
+    theirs: Option<&TomlValue>,
+    conflicts: &mut Vec<Conflict>,
+) -> Option<TomlValue> {
+    if ours == theirs || theirs == base {
+        return ours.cloned();
+    }
+    if ours == base {
+        return theirs.cloned();
+    }
+    match (base, ours, theirs) {
+        (
+            None | Some(TomlValue::Table(_)),
+            Some(TomlValue::Table(ours)),
+            Some(TomlValue::Table(theirs)),
+        ) => {
+            let empty = Map::new();
+            let base = base.and_then(TomlValue::as_table).unwrap_or(&empty);
+            let mut keys: Vec<_> = base
+                .keys()
+                .chain(ours.keys())
+                .chain(theirs.keys())
+                .collect();
+            keys.sort();
+            keys.dedup();
+            let mut merged = Map::new();
+            for key in keys {
+                key_path.push(key.clone());
+                let val = merge_values(
+                    key_path,
+                    base.get(key),
+                    ours.get(key),
+                    theirs.get(key),
+                    conflicts,
+                );
+                key_path.pop();
+                if let Some(val) = val {
+                    merged.insert(key.clone(), val);
+                }
+            }
+            Some(TomlValue::Table(merged))
+        }
+        (
+            Some(TomlValue::Array(base)),
+            Some(TomlValue::Array(ours)),
+            Some(TomlValue::Array(theirs)),
+        ) => {
+            let mut merged = ours.clone();
+            // Elements in `base` that `theirs` doesn't have any more were removed by `theirs`
+            let mut kept = theirs.clone();
+            for elem in base {
+                match kept.iter().position(|e| e == elem) {
+                    Some(i) => {
+                        kept.remove(i);
+                    }
+                    None => {
+                        if let Some(i) = merged.iter().position(|e| e == elem) {
+                            merged.remove(i);
+                        }
+                    }
+                }
+            }
+            // Whatever is left in `kept` was added by `theirs`
+            merged.extend(kept);
+            Some(TomlValue::Array(merged))
+        }
+        _ => {
+            conflicts.push(Conflict {
+                key_path: key_path.clone(),
+                base: base.cloned(),
+                ours: ours.cloned(),
+                theirs: theirs.cloned(),
+            });
+            ours.cloned()
+        }
+    }
+}
+
+#[cfg(feature = "render")]
+impl Merge {
+    /// Render the merged document as TOML, followed by a comment block for each conflict in the
+    /// style of git's conflict markers.
+    pub fn to_toml_string(&self) -> String {
+        let mut s = toml::to_string(&self.merged).unwrap();
+        for conflict in &self.conflicts {
+            if !s.is_empty() && !s.ends_with("\n\n") {
+                s.push('\n');
+            }
+            let key_path: Vec<_> = conflict
+                .key_path
+                .iter()
+                .map(|key| PathSegment::Key(key))
+                .collect();
+            writeln!(s, "# <<<<<<< ours").unwrap();
+            push_commented(&mut s, &key_path, conflict.ours.as_ref());
+            writeln!(s, "# =======").unwrap();
+            push_commented(&mut s, &key_path, conflict.theirs.as_ref());
+            writeln!(s, "# >>>>>>> theirs").unwrap();
+        }
+        s
+    }
+}
+
+#[cfg(feature = "render")]
+impl Merge {
+    /// Render the merged document as TOML with each conflict marked where it is, in the style of
+    /// git's `diff3` conflicts, so that it can be resolved in an editor:
+    ///
+    /// ```text
+    /// [server]
+    /// # <<<<<<< ours
+    /// port = 9090
+    /// # ||||||| base
+    /// # port = 8080
+    /// # =======
+    /// # port = 7070
+    /// # >>>>>>> theirs
+    /// ```
+    ///
+    /// Our side is left uncommented, so the document is still valid TOML that holds the merged
+    /// document. A key our side deleted is marked at the end of the table it was in, or of the
+    /// top-level keys.
+    pub fn to_diff3_string(&self) -> String {
+        let merged = toml::to_string(&self.merged).unwrap();
+        let spans = Spans::new(&merged);
+        let lines: Vec<&str> = merged.lines().collect();
+        // Where each conflict goes: the index of its first line, how many lines of the merged
+        // document its block replaces, and the block
+        let mut blocks = vec![];
+        for conflict in &self.conflicts {
+            let key_path: Vec<_> = conflict
+                .key_path
+                .iter()
+                .map(|key| PathSegment::Key(key))
+                .collect();
+            let span = spans.find(&key_path);
+            let (at, len) = match (&conflict.ours, span) {
+                (Some(_), Some(span)) => {
+                    // The span ends at the start of the line after it if it takes in a newline
+                    let end = span.end.line - usize::from(span.end.column == 1);
+                    (span.start.line - 1, end + 1 - span.start.line)
+                }
+                // The span of the table the key was in
+                (None, Some(span)) => (span.end.line - usize::from(span.end.column == 1), 0),
+                (_, None) => {
+                    let mut at = lines
+                        .iter()
+                        .position(|line| line.starts_with('['))
+                        .unwrap_or(lines.len());
+                    while at > 0 && lines[at - 1].is_empty() {
+                        at -= 1;
+                    }
+                    (at, 0)
+                }
+            };
+            // Values are written relative to the table they're in, except tables, which are
+            // written under their full header
+            let relative = &key_path[key_path.len() - 1..];
+            let mut block = String::new();
+            writeln!(block, "# <<<<<<< ours").unwrap();
+            match conflict.ours {
+                Some(_) => {
+                    for line in &lines[at..at + len] {
+                        writeln!(block, "{line}").unwrap();
+                    }
+                }
+                None => writeln!(block, "# (deleted)").unwrap(),
+            }
+            writeln!(block, "# ||||||| base").unwrap();
+            match &conflict.base {
+                Some(base) => push_commented_in(&mut block, &key_path, relative, Some(base)),
+                None => writeln!(block, "# (absent)").unwrap(),
+            }
+            writeln!(block, "# =======").unwrap();
+            push_commented_in(&mut block, &key_path, relative, conflict.theirs.as_ref());
+            writeln!(block, "# >>>>>>> theirs").unwrap();
+            blocks.push((at, len, block));
+        }
+        // Blocks at the same line stay in the order of the conflicts
+        blocks.sort_by_key(|&(at, ..)| at);
+
+        let mut s = String::new();
+        let mut blocks = blocks.into_iter().peekable();
+        // The lines up to this one are in a block already
+        let mut replaced = 0;
+        for i in 0..=lines.len() {
+            while let Some((_, len, block)) = blocks.next_if(|&(at, ..)| at == i) {
+                s.push_str(&block);
+                replaced = replaced.max(i + len);
+            }
+            if i < lines.len() && i >= replaced {
+                writeln!(s, "{}", lines[i]).unwrap();
+            }
+        }
+        s
+    }
+}
+
+/// Comment out `val` as [`push_commented`] does, with its key written as `relative` unless it's a
+/// table, which is written under the header of its full `key_path`
+#[cfg(feature = "render")]
+fn push_commented_in(
+    s: &mut String,
+    key_path: &[PathSegment],
+    relative: &[PathSegment],
+    val: Option<&TomlValue>,
+) {
+    match val {
+        Some(val) if !val.is_table() => push_commented(s, relative, Some(val)),
+        val => push_commented(s, key_path, val),
+    }
+}
+
+#[cfg(feature = "render")]
+fn push_commented(s: &mut String, key_path: &[PathSegment], val: Option<&TomlValue>) {
+    let Some(val) = val else {
+        writeln!(s, "# (deleted)").unwrap();
+        return;
+    };
+    for line in format_value(key_path, val).unwrap().lines() {
+        writeln!(s, "# {line}").unwrap();
+    }
+}
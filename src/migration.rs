@@ -0,0 +1,334 @@
+//! Config migrations generated from a diff. A [`Migration`] is the list of edits that turns the
+//! old document into the new one, each with a description for a changelog or a code review,
+//! in the order they must be made:
+//!
+//! ```text
+//! 1. Rename `server.Port` to `port`
+//! 2. Move `http_port` to `server.http_port`
+//! 3. Remove `cache`
+//! 4. Change `server.port` from 80 to 8080
+//! 5. Add `server.workers = 4`
+//! ```
+//!
+//! [`Migration::to_rust_snippet`] writes the same edits as Rust code using the
+//! [`toml_edit`](https://docs.rs/toml_edit) crate, which keeps the formatting and comments of
+//! the documents it edits, as a starting point for a hand-maintained migration.
+
+use std::fmt::{self, Write};
+
+use toml::Value as TomlValue;
+
+use crate::path::format_key_path;
+use crate::report::inline;
+use crate::{PathSegment, TomlChange, TomlDiff};
+
+/// The edits that turn the old document of a diff into the new one, from
+/// [`TomlDiff::to_migration`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Migration<'a> {
+    /// The steps in the order they must be made, in which each step's key path is valid once
+    /// the steps before it have been made
+    pub steps: Vec<Step<'a>>,
+}
+
+/// One edit of a [`Migration`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Step<'a> {
+    pub operation: Operation<'a>,
+    /// What the step does, like ``Change `server.port` from 80 to 8080``
+    pub description: String,
+}
+
+/// What a [`Step`] does to the document.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operation<'a> {
+    /// Rename the last key of `key_path` from `old_key`
+    Rename {
+        key_path: Vec<PathSegment<'a>>,
+        old_key: &'a str,
+    },
+    /// Move the key at `old_path` to `key_path`
+    Move {
+        key_path: Vec<PathSegment<'a>>,
+        old_path: Vec<PathSegment<'a>>,
+    },
+    /// Reorder the array at `key_path`, so that element `i` is the element that was at
+    /// `permutation[i]`
+    Reorder {
+        key_path: Vec<PathSegment<'a>>,
+        permutation: Vec<usize>,
+    },
+    /// Remove the key or array element at `key_path`
+    Remove { key_path: Vec<PathSegment<'a>> },
+    /// Set the key at `key_path` to `value`, adding it if it isn't there
+    Set {
+        key_path: Vec<PathSegment<'a>>,
+        value: &'a TomlValue,
+    },
+    /// Insert `value` into an array, at the index `key_path` ends with
+    Insert {
+        key_path: Vec<PathSegment<'a>>,
+        value: &'a TomlValue,
+    },
+}
+
+impl<'a> TomlDiff<'a> {
+    /// The edits that turn the old document into the new one, in the order
+    /// [`TomlDiff::apply`] makes them. Redactions don't apply, since a migration needs the real
+    /// values.
+    pub fn to_migration(&self) -> Migration<'a> {
+        let steps = self
+            .apply_order()
+            .into_iter()
+            .map(|change| {
+                let key_path = change.key_path().to_vec();
+                let path = format_key_path(&key_path);
+                let is_element = matches!(key_path.last(), Some(PathSegment::Index(_)));
+                match *change {
+                    TomlChange::KeyStyleChanged(_, old_key) => {
+                        let mut old_path = key_path.clone();
+                        *old_path.last_mut().unwrap() = PathSegment::Key(old_key);
+                        let new_key = key_path.last().unwrap();
+                        Step {
+                            description: format!(
+                                "Rename `{}` to `{}`",
+                                format_key_path(&old_path),
+                                format_key_path(&[*new_key]),
+                            ),
+                            operation: Operation::Rename { key_path, old_key },
+                        }
+                    }
+                    TomlChange::Moved(_, ref old_path) => Step {
+                        description: format!("Move `{}` to `{path}`", format_key_path(old_path)),
+                        operation: Operation::Move {
+                            key_path,
+                            old_path: old_path.clone(),
+                        },
+                    },
+                    TomlChange::Reordered(_, ref permutation) => Step {
+                        description: format!("Reorder `{path}`"),
+                        operation: Operation::Reorder {
+                            key_path,
+                            permutation: permutation.clone(),
+                        },
+                    },
+                    TomlChange::Deleted(..) => Step {
+                        description: format!("Remove `{path}`"),
+                        operation: Operation::Remove { key_path },
+                    },
+                    TomlChange::Changed(_, old, new) => Step {
+                        description: format!(
+                            "Change `{path}` from {} to {}",
+                            inline(old),
+                            inline(new)
+                        ),
+                        operation: Operation::Set {
+                            key_path,
+                            value: new,
+                        },
+                    },
+                    TomlChange::Added(_, new) => Step {
+                        description: format!("Add `{path} = {}`", inline(new)),
+                        operation: if is_element {
+                            Operation::Insert {
+                                key_path,
+                                value: new,
+                            }
+                        } else {
+                            Operation::Set {
+                                key_path,
+                                value: new,
+                            }
+                        },
+                    },
+                }
+            })
+            .collect();
+        Migration { steps }
+    }
+}
+
+/// The helpers every snippet calls, written once at the top of it
+const RUST_HELPERS: &str = r#"/// A value written inline, like `8080` or `{ enabled = true }`
+fn value(toml: &str) -> Item {
+    let mut doc: DocumentMut = format!("value = {toml}").parse().unwrap();
+    doc.remove("value").unwrap()
+}
+
+/// A table written under a header of its own
+fn table(toml: &str) -> Item {
+    let doc: DocumentMut = toml.parse().unwrap();
+    Item::Table(doc.as_table().clone())
+}
+
+fn rename(table: &mut Item, old_key: &str, new_key: &str) {
+    let table = table.as_table_like_mut().unwrap();
+    let item = table.remove(old_key).unwrap();
+    table.insert(new_key, item);
+}
+
+fn take(table: &mut Item, key: &str) -> Item {
+    table.as_table_like_mut().unwrap().remove(key).unwrap()
+}
+
+fn remove(table: &mut Item, key: &str) {
+    table.as_table_like_mut().unwrap().remove(key);
+}
+
+fn remove_element(array: &mut Item, index: usize) {
+    match array {
+        Item::ArrayOfTables(tables) => {
+            tables.remove(index);
+        }
+        array => {
+            array.as_array_mut().unwrap().remove(index);
+        }
+    }
+}
+
+fn insert_element(array: &mut Item, index: usize, element: Item) {
+    match (array, element) {
+        (Item::ArrayOfTables(tables), Item::Table(table)) => {
+            let mut elements: Vec<Table> = tables.iter().cloned().collect();
+            elements.insert(index, table);
+            tables.clear();
+            for element in elements {
+                tables.push(element);
+            }
+        }
+        (array, element) => {
+            let element = element.into_value().unwrap();
+            array.as_array_mut().unwrap().insert_formatted(index, element);
+        }
+    }
+}
+
+fn reorder(array: &mut Item, permutation: &[usize]) {
+    match array {
+        Item::ArrayOfTables(tables) => {
+            let elements: Vec<Table> = tables.iter().cloned().collect();
+            tables.clear();
+            for &i in permutation {
+                tables.push(elements[i].clone());
+            }
+        }
+        array => {
+            let array = array.as_array_mut().unwrap();
+            let elements: Vec<Value> = array.iter().cloned().collect();
+            array.clear();
+            for &i in permutation {
+                array.push_formatted(elements[i].clone());
+            }
+        }
+    }
+}
+"#;
+
+impl Migration<'_> {
+    /// Write the migration as a Rust function, `migrate`, that makes the same edits to a
+    /// `toml_edit::DocumentMut`, after the helpers it calls for each kind of edit. Each edit is
+    /// commented with the description of its step. Added tables are written under headers of
+    /// their own, and other added values inline.
+    pub fn to_rust_snippet(&self) -> String {
+        let mut s = String::from("use toml_edit::{DocumentMut, Item, Table, Value};\n\n");
+        s.push_str(RUST_HELPERS);
+        s.push_str("\nfn migrate(doc: &mut DocumentMut) {\n");
+        for step in &self.steps {
+            writeln!(s, "    // {}", step.description).unwrap();
+            let key_path = match &step.operation {
+                Operation::Rename { key_path, .. }
+                | Operation::Move { key_path, .. }
+                | Operation::Reorder { key_path, .. }
+                | Operation::Remove { key_path }
+                | Operation::Set { key_path, .. }
+                | Operation::Insert { key_path, .. } => key_path,
+            };
+            let (last, parent) = key_path.split_last().unwrap();
+            let item = format!("doc{}", rust_index(key_path));
+            let item_mut = |key_path: &[PathSegment]| match key_path {
+                [] => "doc.as_item_mut()".to_owned(),
+                key_path => format!("&mut doc{}", rust_index(key_path)),
+            };
+            let parent = item_mut(parent);
+            let line = match (&step.operation, last) {
+                (Operation::Rename { old_key, .. }, PathSegment::Key(key)) => {
+                    format!("rename({parent}, {old_key:?}, {key:?});")
+                }
+                (Operation::Move { old_path, .. }, _) => match old_path.split_last() {
+                    Some((PathSegment::Key(old_key), old_parent)) => {
+                        format!("{item} = take({}, {old_key:?});", item_mut(old_parent))
+                    }
+                    _ => unreachable!(),
+                },
+                (Operation::Reorder { permutation, .. }, _) => {
+                    format!("reorder(&mut {item}, &{permutation:?});")
+                }
+                (Operation::Remove { .. }, PathSegment::Key(key)) => {
+                    format!("remove({parent}, {key:?});")
+                }
+                (Operation::Remove { .. }, PathSegment::Index(i)) => {
+                    format!("remove_element({parent}, {i});")
+                }
+                (Operation::Set { value, .. }, _) => format!("{item} = {};", rust_value(value)),
+                (Operation::Insert { value, .. }, PathSegment::Index(i)) => {
+                    format!("insert_element({parent}, {i}, {});", rust_value(value))
+                }
+                // Only keys are renamed and only array elements are inserted
+                _ => unreachable!(),
+            };
+            writeln!(s, "    {line}").unwrap();
+        }
+        s.push_str("}\n");
+        s
+    }
+}
+
+/// Renders the steps as a numbered list, one per line.
+impl fmt::Display for Migration<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, step) in self.steps.iter().enumerate() {
+            writeln!(f, "{}. {}", i + 1, step.description)?;
+        }
+        Ok(())
+    }
+}
+
+/// Rust code indexing an item down `key_path`, like `["servers"][0]`
+fn rust_index(key_path: &[PathSegment]) -> String {
+    let mut s = String::new();
+    for segment in key_path {
+        match segment {
+            PathSegment::Key(key) => write!(s, "[{key:?}]").unwrap(),
+            PathSegment::Index(i) => write!(s, "[{i}]").unwrap(),
+        }
+    }
+    s
+}
+
+/// Rust code building `val` as an item, calling `value` or `table` from [`RUST_HELPERS`]
+fn rust_value(val: &TomlValue) -> String {
+    let (helper, toml) = match val {
+        TomlValue::Table(_) => {
+            let toml = toml::to_string(val).unwrap_or_default();
+            ("table", toml.trim_end().to_owned())
+        }
+        val => ("value", inline(val)),
+    };
+    format!("{helper}({})", rust_raw_string(&toml))
+}
+
+/// `s` as a Rust raw string literal, with enough `#`s that nothing in it ends the literal
+fn rust_raw_string(s: &str) -> String {
+    let mut hashes = 0;
+    let mut run = None;
+    for c in s.chars() {
+        run = match (c, run) {
+            ('"', _) => Some(0),
+            ('#', Some(n)) => Some(n + 1),
+            _ => None,
+        };
+        hashes = hashes.max(run.map_or(0, |n| n + 1));
+    }
+    let hashes = "#".repeat(hashes);
+    format!("r{hashes}\"{s}\"{hashes}")
+}
@@ -0,0 +1,772 @@
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::Arc;
+
+use toml::Value as TomlValue;
+
+use crate::path;
+use crate::progress::ProgressCallback;
+use crate::unicode::{self, UnicodeForm};
+use crate::{DiffValue, PathPattern, TomlChange};
+
+/// Options tuning how [`TomlDiff::diff_with_options`](crate::TomlDiff::diff_with_options)
+/// compares two documents. The defaults match [`TomlDiff::diff`](crate::TomlDiff::diff).
+#[derive(Debug)]
+pub struct DiffOptions<V = TomlValue> {
+    pub(crate) aliases: Vec<(Vec<String>, Vec<String>)>,
+    pub(crate) arrays_as_sets: bool,
+    pub(crate) coalesce_tables: Option<f64>,
+    pub(crate) comparators: Vec<Comparator<V>>,
+    pub(crate) empty_equals_missing: bool,
+    pub(crate) error_on_unsupported: bool,
+    pub(crate) ignored: Vec<PathPattern>,
+    pub(crate) ignored_changes: Vec<ChangeRule<V>>,
+    pub(crate) key_case_insensitive: bool,
+    pub(crate) key_normalization: bool,
+    pub(crate) match_keys: Vec<(PathPattern, Vec<String>)>,
+    pub(crate) match_similar: bool,
+    pub(crate) max_array_len: Option<usize>,
+    pub(crate) max_changes: Option<usize>,
+    pub(crate) max_depth: Option<usize>,
+    pub(crate) memory_budget: Option<usize>,
+    pub(crate) natural_key_order: bool,
+    pub(crate) on_progress: Option<ProgressCallback>,
+    pub(crate) placeholder: Option<Placeholder<V>>,
+    pub(crate) record_suppressed: bool,
+    pub(crate) record_unchanged: bool,
+    pub(crate) report_reorders: bool,
+    pub(crate) structure_only: bool,
+    pub(crate) unicode_form: Option<UnicodeForm>,
+    pub(crate) values_only: bool,
+    #[cfg(feature = "parallel")]
+    pub(crate) parallel: bool,
+}
+
+/// How small a numeric change [`DiffOptions::ignore_below`] ignores.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Threshold {
+    /// Changes by less than this amount
+    Absolute(f64),
+    /// Changes by less than this fraction of the old value, so `0.05` ignores changes under 5%
+    Relative(f64),
+}
+
+/// A named preset of [`DiffOptions`] suiting a kind of document, applied with
+/// [`DiffOptions::profile`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Profile {
+    /// Every difference counts, down to the spelling of keys and the order of arrays
+    Strict,
+    /// Only differences in meaning count: keys match regardless of case and word separators,
+    /// arrays are compared as sets, empty values are the same as missing ones and similar
+    /// tables in arrays are paired up
+    Lenient,
+    /// For `Cargo.toml`: keys match regardless of word separators, as Cargo reads
+    /// `default_features` the same as `default-features`, empty values are the same as missing
+    /// ones, and a dependency written as a version string is the same as a table holding just
+    /// that version
+    CargoManifest,
+    /// For `pyproject.toml`: the same as [`Profile::CargoManifest`], with the version strings
+    /// under Poetry's dependency tables
+    PyProject,
+    /// For `Cargo.lock`: `[[package]]` entries are matched up by their name and source, so a
+    /// package that changed version is reported as the changes inside its entry, rather than as
+    /// one entry deleted and another added. [`lockfile::diff`](crate::lockfile::diff) lists the
+    /// changes to packages more compactly.
+    CargoLock,
+}
+
+impl Profile {
+    /// Every profile, in the order of their variants
+    pub const ALL: [Profile; 5] = [
+        Profile::Strict,
+        Profile::Lenient,
+        Profile::CargoManifest,
+        Profile::PyProject,
+        Profile::CargoLock,
+    ];
+
+    /// The name of the profile, as the CLI takes it: `strict`, `lenient`, `cargo`, `pyproject`
+    /// or `cargo-lock`
+    pub fn name(self) -> &'static str {
+        match self {
+            Profile::Strict => "strict",
+            Profile::Lenient => "lenient",
+            Profile::CargoManifest => "cargo",
+            Profile::PyProject => "pyproject",
+            Profile::CargoLock => "cargo-lock",
+        }
+    }
+
+    /// The profile called `name`, as given by [`Profile::name`]
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|profile| profile.name() == name)
+    }
+}
+
+/// A custom equality for values, given the old value and then the new one
+type ValueEq<V> = dyn Fn(&V, &V) -> bool + Send + Sync;
+
+/// A rule of [`DiffOptions`] that left changes out of a diff, from
+/// [`TomlDiff::suppressed`](crate::TomlDiff::suppressed).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IgnoreRule {
+    /// A pattern of keys left out with [`DiffOptions::ignore`]
+    Ignore(PathPattern),
+    /// A pattern of keys compared with a custom equality, by [`DiffOptions::compare`],
+    /// [`DiffOptions::ignore_below`] or a [profile](DiffOptions::profile)
+    Compare(PathPattern),
+    /// A pattern of keys whose changes are ignored by [`DiffOptions::ignore_change`]
+    IgnoreChange(PathPattern),
+}
+
+/// A custom equality for the values at key paths matching `pattern`
+pub(crate) struct Comparator<V> {
+    pattern: PathPattern,
+    eq: Arc<ValueEq<V>>,
+}
+
+impl<V> Clone for Comparator<V> {
+    fn clone(&self) -> Self {
+        Self {
+            pattern: self.pattern.clone(),
+            eq: self.eq.clone(),
+        }
+    }
+}
+
+impl<V> fmt::Debug for Comparator<V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("Comparator").field(&self.pattern).finish()
+    }
+}
+
+/// Whether to ignore a change, given the old value, if there is one, and then the new one
+type IgnoreChange<V> = dyn Fn(Option<&V>, Option<&V>) -> bool + Send + Sync;
+
+/// A rule ignoring the changes at key paths matching `pattern` that `ignore` returns `true` for
+pub(crate) struct ChangeRule<V> {
+    pub(crate) pattern: PathPattern,
+    ignore: Arc<IgnoreChange<V>>,
+}
+
+impl<V> Clone for ChangeRule<V> {
+    fn clone(&self) -> Self {
+        Self {
+            pattern: self.pattern.clone(),
+            ignore: self.ignore.clone(),
+        }
+    }
+}
+
+impl<V> ChangeRule<V> {
+    /// Whether this rule ignores `change`
+    pub(crate) fn ignores(&self, change: &TomlChange<V>) -> bool {
+        let (old, new) = match change {
+            TomlChange::Added(_, new) => (None, Some(*new)),
+            TomlChange::Deleted(_, old) => (Some(*old), None),
+            TomlChange::Changed(_, old, new) => (Some(*old), Some(*new)),
+            TomlChange::KeyStyleChanged(..) | TomlChange::Moved(..) | TomlChange::Reordered(..) => {
+                return false
+            }
+        };
+        self.pattern.matches(&path::keys(change.key_path())) && (self.ignore)(old, new)
+    }
+}
+
+impl<V> fmt::Debug for ChangeRule<V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("ChangeRule").field(&self.pattern).finish()
+    }
+}
+
+/// Whether a value of the old document is a placeholder
+type IsPlaceholder<V> = dyn Fn(&V) -> bool + Send + Sync;
+
+pub(crate) struct Placeholder<V>(Arc<IsPlaceholder<V>>);
+
+impl<V> Clone for Placeholder<V> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<V> fmt::Debug for Placeholder<V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Placeholder")
+    }
+}
+
+// Not derived, since that would require `V: Clone`
+impl<V> Clone for DiffOptions<V> {
+    fn clone(&self) -> Self {
+        Self {
+            aliases: self.aliases.clone(),
+            arrays_as_sets: self.arrays_as_sets,
+            coalesce_tables: self.coalesce_tables,
+            comparators: self.comparators.clone(),
+            empty_equals_missing: self.empty_equals_missing,
+            error_on_unsupported: self.error_on_unsupported,
+            ignored: self.ignored.clone(),
+            ignored_changes: self.ignored_changes.clone(),
+            key_case_insensitive: self.key_case_insensitive,
+            key_normalization: self.key_normalization,
+            match_keys: self.match_keys.clone(),
+            match_similar: self.match_similar,
+            max_array_len: self.max_array_len,
+            max_changes: self.max_changes,
+            max_depth: self.max_depth,
+            memory_budget: self.memory_budget,
+            natural_key_order: self.natural_key_order,
+            on_progress: self.on_progress.clone(),
+            placeholder: self.placeholder.clone(),
+            record_suppressed: self.record_suppressed,
+            record_unchanged: self.record_unchanged,
+            report_reorders: self.report_reorders,
+            structure_only: self.structure_only,
+            unicode_form: self.unicode_form,
+            values_only: self.values_only,
+            #[cfg(feature = "parallel")]
+            parallel: self.parallel,
+        }
+    }
+}
+
+impl<V> Default for DiffOptions<V> {
+    fn default() -> Self {
+        Self {
+            aliases: vec![],
+            arrays_as_sets: false,
+            coalesce_tables: None,
+            comparators: vec![],
+            empty_equals_missing: false,
+            error_on_unsupported: false,
+            ignored: vec![],
+            ignored_changes: vec![],
+            key_case_insensitive: false,
+            key_normalization: false,
+            match_keys: vec![],
+            match_similar: false,
+            max_array_len: None,
+            max_changes: None,
+            max_depth: None,
+            memory_budget: None,
+            natural_key_order: false,
+            on_progress: None,
+            placeholder: None,
+            record_suppressed: false,
+            record_unchanged: false,
+            report_reorders: false,
+            structure_only: false,
+            unicode_form: None,
+            values_only: false,
+            #[cfg(feature = "parallel")]
+            parallel: false,
+        }
+    }
+}
+
+impl<V> DiffOptions<V> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Compare the values of keys matching `pattern` with `eq` instead of plain equality, so
+    /// that domain-specific equality, such as of URLs or durations written in different ways,
+    /// can be plugged in. `eq` is given the old value, then the new one, and is only called for
+    /// keys present in both documents whose values aren't equal. If it returns `true`, the
+    /// values count as the same and nothing inside them is compared. Elements of arrays are
+    /// always compared with plain equality. If several patterns match a key, the first one
+    /// added is used.
+    pub fn compare(
+        mut self,
+        pattern: impl Into<PathPattern>,
+        eq: impl Fn(&V, &V) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.comparators.push(Comparator {
+            pattern: pattern.into(),
+            eq: Arc::new(eq),
+        });
+        self
+    }
+
+    /// The custom equality of the values at `key_path`, if it has one, and the pattern it was
+    /// added for
+    pub(crate) fn comparator(&self, key_path: &[&str]) -> Option<(&PathPattern, &ValueEq<V>)> {
+        self.comparators
+            .iter()
+            .find(|comparator| comparator.pattern.matches(key_path))
+            .map(|comparator| (&comparator.pattern, &*comparator.eq))
+    }
+
+    /// Treat a key holding an empty array, or a table with nothing but empty values in it, the
+    /// same as if the key wasn't there at all. Some generators write out `[section]` or `xs = []`
+    /// where others leave the key out, and this keeps those from showing up as changes.
+    pub fn empty_equals_missing(mut self, yes: bool) -> Self {
+        self.empty_equals_missing = yes;
+        self
+    }
+
+    /// Refuse to diff documents holding something this crate can't diff precisely, rather than
+    /// approximating its diff, so that automated pipelines can tell when a diff may be wrong or
+    /// incomplete. [`TomlDiff::try_diff_with_options`](crate::TomlDiff::try_diff_with_options)
+    /// returns [`DiffError::Unsupported`](crate::DiffError::Unsupported) for such documents,
+    /// naming the first [construct](crate::UnsupportedConstruct) found, and the other ways of
+    /// diffing panic.
+    pub fn error_on_unsupported(mut self, yes: bool) -> Self {
+        self.error_on_unsupported = yes;
+        self
+    }
+
+    /// Leave keys matching `pattern` out of both documents, so that nothing about them or
+    /// anything inside them is reported, such as generated timestamps. Keys inside the elements
+    /// of arrays are never left out, since elements are compared as a whole.
+    pub fn ignore(mut self, pattern: impl Into<PathPattern>) -> Self {
+        self.ignored.push(pattern.into());
+        self
+    }
+
+    /// The pattern the key at `key_path` is left out by with [`DiffOptions::ignore`], if any
+    pub(crate) fn ignored_by(&self, key_path: &[&str]) -> Option<&PathPattern> {
+        self.ignored
+            .iter()
+            .find(|pattern| pattern.matches(key_path))
+    }
+
+    /// Whether an ignore rule could leave out something inside the table at `key_path`
+    pub(crate) fn ignores_within(&self, key_path: &[&str]) -> bool {
+        let rules = self.ignored_changes.iter().map(|rule| &rule.pattern);
+        self.ignored
+            .iter()
+            .chain(rules)
+            .any(|pattern| pattern.matches_within(key_path))
+    }
+
+    /// Ignore the changes at key paths matching `pattern` for which `ignore` returns `true`,
+    /// given the old value and then the new one, either of which is `None` for an added or
+    /// deleted key. This ignores changes by what they do rather than where they are, like a
+    /// key added with an empty string, or a string whose case is all that changed. Changes
+    /// inside arrays are at the key path of their array and given the elements that changed,
+    /// and renamed and moved keys and reordered arrays, which change no values, are never ignored.
+    pub fn ignore_change(
+        mut self,
+        pattern: impl Into<PathPattern>,
+        ignore: impl Fn(Option<&V>, Option<&V>) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.ignored_changes.push(ChangeRule {
+            pattern: pattern.into(),
+            ignore: Arc::new(ignore),
+        });
+        self
+    }
+
+    /// Treat the key at `old`, a key path written as dotted keys like `http_port`, as having
+    /// moved to `new`, like `server.port`, so that a document with the key at `new` instead of
+    /// `old` has the move reported as a single [`TomlChange::Moved`], followed by any changes
+    /// between the old and new values, instead of a deletion and an unrelated addition. The key
+    /// is only taken to have moved if it alone was deleted and added, so the tables that hold
+    /// `old` and `new` have to be in both documents.
+    pub fn alias(mut self, old: &str, new: &str) -> Self {
+        let keys = |path: &str| path.split('.').map(str::to_owned).collect();
+        self.aliases.push((keys(old), keys(new)));
+        self
+    }
+
+    /// Compare arrays as sets, ignoring any repeated elements, so `[1, 1, 2]` and `[1, 2]` are
+    /// the same array.
+    pub fn arrays_as_sets(mut self, yes: bool) -> Self {
+        self.arrays_as_sets = yes;
+        self
+    }
+
+    /// Report a table in both documents as a single change to the whole table, instead of a
+    /// change for each key inside it, when more than `threshold` of its keys changed, so `0.8`
+    /// coalesces tables with over 80% of their keys added, deleted or changed. This keeps a
+    /// table that was replaced wholesale from burying the rest of the diff. Keys and values are
+    /// compared exactly for this, before any other options apply. A coalesced table is rendered
+    /// whole, so tables holding keys that an ignore rule could leave out are never coalesced.
+    pub fn coalesce_tables(mut self, threshold: f64) -> Self {
+        self.coalesce_tables = Some(threshold);
+        self
+    }
+
+    /// Match keys regardless of their letter case, so `Port` and `port` are the same key. If a
+    /// table holds several keys that are the same this way, like both `Port` and `port`, they
+    /// can't be told apart, so those keys are matched exactly.
+    pub fn key_case_insensitive(mut self, yes: bool) -> Self {
+        self.key_case_insensitive = yes;
+        self
+    }
+
+    /// Match keys regardless of word separators, so `max_connections`, `max-connections` and
+    /// `max connections` are the same key. Keys of one table that are the same this way are
+    /// matched exactly, as with [`DiffOptions::key_case_insensitive`].
+    pub fn key_normalization(mut self, yes: bool) -> Self {
+        self.key_normalization = yes;
+        self
+    }
+
+    /// Pair up tables in arrays at key paths matching `pattern` that aren't equal, but hold the
+    /// same values under `keys`, and diff each pair key by key rather than reporting one table
+    /// as deleted and the other as added, so an element of an array of tables can be identified
+    /// by a name or an ID, like `[[package]]` entries by their `name` and `source`. A key
+    /// missing from both tables counts as the same, but tables missing all of `keys` are never
+    /// paired. Pairs are made in the order of the added tables, before any
+    /// [similar tables](DiffOptions::match_similar) are, and the changes inside a pair have the
+    /// index of the old table in their key path.
+    pub fn match_by(mut self, pattern: impl Into<PathPattern>, keys: &[&str]) -> Self {
+        let keys = keys.iter().map(|key| key.to_string()).collect();
+        self.match_keys.push((pattern.into(), keys));
+        self
+    }
+
+    /// The keys [`DiffOptions::match_by`] pairs up the tables in the array at `key_path` by
+    pub(crate) fn match_keys(&self, key_path: &[&str]) -> Option<&[String]> {
+        self.match_keys
+            .iter()
+            .find(|(pattern, _)| pattern.matches(key_path))
+            .map(|(_, keys)| &**keys)
+    }
+
+    /// Pair up tables in arrays that aren't equal, but hold the same values under at least half
+    /// of their keys, and diff each pair key by key rather than reporting one table as deleted
+    /// and the other as added. This suits arrays of tables without a key to match them up by,
+    /// where an element is usually edited rather than replaced. The most similar tables are
+    /// paired first, and the changes inside a pair have the index of the old table in their key
+    /// path.
+    pub fn match_similar(mut self, yes: bool) -> Self {
+        self.match_similar = yes;
+        self
+    }
+
+    /// Report an array in both documents as a single change to the whole array, instead of a
+    /// change for each element, when either document has more than `n` elements in it besides
+    /// those both arrays start and end with. Matching up elements takes sorting them, and
+    /// pairing up tables by [`DiffOptions::match_similar`] can compare each added table with
+    /// many deleted ones, so this bounds the time spent on huge, unrelated arrays.
+    pub fn max_array_len(mut self, n: usize) -> Self {
+        self.max_array_len = Some(n);
+        self
+    }
+
+    /// Stop collecting changes after the first `n`, only counting the rest in
+    /// [`TomlDiff::truncated`](crate::TomlDiff::truncated). This keeps accidental diffs of huge,
+    /// unrelated documents from taking up huge amounts of memory and output.
+    pub fn max_changes(mut self, n: usize) -> Self {
+        self.max_changes = Some(n);
+        self
+    }
+
+    /// Refuse to diff documents with values more than `depth` keys and indices deep, as
+    /// pathological inputs from fuzzers can have, so that a diff or its rendering never runs
+    /// out of stack. [`TomlDiff::try_diff_with_options`](crate::TomlDiff::try_diff_with_options)
+    /// returns [`DiffError::MaxDepthExceeded`](crate::DiffError::MaxDepthExceeded) for such
+    /// documents, and the other ways of diffing panic. Checking the depth takes a pass over
+    /// both documents, so there's no limit by default.
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = Some(depth);
+        self
+    }
+
+    /// Stop collecting changes once they take up about `bytes` of memory, only counting the
+    /// rest under the top-level table or array they're in, in
+    /// [`TomlDiff::summaries`](crate::TomlDiff::summaries). This keeps adversarially large
+    /// documents from exhausting the memory of the process diffing them, while still saying
+    /// where they differ. The budget covers the changes, not the documents or the diff's own
+    /// bookkeeping. Diffing in parallel splits the budget between the threads, so fewer changes
+    /// may be kept than on a single thread.
+    pub fn memory_budget(mut self, bytes: usize) -> Self {
+        self.memory_budget = Some(bytes);
+        self
+    }
+
+    /// Order keys naturally, with each run of digits in them compared as a number, so tables
+    /// keyed by numbers like `"1"`, `"2"` and `"10"` have their changes listed in that order
+    /// rather than as `"1"`, `"10"`, `"2"`. This also orders the groups of
+    /// [`TomlDiff::grouped`](crate::TomlDiff::grouped) and the changes sorted by
+    /// [`ChangeOrder::Path`](crate::ChangeOrder::Path).
+    pub fn natural_key_order(mut self, yes: bool) -> Self {
+        self.natural_key_order = yes;
+        self
+    }
+
+    /// Call `callback` as the diff goes, with the number of keys and array elements visited so
+    /// far and an estimate of how many will be visited in total, to drive a progress bar for
+    /// huge documents. The estimate is the size of both documents, which a diff only reaches
+    /// once it's finished, since nothing inside an added or deleted value is visited. Diffing in
+    /// parallel calls `callback` from several threads at once.
+    pub fn on_progress(mut self, callback: impl Fn(usize, usize) + Send + Sync + 'static) -> Self {
+        self.on_progress = Some(ProgressCallback(Arc::new(callback)));
+        self
+    }
+
+    /// Treat the values of the old document for which `is_placeholder` returns `true` as
+    /// placeholders, which match any value of the same key in the new document. This compares a
+    /// rendered document against the template it was rendered from without flagging every
+    /// templated key. A placeholder doesn't match a missing key, and elements of arrays are
+    /// never placeholders.
+    pub fn placeholders(
+        mut self,
+        is_placeholder: impl Fn(&V) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.placeholder = Some(Placeholder(Arc::new(is_placeholder)));
+        self
+    }
+
+    /// Whether `val`, from the old document, is a placeholder
+    pub(crate) fn is_placeholder(&self, val: &V) -> bool {
+        self.placeholder
+            .as_ref()
+            .is_some_and(|placeholder| (placeholder.0)(val))
+    }
+
+    /// Count the changes that ignore rules leave out of the diff, by the rule that left each of
+    /// them out, in [`TomlDiff::suppressed`](crate::TomlDiff::suppressed), so that an ignore list
+    /// hiding more than it should can be caught. A key left out with [`DiffOptions::ignore`]
+    /// counts as a change unless it holds the same value in both documents, and changes inside
+    /// it aren't counted separately.
+    pub fn record_suppressed(mut self, yes: bool) -> Self {
+        self.record_suppressed = yes;
+        self
+    }
+
+    /// Record the keys present in both documents whose values are the same, or count as the
+    /// same under these options, in [`TomlDiff::unchanged`](crate::TomlDiff::unchanged), for
+    /// reports of which settings two environments share. A table or array that's the same as a
+    /// whole is recorded once, without anything inside it, and an array holding the same
+    /// elements in a different order counts as the same unless reorders are
+    /// [reported](DiffOptions::report_reorders).
+    pub fn record_unchanged(mut self, yes: bool) -> Self {
+        self.record_unchanged = yes;
+        self
+    }
+
+    /// Report arrays whose elements in both documents come in a different order as a single
+    /// [`TomlChange::Reordered`](crate::TomlChange::Reordered), alongside any elements added or
+    /// deleted. Arrays are compared without regard to order, so by default reordering an array
+    /// isn't a change at all.
+    pub fn report_reorders(mut self, yes: bool) -> Self {
+        self.report_reorders = yes;
+        self
+    }
+
+    /// Only report changes to the shape of the documents: added and deleted keys, and values
+    /// whose type changed. Values that changed but kept their type aren't reported, and arrays
+    /// count as values, so changes to their elements aren't reported either. This checks that
+    /// two environments are configured with the same keys, even though their values differ.
+    pub fn structure_only(mut self, yes: bool) -> Self {
+        self.structure_only = yes;
+        self
+    }
+
+    /// Put strings and keys in the Unicode normalization `form` before comparing them, so that
+    /// text written differently by different platforms and editors, such as an accented letter
+    /// typed as one character or as the letter followed by a combining accent, isn't reported
+    /// as changed, and neither is a key [renamed](crate::TomlChange::KeyStyleChanged) to another
+    /// form. Changes still hold the strings as they are in the documents.
+    pub fn unicode_normalization(mut self, form: UnicodeForm) -> Self {
+        self.unicode_form = Some(form);
+        self
+    }
+
+    /// Only report changed values of keys present in both documents, leaving out added and
+    /// deleted keys and keys whose spelling changed. Arrays count as values, so an array whose
+    /// elements changed is reported as a single change. This checks the settings two
+    /// environments share for drift, while allowing either to have extra keys of its own.
+    pub fn values_only(mut self, yes: bool) -> Self {
+        self.values_only = yes;
+        self
+    }
+
+    /// Diff each of the nested tables and arrays at the top level on its own thread. The changes
+    /// are the same, and in the same order, as when diffing on a single thread.
+    #[cfg(feature = "parallel")]
+    pub fn parallel(mut self, yes: bool) -> Self {
+        self.parallel = yes;
+        self
+    }
+
+    /// The form of `key` that keys are matched by between the two documents.
+    pub(crate) fn normalize_key<'k>(&self, key: &'k str) -> Cow<'k, str> {
+        let mut key = self.normalize_text(key);
+        if self.key_case_insensitive {
+            key = Cow::Owned(key.to_lowercase());
+        }
+        if self.key_normalization {
+            key = Cow::Owned(key.replace(['-', ' '], "_"));
+        }
+        key
+    }
+
+    /// How keys `a` and `b` are ordered when matching them up between the two documents, by
+    /// their normalized form, or by the keys themselves if their normalized form is among
+    /// `collisions`
+    pub(crate) fn compare_keys(&self, a: &str, b: &str, collisions: &HashSet<String>) -> Ordering {
+        let matched_by = |key| {
+            let normalized = self.normalize_key(key);
+            if collisions.contains(&*normalized) {
+                Cow::Borrowed(key)
+            } else {
+                normalized
+            }
+        };
+        let (a, b) = (matched_by(a), matched_by(b));
+        if self.natural_key_order {
+            path::natural_cmp(&a, &b)
+        } else {
+            a.cmp(&b)
+        }
+    }
+
+    /// The normalized forms shared by several different keys among `keys`, the keys of a table.
+    /// Keys are only normalized with key normalization enabled, so there are none otherwise.
+    pub(crate) fn colliding_keys<'k>(
+        &self,
+        keys: impl Iterator<Item = &'k str>,
+    ) -> HashSet<String> {
+        let mut collisions = HashSet::new();
+        if !self.key_case_insensitive && !self.key_normalization && self.unicode_form.is_none() {
+            return collisions;
+        }
+        let mut spellings: HashMap<Cow<str>, &str> = HashMap::new();
+        for key in keys {
+            let normalized = self.normalize_key(key);
+            match spellings.get(&normalized) {
+                Some(&spelling) if spelling != key => {
+                    collisions.insert(normalized.into_owned());
+                }
+                Some(_) => {}
+                None => {
+                    spellings.insert(normalized, key);
+                }
+            }
+        }
+        collisions
+    }
+
+    /// Whether `a` and `b` are strings that are the same once put in the
+    /// [normalization form](DiffOptions::unicode_normalization)
+    pub(crate) fn same_string(&self, a: &V, b: &V) -> bool
+    where
+        V: DiffValue,
+    {
+        match (self.unicode_form, a.as_str(), b.as_str()) {
+            (Some(_), Some(a), Some(b)) => self.normalize_text(a) == self.normalize_text(b),
+            _ => false,
+        }
+    }
+
+    /// Whether any option compares the keys or values inside tables in a way that matching up
+    /// array elements by their [sort keys](DiffOptions::sort_key) doesn't
+    pub(crate) fn tunes_nested_values(&self) -> bool {
+        !self.ignored.is_empty()
+            || !self.comparators.is_empty()
+            || self.key_case_insensitive
+            || self.key_normalization
+            || self.empty_equals_missing
+            || self.placeholder.is_some()
+    }
+
+    /// The sort key of `val`, which array elements are matched up by, with any strings in it
+    /// put in the [normalization form](DiffOptions::unicode_normalization)
+    pub(crate) fn sort_key(&self, val: &V) -> String
+    where
+        V: DiffValue,
+    {
+        let key = val.sort_key();
+        match self.normalize_text(&key) {
+            Cow::Borrowed(_) => key,
+            Cow::Owned(key) => key,
+        }
+    }
+
+    /// `s` in the [normalization form](DiffOptions::unicode_normalization), if there is one
+    pub(crate) fn normalize_text<'s>(&self, s: &'s str) -> Cow<'s, str> {
+        match self.unicode_form {
+            Some(form) => unicode::normalize(s, form),
+            None => Cow::Borrowed(s),
+        }
+    }
+}
+
+impl DiffOptions {
+    /// Apply the options of `profile`, replacing any earlier settings of the options it covers.
+    /// Options it doesn't cover, like keys to [ignore](DiffOptions::ignore), are kept, so a
+    /// profile can be tuned further for a particular document.
+    pub fn profile(mut self, profile: Profile) -> Self {
+        let lenient = profile == Profile::Lenient;
+        self.arrays_as_sets = lenient;
+        self.key_case_insensitive = lenient;
+        self.match_similar = lenient;
+        self.report_reorders = profile == Profile::Strict;
+        // Lockfiles are written by Cargo alone, so nothing about them needs loosening
+        let loose = !matches!(profile, Profile::Strict | Profile::CargoLock);
+        self.empty_equals_missing = loose;
+        self.key_normalization = loose;
+        let dependencies: &[&str] = match profile {
+            Profile::Strict | Profile::Lenient | Profile::CargoLock => &[],
+            Profile::CargoManifest => &[
+                "dependencies.*",
+                "dev-dependencies.*",
+                "build-dependencies.*",
+                "target.*.dependencies.*",
+                "target.*.dev-dependencies.*",
+                "target.*.build-dependencies.*",
+                "workspace.dependencies.*",
+            ],
+            Profile::PyProject => &[
+                "tool.poetry.dependencies.*",
+                "tool.poetry.dev-dependencies.*",
+                "tool.poetry.group.*.dependencies.*",
+            ],
+        };
+        for pattern in dependencies {
+            self = self.compare(*pattern, |old, new| {
+                dependency_table(old) == dependency_table(new)
+            });
+        }
+        if profile == Profile::CargoLock {
+            self = self.match_by("package", &["name", "source"]);
+        }
+        self
+    }
+
+    /// Treat strings in the old document equal to `marker`, such as `"${ANY}"`, as
+    /// [placeholders](DiffOptions::placeholders).
+    pub fn placeholder(self, marker: impl Into<String>) -> Self {
+        let marker = marker.into();
+        self.placeholders(move |val| val.as_str() == Some(&marker))
+    }
+
+    /// Ignore changes to numbers under keys matching `pattern` that are smaller than
+    /// `threshold`, so that diffs of auto-tuned configuration only show meaningful adjustments.
+    /// Integers and floats are compared alike. A relative threshold never ignores a change from
+    /// zero. This is a [comparator](DiffOptions::compare), and the same rules apply to it.
+    pub fn ignore_below(self, pattern: impl Into<PathPattern>, threshold: Threshold) -> Self {
+        self.compare(pattern, move |old, new| {
+            let as_float = |val: &TomlValue| val.as_float().or(val.as_integer().map(|i| i as f64));
+            let (Some(old), Some(new)) = (as_float(old), as_float(new)) else {
+                return false;
+            };
+            let delta = (new - old).abs();
+            match threshold {
+                Threshold::Absolute(min) => delta < min,
+                Threshold::Relative(min) => delta < min * old.abs(),
+            }
+        })
+    }
+}
+
+/// A dependency as a table, with a version string written as a table holding just that version
+pub(crate) fn dependency_table(dependency: &TomlValue) -> Cow<'_, TomlValue> {
+    match dependency {
+        TomlValue::String(version) => {
+            let mut table = toml::map::Map::new();
+            table.insert("version".to_owned(), TomlValue::String(version.clone()));
+            Cow::Owned(TomlValue::Table(table))
+        }
+        dependency => Cow::Borrowed(dependency),
+    }
+}
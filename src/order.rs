@@ -0,0 +1,60 @@
+use std::cmp::Reverse;
+
+use crate::path::natural_cmp_paths;
+use crate::{DiffValue, TomlChange, TomlDiff};
+
+/// An order to list a diff's changes in, for [`TomlDiff::sort_by`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ChangeOrder {
+    /// Deletions, then additions, changed values, renamed keys, moved keys and reordered arrays
+    Kind,
+    /// By key path, so that changes sit beside the changes to the keys next to them
+    Path,
+    /// By [size](TomlChange::size), biggest first: those touching the most entries, then the
+    /// most bytes
+    Impact,
+}
+
+impl ChangeOrder {
+    /// Every order, in the order of their variants
+    pub const ALL: [ChangeOrder; 3] = [ChangeOrder::Kind, ChangeOrder::Path, ChangeOrder::Impact];
+
+    /// The name of the order, as the CLI takes it: `kind`, `path` or `impact`
+    pub fn name(self) -> &'static str {
+        match self {
+            ChangeOrder::Kind => "kind",
+            ChangeOrder::Path => "path",
+            ChangeOrder::Impact => "impact",
+        }
+    }
+
+    /// The order called `name`, as given by [`ChangeOrder::name`]
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|order| order.name() == name)
+    }
+}
+
+impl<'a, V: DiffValue> TomlDiff<'a, V> {
+    /// List the changes in `order` instead of the order the documents were walked in. The sort
+    /// is stable, so changes that `order` ranks alike keep their order.
+    pub fn sort_by(mut self, order: ChangeOrder) -> Self {
+        match order {
+            ChangeOrder::Kind => self.changes.sort_by_key(|change| match change {
+                TomlChange::Deleted(..) => 0,
+                TomlChange::Added(..) => 1,
+                TomlChange::Changed(..) => 2,
+                TomlChange::KeyStyleChanged(..) => 3,
+                TomlChange::Moved(..) => 4,
+                TomlChange::Reordered(..) => 5,
+            }),
+            ChangeOrder::Path if self.natural_key_order => self
+                .changes
+                .sort_by(|a, b| natural_cmp_paths(a.key_path(), b.key_path())),
+            ChangeOrder::Path => self.changes.sort_by(|a, b| a.key_path().cmp(b.key_path())),
+            ChangeOrder::Impact => self.changes.sort_by_key(|change| Reverse(change.size())),
+        }
+        // Updating relies on the changes being in the order they were found in
+        self.documents = None;
+        self
+    }
+}
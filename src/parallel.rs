@@ -0,0 +1,117 @@
+use std::num::NonZeroUsize;
+use std::thread;
+
+use crate::{diff_level, ChangeList, DiffOptions, DiffValue, Progress, StackItem, TomlDiff};
+
+/// Diff every Table and Array pair in `stack` to completion, spreading them across threads, and
+/// add their changes to `changes` in the order a single-threaded diff would have produced them.
+pub(crate) fn diff_subtrees<'a, V: DiffValue>(
+    stack: &mut Vec<StackItem<'a, V>>,
+    options: &DiffOptions<V>,
+    progress: Option<&Progress>,
+    changes: &mut ChangeList<'a, V>,
+) {
+    // A single-threaded diff pops subtrees off the end of the stack, finishing each one before
+    // starting the next, so that's the order their changes are concatenated in
+    let jobs: Vec<_> = stack.drain(..).rev().collect();
+    let threads = thread::available_parallelism().map_or(1, NonZeroUsize::get);
+    let chunk_size = jobs.len().div_ceil(threads).max(1);
+    let results: Vec<_> = thread::scope(|scope| {
+        let handles: Vec<_> = jobs
+            .chunks(chunk_size)
+            .map(|chunk| {
+                scope.spawn(move || {
+                    let mut changes = ChangeList::new(options);
+                    // The threads share the budget, so that together they stay within it
+                    changes.budget = options.memory_budget.map(|budget| budget / threads);
+                    for (a, b, key_path) in chunk {
+                        let mut stack = vec![(*a, *b, key_path.clone())];
+                        while let Some((a, b, key_path)) = stack.pop() {
+                            diff_level(a, b, key_path, options, progress, &mut changes, &mut stack);
+                        }
+                    }
+                    changes
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .collect()
+    });
+    for result in results {
+        changes.extend(result.changes);
+        let summarized: usize = result.summaries.iter().map(|(_, n)| n).sum();
+        for (key_path, n) in result.summaries {
+            changes.summarize(key_path, n);
+        }
+        changes.truncated += result.truncated - summarized;
+        if let (Some(unchanged), Some(result)) = (&mut changes.unchanged, result.unchanged) {
+            unchanged.extend(result);
+        }
+        for (rule, n) in result.suppressed.into_iter().flatten() {
+            changes.count_suppressed(rule, n);
+        }
+        if changes.unsupported.is_none() {
+            changes.unsupported = result.unsupported;
+        }
+    }
+}
+
+/// Diff each of `pairs` on a thread of its own, in as many chunks as there are threads.
+pub(crate) fn diff_batch<'a, V: DiffValue>(
+    pairs: &[(&'a str, &'a V, &'a V)],
+    options: &DiffOptions<V>,
+) -> Vec<(&'a str, TomlDiff<'a, V>)> {
+    // Each diff runs on a single thread, since the pairs are already spread across threads
+    let options = &options.clone().parallel(false);
+    let threads = thread::available_parallelism().map_or(1, NonZeroUsize::get);
+    let chunk_size = pairs.len().div_ceil(threads).max(1);
+    let results: Vec<_> = thread::scope(|scope| {
+        let handles: Vec<_> = pairs
+            .chunks(chunk_size)
+            .map(|chunk| {
+                scope.spawn(move || {
+                    // Diffs can't be sent between threads, since they may hold redactions
+                    let diffs: Vec<_> = chunk
+                        .iter()
+                        .map(|&(name, a, b)| {
+                            let diff = TomlDiff::diff_with_options(a, b, options);
+                            let TomlDiff {
+                                changes,
+                                truncated,
+                                summaries,
+                                unchanged,
+                                suppressed,
+                                ..
+                            } = diff;
+                            (name, changes, truncated, summaries, unchanged, suppressed)
+                        })
+                        .collect();
+                    diffs
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect()
+    });
+    results
+        .into_iter()
+        .map(
+            |(name, changes, truncated, summaries, unchanged, suppressed)| {
+                let diff = TomlDiff {
+                    summaries,
+                    unchanged,
+                    suppressed,
+                    natural_key_order: options.natural_key_order,
+                    #[cfg(feature = "render")]
+                    style: crate::display::Style::with_options(),
+                    ..TomlDiff::from_changes(changes, truncated)
+                };
+                (name, diff)
+            },
+        )
+        .collect()
+}
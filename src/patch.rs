@@ -0,0 +1,230 @@
+//! A patch format that is itself TOML, so that diffs can be stored and shipped alongside the
+//! documents they apply to. A patch is an array of `[[change]]` tables, each with an `op` and a
+//! `path`:
+//!
+//! ```toml
+//! [[change]]
+//! op = "replace"
+//! path = ["server", "port"]
+//! old = 80
+//! new = 8080
+//! ```
+//!
+//! `path` holds keys as strings and array indices as integers. Each op carries the values it
+//! needs to be applied: `add` has `new`, `remove` has `old`, `replace` has both, `rename` has
+//! the key's `old_key`, `move` has the key's `old_path`, written like `path`, and `reorder` has
+//! the array's `permutation`. Any
+//! [annotations](crate::TomlDiff::annotate) of a change are held in an `annotations` table of
+//! strings.
+
+use std::fmt;
+
+use toml::{map::Map, Value as TomlValue};
+
+use crate::annotate::Annotation;
+use crate::{PathSegment, TomlChange, TomlDiff};
+
+/// A parsed patch, ready to be applied. See the [module docs](self) for its format.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Patch {
+    doc: TomlValue,
+}
+
+/// An error from parsing a [`Patch`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum PatchError {
+    /// The patch isn't valid TOML
+    Toml(toml::de::Error),
+    /// The `[[change]]` at `index` is malformed
+    Change { index: usize, message: &'static str },
+}
+
+impl fmt::Display for PatchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PatchError::Toml(err) => write!(f, "{err}"),
+            PatchError::Change { index, message } => write!(f, "{message} in change {index}"),
+        }
+    }
+}
+
+impl std::error::Error for PatchError {}
+
+impl<'a> TomlDiff<'a> {
+    /// Render the diff as a TOML [patch](crate::patch), which [`Patch::parse`] reads back.
+    /// Redactions don't apply, since a patch needs the real values to be applied.
+    pub fn to_patch(&self) -> String {
+        let changes = self.changes.iter().map(|change| {
+            let mut table = Map::new();
+            let mut insert = |key: &str, val| {
+                table.insert(key.to_owned(), val);
+            };
+            let op = match change {
+                TomlChange::Added(_, new) => {
+                    insert("new", (*new).clone());
+                    "add"
+                }
+                TomlChange::Deleted(_, old) => {
+                    insert("old", (*old).clone());
+                    "remove"
+                }
+                TomlChange::Changed(_, old, new) => {
+                    insert("old", (*old).clone());
+                    insert("new", (*new).clone());
+                    "replace"
+                }
+                TomlChange::KeyStyleChanged(_, old_key) => {
+                    insert("old_key", TomlValue::String((*old_key).to_owned()));
+                    "rename"
+                }
+                TomlChange::Moved(_, old_path) => {
+                    insert("old_path", path_value(old_path));
+                    "move"
+                }
+                TomlChange::Reordered(_, permutation) => {
+                    let permutation = permutation.iter().map(|&i| TomlValue::Integer(i as i64));
+                    insert("permutation", TomlValue::Array(permutation.collect()));
+                    "reorder"
+                }
+            };
+            let annotations: Map<_, _> = self
+                .annotations(change)
+                .into_iter()
+                .map(|(key, value)| (key.to_owned(), TomlValue::String(value.to_owned())))
+                .collect();
+            if !annotations.is_empty() {
+                insert("annotations", TomlValue::Table(annotations));
+            }
+            insert("op", TomlValue::String(op.to_owned()));
+            insert("path", path_value(change.key_path()));
+            TomlValue::Table(table)
+        });
+        let mut doc = Map::new();
+        doc.insert("change".to_owned(), TomlValue::Array(changes.collect()));
+        toml::to_string(&doc).unwrap()
+    }
+}
+
+/// `key_path` as the array of keys and indices a patch writes it as
+fn path_value(key_path: &[PathSegment]) -> TomlValue {
+    let path = key_path.iter().map(|segment| match segment {
+        PathSegment::Key(key) => TomlValue::String((*key).to_owned()),
+        PathSegment::Index(i) => TomlValue::Integer(*i as i64),
+    });
+    TomlValue::Array(path.collect())
+}
+
+impl Patch {
+    pub fn parse(patch: &str) -> Result<Self, PatchError> {
+        let doc = toml::from_str(patch).map_err(PatchError::Toml)?;
+        // Check every change up front, so that applying the patch can't fail
+        changes(&doc)?;
+        Ok(Self { doc })
+    }
+
+    /// The changes in the patch, as a diff of the documents it was made from.
+    pub fn to_diff(&self) -> TomlDiff<'_> {
+        let changes = changes(&self.doc).unwrap();
+        let annotations = self.doc.get("change").and_then(TomlValue::as_array);
+        let annotations = changes
+            .iter()
+            .zip(annotations.into_iter().flatten())
+            .flat_map(|(change, table)| {
+                let key_path: Vec<_> = change
+                    .key_path()
+                    .iter()
+                    .filter_map(|segment| Some(segment.as_key()?.to_owned()))
+                    .collect();
+                let annotations = table.get("annotations").and_then(TomlValue::as_table);
+                annotations.into_iter().flatten().map(move |(key, value)| {
+                    let value = value.as_str().unwrap().to_owned();
+                    Annotation::at_key_path(key_path.clone(), key.clone(), value)
+                })
+            })
+            .collect();
+        TomlDiff {
+            annotations,
+            ..TomlDiff::from_changes(changes, 0)
+        }
+    }
+
+    /// Apply the patch to `target`, exactly as [`TomlDiff::apply`] would.
+    pub fn apply(&self, target: &mut TomlValue) {
+        self.to_diff().apply(target);
+    }
+}
+
+fn changes<'d>(doc: &'d TomlValue) -> Result<Vec<TomlChange<'d>>, PatchError> {
+    let Some(changes) = doc.get("change") else {
+        return Ok(vec![]);
+    };
+    let changes = changes.as_array().ok_or(PatchError::Change {
+        index: 0,
+        message: "`change` is not an array of tables",
+    })?;
+    changes
+        .iter()
+        .enumerate()
+        .map(|(index, change)| {
+            let error = |message| PatchError::Change { index, message };
+            let field = |key| change.get(key).ok_or(error("Missing field"));
+            let path = |val: &'d TomlValue, message| {
+                val.as_array()
+                    .ok_or(error(message))?
+                    .iter()
+                    .map(|segment| match segment {
+                        TomlValue::String(key) => Some(PathSegment::Key(key)),
+                        TomlValue::Integer(i) => usize::try_from(*i).ok().map(PathSegment::Index),
+                        _ => None,
+                    })
+                    .collect::<Option<Vec<_>>>()
+                    .ok_or(error("Invalid path segment"))
+            };
+            let key_path = path(field("path")?, "`path` is not an array")?;
+            if let Some(annotations) = change.get("annotations") {
+                let valid = annotations
+                    .as_table()
+                    .is_some_and(|table| table.values().all(TomlValue::is_str));
+                if !valid {
+                    return Err(error("Invalid `annotations`"));
+                }
+            }
+            let op = field("op")?.as_str().ok_or(error("`op` is not a string"))?;
+            Ok(match op {
+                "add" => TomlChange::Added(key_path, field("new")?),
+                "remove" => TomlChange::Deleted(key_path, field("old")?),
+                "replace" => TomlChange::Changed(key_path, field("old")?, field("new")?),
+                "rename" => {
+                    let old_key = field("old_key")?.as_str();
+                    TomlChange::KeyStyleChanged(
+                        key_path,
+                        old_key.ok_or(error("Invalid `old_key`"))?,
+                    )
+                }
+                "move" => {
+                    let old_path = path(field("old_path")?, "`old_path` is not an array")?;
+                    TomlChange::Moved(key_path, old_path)
+                }
+                "reorder" => {
+                    let permutation: Vec<usize> = field("permutation")?
+                        .as_array()
+                        .ok_or(error("Invalid `permutation`"))?
+                        .iter()
+                        .map(|i| {
+                            let i = i.as_integer().and_then(|i| usize::try_from(i).ok());
+                            i.ok_or(error("Invalid `permutation`"))
+                        })
+                        .collect::<Result<_, _>>()?;
+                    // Each index has to appear exactly once
+                    let mut sorted = permutation.clone();
+                    sorted.sort_unstable();
+                    if !sorted.into_iter().eq(0..permutation.len()) {
+                        return Err(error("`permutation` is not a permutation"));
+                    }
+                    TomlChange::Reordered(key_path, permutation)
+                }
+                _ => return Err(error("Unknown `op`")),
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,226 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::sync::Arc;
+
+/// One step of the path from the top-level table to a changed value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum PathSegment<'a> {
+    /// A key in a table
+    Key(&'a str),
+    /// An element of an array. Added elements carry their index in the new array, and deleted
+    /// elements, as well as changes inside elements, their index in the old one.
+    Index(usize),
+}
+
+impl<'a> PathSegment<'a> {
+    pub fn as_key(&self) -> Option<&'a str> {
+        match self {
+            PathSegment::Key(key) => Some(key),
+            PathSegment::Index(_) => None,
+        }
+    }
+
+    pub fn as_index(&self) -> Option<usize> {
+        match self {
+            PathSegment::Key(_) => None,
+            PathSegment::Index(i) => Some(*i),
+        }
+    }
+}
+
+impl PartialEq<&str> for PathSegment<'_> {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_key() == Some(other)
+    }
+}
+
+impl PartialEq<usize> for PathSegment<'_> {
+    fn eq(&self, other: &usize) -> bool {
+        self.as_index() == Some(*other)
+    }
+}
+
+impl fmt::Display for PathSegment<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PathSegment::Key(key) => write!(f, "{key}"),
+            PathSegment::Index(i) => write!(f, "[{i}]"),
+        }
+    }
+}
+
+/// Conversions of a key path, like [`TomlChange::key_path`](crate::TomlChange::key_path), into
+/// the conventions other tools take paths in.
+pub trait KeyPath {
+    /// The path as a JSON Pointer (RFC 6901), like `/servers/0/host`, with `~` in keys escaped
+    /// as `~0` and `/` as `~1`. The empty path is the empty pointer, to the whole document.
+    fn to_json_pointer(&self) -> String;
+
+    /// The path as TOML dotted keys, like `servers[0]."host.name"`, with any key that can't be
+    /// written bare quoted and escaped as a basic string. TOML has no syntax for array
+    /// elements, so indices are written as `[i]`, as in the diff's own output.
+    fn to_toml_dotted(&self) -> String;
+}
+
+impl KeyPath for [PathSegment<'_>] {
+    fn to_json_pointer(&self) -> String {
+        let mut s = String::new();
+        for segment in self {
+            s.push('/');
+            match segment {
+                PathSegment::Key(key) => s.push_str(&key.replace('~', "~0").replace('/', "~1")),
+                PathSegment::Index(i) => s.push_str(&i.to_string()),
+            }
+        }
+        s
+    }
+
+    fn to_toml_dotted(&self) -> String {
+        format_key_path(self)
+    }
+}
+
+/// A key path stored as a link to the path of its parent, so that the paths of the tables and
+/// arrays waiting to be diffed share their prefixes, and take memory linear in the size of the
+/// documents however deeply they nest
+#[derive(Clone, Default)]
+pub(crate) struct SharedPath<'a>(Option<Arc<PathNode<'a>>>);
+
+struct PathNode<'a> {
+    parent: SharedPath<'a>,
+    segment: PathSegment<'a>,
+    len: usize,
+}
+
+impl<'a> SharedPath<'a> {
+    /// The path of the value at `segment` inside the value at this path
+    pub(crate) fn child(&self, segment: PathSegment<'a>) -> Self {
+        Self(Some(Arc::new(PathNode {
+            parent: self.clone(),
+            segment,
+            len: self.len() + 1,
+        })))
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.0.as_ref().map_or(0, |node| node.len)
+    }
+
+    pub(crate) fn to_vec(&self) -> Vec<PathSegment<'a>> {
+        let mut key_path = Vec::with_capacity(self.len());
+        let mut path = self;
+        while let Some(node) = &path.0 {
+            key_path.push(node.segment);
+            path = &node.parent;
+        }
+        key_path.reverse();
+        key_path
+    }
+}
+
+impl Drop for SharedPath<'_> {
+    /// Drops the nodes no other path shares one at a time, since dropping each node's parent
+    /// from its own drop would overflow the stack for deep enough paths
+    fn drop(&mut self) {
+        let mut next = self.0.take();
+        while let Some(node) = next {
+            next = match Arc::try_unwrap(node) {
+                Ok(mut node) => node.parent.0.take(),
+                Err(_) => None,
+            };
+        }
+    }
+}
+
+/// The keys of `key_path`, skipping array indices. Array elements are treated as living at the
+/// key path of their array wherever paths are matched against a
+/// [`PathPattern`](crate::PathPattern).
+pub(crate) fn keys<'a>(key_path: &[PathSegment<'a>]) -> Vec<&'a str> {
+    key_path.iter().filter_map(PathSegment::as_key).collect()
+}
+
+/// Compare keys in natural order, with each run of digits compared by the number it spells, so
+/// `2` comes before `10` and `v9` before `v10`. Keys that only differ in leading zeros, like
+/// `01` and `1`, are ordered as strings, so that only equal keys compare equal.
+pub(crate) fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let digits = |s: &[u8]| s.iter().take_while(|c| c.is_ascii_digit()).count();
+    let number = |s: &[u8]| {
+        let zeros = s.iter().take_while(|&&c| c == b'0').count();
+        let s = &s[zeros..];
+        (s.len(), s.to_vec())
+    };
+    let (mut x, mut y) = (a.as_bytes(), b.as_bytes());
+    while let (Some(c), Some(d)) = (x.first(), y.first()) {
+        let ord = if c.is_ascii_digit() && d.is_ascii_digit() {
+            let (i, j) = (digits(x), digits(y));
+            let ord = number(&x[..i]).cmp(&number(&y[..j]));
+            (x, y) = (&x[i..], &y[j..]);
+            ord
+        } else {
+            (x, y) = (&x[1..], &y[1..]);
+            c.cmp(d)
+        };
+        if ord != Ordering::Equal {
+            return ord;
+        }
+    }
+    x.len().cmp(&y.len()).then_with(|| a.cmp(b))
+}
+
+/// Compare key paths segment by segment, as their `Ord` does, but with keys in
+/// [natural order](natural_cmp)
+pub(crate) fn natural_cmp_paths(a: &[PathSegment], b: &[PathSegment]) -> Ordering {
+    for (x, y) in a.iter().zip(b) {
+        let ord = match (x, y) {
+            (PathSegment::Key(x), PathSegment::Key(y)) => natural_cmp(x, y),
+            _ => x.cmp(y),
+        };
+        if ord != Ordering::Equal {
+            return ord;
+        }
+    }
+    a.len().cmp(&b.len())
+}
+
+/// Render a key path as TOML dotted keys, quoting any key that can't be written bare. Array
+/// indices are rendered as `[i]`.
+pub(crate) fn format_key_path(key_path: &[PathSegment]) -> String {
+    let mut s = String::new();
+    for segment in key_path {
+        match segment {
+            PathSegment::Key(key) => {
+                if !s.is_empty() {
+                    s.push('.');
+                }
+                s.push_str(&format_key(key));
+            }
+            PathSegment::Index(i) => s.push_str(&format!("[{i}]")),
+        }
+    }
+    s
+}
+
+/// Render a single key, quoted and escaped if it can't be written as a bare key.
+pub(crate) fn format_key(key: &str) -> String {
+    let bare = !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if bare {
+        return key.to_owned();
+    }
+    let mut s = String::from('"');
+    for c in key.chars() {
+        match c {
+            '"' => s.push_str("\\\""),
+            '\\' => s.push_str("\\\\"),
+            '\n' => s.push_str("\\n"),
+            '\t' => s.push_str("\\t"),
+            '\r' => s.push_str("\\r"),
+            c if c.is_control() => s.push_str(&format!("\\u{:04X}", c as u32)),
+            c => s.push(c),
+        }
+    }
+    s.push('"');
+    s
+}
@@ -0,0 +1,133 @@
+use crate::PathSegment;
+
+/// A glob-like pattern over key paths, written as dotted keys.
+///
+/// Each segment is matched against one key of the path. A segment of `*` matches any single key,
+/// `**` matches any number of keys (including none), and a `*` inside a segment matches any run
+/// of characters within that key, so `*.password`, `**.token` and `db.*_url` are all valid.
+///
+/// A key can be followed by array indices, like `servers[0]` or `servers[*]` for any element.
+/// Indices are only matched by [`PathPattern::selects`], since everywhere else array elements
+/// are treated as living at the key path of their array and indices in patterns are skipped.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PathPattern {
+    segments: Vec<Segment>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    /// `**`, which matches zero or more keys
+    AnyDepth,
+    /// Any other segment, possibly containing `*` wildcards
+    Key(String),
+    /// `[i]`, or `[*]` for any index
+    Index(Option<usize>),
+}
+
+impl PathPattern {
+    pub fn new(pattern: &str) -> Self {
+        let mut segments = vec![];
+        for segment in pattern.split('.') {
+            // Split off any indices at the end, leaving brackets holding anything else alone
+            let mut key = segment;
+            let mut indices = vec![];
+            while let Some((rest, index)) = key.strip_suffix(']').and_then(|k| k.rsplit_once('[')) {
+                let index = match index {
+                    "*" => None,
+                    i => match i.parse() {
+                        Ok(i) => Some(i),
+                        Err(_) => break,
+                    },
+                };
+                indices.push(Segment::Index(index));
+                key = rest;
+            }
+            match key {
+                "" if !indices.is_empty() => {}
+                "**" => segments.push(Segment::AnyDepth),
+                key => segments.push(Segment::Key(key.to_owned())),
+            }
+            segments.extend(indices.into_iter().rev());
+        }
+        Self { segments }
+    }
+
+    /// Returns `true` if the whole of `key_path` matches this pattern.
+    pub fn matches(&self, key_path: &[&str]) -> bool {
+        let key_path: Vec<_> = key_path.iter().map(|key| PathSegment::Key(key)).collect();
+        matches_segments(&self.segments, &key_path, Match::KeysOnly)
+    }
+
+    /// Returns `true` if a change at `key_path` touches a path this pattern matches, indices
+    /// included: either `key_path` matches, or it lies inside a value the pattern matches, or the
+    /// pattern matches a path inside the value at `key_path`, all of which changed along with it.
+    /// So `servers[*].port` selects a change to the port of any server, as well as the addition
+    /// of a whole server, and `database` selects a change to `database.port`.
+    pub fn selects(&self, key_path: &[PathSegment]) -> bool {
+        matches_segments(&self.segments, key_path, Match::Prefix)
+    }
+
+    /// Returns `true` if this pattern could match a path inside the table at `key_path`, or
+    /// `key_path` itself, skipping indices as [`PathPattern::matches`] does.
+    pub(crate) fn matches_within(&self, key_path: &[&str]) -> bool {
+        let key_path: Vec<_> = key_path.iter().map(|key| PathSegment::Key(key)).collect();
+        matches_segments(&self.segments, &key_path, Match::Within)
+    }
+}
+
+/// How much of a pattern a key path has to match
+#[derive(Clone, Copy, PartialEq)]
+enum Match {
+    /// The whole pattern, skipping its indices, since the path has none
+    KeysOnly,
+    /// The start of the pattern, or all of it and then some
+    Prefix,
+    /// The start of the pattern, skipping its indices
+    Within,
+}
+
+impl From<&str> for PathPattern {
+    fn from(pattern: &str) -> Self {
+        Self::new(pattern)
+    }
+}
+
+fn matches_segments(segments: &[Segment], key_path: &[PathSegment], how: Match) -> bool {
+    match (segments.split_first(), key_path.split_first()) {
+        (None, None) => true,
+        (None, Some(_)) => how == Match::Prefix,
+        (Some((Segment::Index(_), rest)), _) if how != Match::Prefix => {
+            matches_segments(rest, key_path, how)
+        }
+        (Some((Segment::AnyDepth, rest)), _) => {
+            // Either `**` matches nothing, or it swallows the next segment and tries again
+            matches_segments(rest, key_path, how)
+                || (!key_path.is_empty() && matches_segments(segments, &key_path[1..], how))
+        }
+        (Some(_), None) => how != Match::KeysOnly,
+        (Some((Segment::Key(pattern), rest)), Some((PathSegment::Key(key), key_rest))) => {
+            matches_key(pattern, key) && matches_segments(rest, key_rest, how)
+        }
+        (Some((Segment::Index(index), rest)), Some((PathSegment::Index(i), key_rest))) => {
+            index.is_none_or(|index| index == *i) && matches_segments(rest, key_rest, how)
+        }
+        (Some(_), Some(_)) => false,
+    }
+}
+
+/// Match a single key against a pattern segment, where `*` matches any run of characters.
+fn matches_key(pattern: &str, key: &str) -> bool {
+    match pattern.split_once('*') {
+        None => pattern == key,
+        Some((prefix, rest)) => {
+            let Some(key) = key.strip_prefix(prefix) else {
+                return false;
+            };
+            // Try every possible length for the run matched by this `*`
+            key.char_indices()
+                .map(|(i, _)| i)
+                .chain([key.len()])
+                .any(|i| matches_key(rest, &key[i..]))
+        }
+    }
+}
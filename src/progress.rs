@@ -0,0 +1,59 @@
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use crate::{DiffValue, ValueKind};
+
+/// A callback told how many keys and elements have been visited so far, and roughly how many
+/// will be visited in total
+pub(crate) type ProgressFn = dyn Fn(usize, usize) + Send + Sync;
+
+/// A [`ProgressFn`] that can be kept in [`DiffOptions`](crate::DiffOptions)
+#[derive(Clone)]
+pub(crate) struct ProgressCallback(pub(crate) Arc<ProgressFn>);
+
+impl fmt::Debug for ProgressCallback {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("ProgressCallback")
+    }
+}
+
+/// Counts the keys and elements a diff has visited, reporting each step to a callback.
+pub(crate) struct Progress<'o> {
+    visited: AtomicUsize,
+    total: usize,
+    callback: &'o ProgressFn,
+}
+
+impl<'o> Progress<'o> {
+    /// Prepare to report the progress of diffing `a` against `b`. The total is the number of
+    /// keys and elements in both documents, which is only an estimate, since nothing inside an
+    /// added or deleted value is visited.
+    pub(crate) fn new<V: DiffValue>(a: &V, b: &V, callback: &'o ProgressFn) -> Self {
+        Self {
+            visited: AtomicUsize::new(0),
+            total: count(a) + count(b),
+            callback,
+        }
+    }
+
+    /// Count `n` more keys or elements as visited
+    pub(crate) fn visit(&self, n: usize) {
+        let visited = self.visited.fetch_add(n, Ordering::Relaxed) + n;
+        (self.callback)(visited, self.total);
+    }
+
+    /// Report the diff as finished, which rounds the number visited up to the estimate
+    pub(crate) fn finish(&self) {
+        (self.callback)(self.total, self.total);
+    }
+}
+
+/// The number of keys and elements in `val`, at every depth
+fn count<V: DiffValue>(val: &V) -> usize {
+    match val.kind() {
+        ValueKind::Table => val.entries().map(|(_, val)| 1 + count(val)).sum(),
+        ValueKind::Array => val.elements().map(|val| 1 + count(val)).sum(),
+        ValueKind::Scalar => 0,
+    }
+}
@@ -0,0 +1,80 @@
+// Redactions only take effect when rendering
+#![cfg_attr(not(feature = "render"), allow(dead_code))]
+
+use std::borrow::Cow;
+use std::rc::Rc;
+
+use toml::Value as TomlValue;
+
+use crate::path::keys;
+use crate::{PathPattern, PathSegment};
+
+/// The text that redacted values are rendered as.
+pub const REDACTED: &str = "***";
+
+type KeyPathPredicate = Rc<dyn Fn(&[&str]) -> bool>;
+
+/// A rule deciding which key paths have their values hidden when rendering a diff.
+#[derive(Clone)]
+pub(crate) enum Redaction {
+    Pattern(PathPattern),
+    Predicate(KeyPathPredicate),
+    /// Exactly this key path, for the secrets found by
+    /// [`TomlDiff::redact_secrets`](crate::TomlDiff::redact_secrets)
+    KeyPath(Vec<String>),
+}
+
+impl Redaction {
+    fn matches(&self, key_path: &[&str]) -> bool {
+        match self {
+            Redaction::Pattern(pattern) => pattern.matches(key_path),
+            Redaction::Predicate(predicate) => predicate(key_path),
+            Redaction::KeyPath(keys) => keys.iter().eq(key_path),
+        }
+    }
+}
+
+/// Return `val` with every value whose key path matches one of `redactions` replaced by
+/// [`REDACTED`]. `key_path` is the path of `val` itself.
+pub(crate) fn redact<'a>(
+    redactions: &[Redaction],
+    key_path: &[PathSegment],
+    val: &'a TomlValue,
+) -> Cow<'a, TomlValue> {
+    if redactions.is_empty() {
+        return Cow::Borrowed(val);
+    }
+    let key_path = keys(key_path);
+    // If the value sits anywhere beneath a redacted key, the whole value is hidden
+    let redacted_prefix =
+        (1..=key_path.len()).any(|len| redactions.iter().any(|r| r.matches(&key_path[..len])));
+    if redacted_prefix {
+        return Cow::Owned(TomlValue::String(REDACTED.to_owned()));
+    }
+    let mut val = val.clone();
+    redact_children(redactions, &key_path, &mut val);
+    Cow::Owned(val)
+}
+
+fn redact_children(redactions: &[Redaction], key_path: &[&str], val: &mut TomlValue) {
+    match val {
+        TomlValue::Table(table) => {
+            for (key, val) in table.iter_mut() {
+                let mut key_path = key_path.to_vec();
+                key_path.push(key);
+                if redactions.iter().any(|r| r.matches(&key_path)) {
+                    *val = TomlValue::String(REDACTED.to_owned());
+                } else {
+                    redact_children(redactions, &key_path, val);
+                }
+            }
+        }
+        // Array elements share the key path of the array they are in
+        TomlValue::Array(array) => {
+            for elem in array.iter_mut() {
+                redact_children(redactions, key_path, elem);
+            }
+        }
+        _ => {}
+    }
+}
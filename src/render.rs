@@ -0,0 +1,428 @@
+//! The event stream the built-in renderers are written against. [`TomlDiff::render_to`] feeds a
+//! [`RenderSink`] the changes of a diff with redactions already applied, the tables they sit in,
+//! their annotations, and the changes left out by truncation or ignore rules, so that a new
+//! output format only has to write each event out.
+
+use std::convert::Infallible;
+use std::fmt;
+
+use toml::Value as TomlValue;
+
+use crate::display::StringSink;
+use crate::json::JsonlSink;
+use crate::redact::redact;
+use crate::{PathSegment, TomlChange, TomlDiff};
+
+/// An output format for a [`TomlDiff`], fed by [`TomlDiff::render_to`].
+///
+/// The `emit_*` methods receive the full key path of each change, in the order the changes are
+/// in the diff. `begin_table` and `end_table` bracket the changes made inside each table, so a
+/// table is begun again if the diff comes back to it after changes elsewhere, as can happen
+/// once a diff is [sorted](TomlDiff::sort_by). The top-level table is never begun or ended,
+/// and the changed elements of an array are emitted as part of the table that holds the array.
+/// The methods without a default have to be written by every format, since leaving one out
+/// would drop changes from its output.
+#[allow(unused_variables)]
+pub trait RenderSink {
+    type Error;
+
+    fn begin_table(&mut self, key_path: &[PathSegment]) -> Result<(), Self::Error> {
+        Ok(())
+    }
+    fn end_table(&mut self, key_path: &[PathSegment]) -> Result<(), Self::Error> {
+        Ok(())
+    }
+    fn emit_added(&mut self, key_path: &[PathSegment], val: &TomlValue) -> Result<(), Self::Error>;
+    fn emit_deleted(
+        &mut self,
+        key_path: &[PathSegment],
+        val: &TomlValue,
+    ) -> Result<(), Self::Error>;
+    fn emit_changed(
+        &mut self,
+        key_path: &[PathSegment],
+        old: &TomlValue,
+        new: &TomlValue,
+    ) -> Result<(), Self::Error>;
+    /// The key at `key_path` was spelled `old_key` in the old document
+    fn emit_key_style_changed(
+        &mut self,
+        key_path: &[PathSegment],
+        old_key: &str,
+    ) -> Result<(), Self::Error>;
+    /// The key at `key_path` was at `old_path` in the old document
+    fn emit_moved(
+        &mut self,
+        key_path: &[PathSegment],
+        old_path: &[PathSegment],
+    ) -> Result<(), Self::Error>;
+    /// Element `i` of the array at `key_path` was element `permutation[i]` of the old array
+    fn emit_reordered(
+        &mut self,
+        key_path: &[PathSegment],
+        permutation: &[usize],
+    ) -> Result<(), Self::Error>;
+    /// The [annotations](TomlDiff::annotate) of the change just emitted, if it has any
+    fn emit_annotations(
+        &mut self,
+        key_path: &[PathSegment],
+        annotations: &[(&str, &str)],
+    ) -> Result<(), Self::Error> {
+        Ok(())
+    }
+    /// `count` changes under the top-level table or array at `key_path`, or under the document
+    /// itself if `key_path` is empty, were left out for the
+    /// [memory budget](crate::DiffOptions::memory_budget)
+    fn emit_summary(&mut self, key_path: &[PathSegment], count: usize) -> Result<(), Self::Error> {
+        Ok(())
+    }
+    /// `count` more changes were left out, beyond those counted by `emit_summary`. Called last,
+    /// and only if any were.
+    fn emit_truncated(&mut self, count: usize) -> Result<(), Self::Error> {
+        Ok(())
+    }
+    /// `changes` changes were left out of the diff by `rules` ignore rules, as counted with
+    /// [`DiffOptions::record_suppressed`](crate::DiffOptions::record_suppressed). Called last,
+    /// and only if any were.
+    fn emit_suppressed(&mut self, changes: usize, rules: usize) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+impl<S: RenderSink + ?Sized> RenderSink for &mut S {
+    type Error = S::Error;
+
+    fn begin_table(&mut self, key_path: &[PathSegment]) -> Result<(), S::Error> {
+        (**self).begin_table(key_path)
+    }
+    fn end_table(&mut self, key_path: &[PathSegment]) -> Result<(), S::Error> {
+        (**self).end_table(key_path)
+    }
+    fn emit_added(&mut self, key_path: &[PathSegment], val: &TomlValue) -> Result<(), S::Error> {
+        (**self).emit_added(key_path, val)
+    }
+    fn emit_deleted(&mut self, key_path: &[PathSegment], val: &TomlValue) -> Result<(), S::Error> {
+        (**self).emit_deleted(key_path, val)
+    }
+    fn emit_changed(
+        &mut self,
+        key_path: &[PathSegment],
+        old: &TomlValue,
+        new: &TomlValue,
+    ) -> Result<(), S::Error> {
+        (**self).emit_changed(key_path, old, new)
+    }
+    fn emit_key_style_changed(
+        &mut self,
+        key_path: &[PathSegment],
+        old_key: &str,
+    ) -> Result<(), S::Error> {
+        (**self).emit_key_style_changed(key_path, old_key)
+    }
+    fn emit_moved(
+        &mut self,
+        key_path: &[PathSegment],
+        old_path: &[PathSegment],
+    ) -> Result<(), S::Error> {
+        (**self).emit_moved(key_path, old_path)
+    }
+    fn emit_reordered(
+        &mut self,
+        key_path: &[PathSegment],
+        permutation: &[usize],
+    ) -> Result<(), S::Error> {
+        (**self).emit_reordered(key_path, permutation)
+    }
+    fn emit_annotations(
+        &mut self,
+        key_path: &[PathSegment],
+        annotations: &[(&str, &str)],
+    ) -> Result<(), S::Error> {
+        (**self).emit_annotations(key_path, annotations)
+    }
+    fn emit_summary(&mut self, key_path: &[PathSegment], count: usize) -> Result<(), S::Error> {
+        (**self).emit_summary(key_path, count)
+    }
+    fn emit_truncated(&mut self, count: usize) -> Result<(), S::Error> {
+        (**self).emit_truncated(count)
+    }
+    fn emit_suppressed(&mut self, changes: usize, rules: usize) -> Result<(), S::Error> {
+        (**self).emit_suppressed(changes, rules)
+    }
+}
+
+/// Feeds each sink every event in turn, stopping at the first error, so that several formats
+/// can be rendered from one walk of a diff.
+impl<S: RenderSink> RenderSink for [S] {
+    type Error = S::Error;
+
+    fn begin_table(&mut self, key_path: &[PathSegment]) -> Result<(), S::Error> {
+        self.iter_mut()
+            .try_for_each(|sink| sink.begin_table(key_path))
+    }
+    fn end_table(&mut self, key_path: &[PathSegment]) -> Result<(), S::Error> {
+        self.iter_mut()
+            .try_for_each(|sink| sink.end_table(key_path))
+    }
+    fn emit_added(&mut self, key_path: &[PathSegment], val: &TomlValue) -> Result<(), S::Error> {
+        self.iter_mut()
+            .try_for_each(|sink| sink.emit_added(key_path, val))
+    }
+    fn emit_deleted(&mut self, key_path: &[PathSegment], val: &TomlValue) -> Result<(), S::Error> {
+        self.iter_mut()
+            .try_for_each(|sink| sink.emit_deleted(key_path, val))
+    }
+    fn emit_changed(
+        &mut self,
+        key_path: &[PathSegment],
+        old: &TomlValue,
+        new: &TomlValue,
+    ) -> Result<(), S::Error> {
+        self.iter_mut()
+            .try_for_each(|sink| sink.emit_changed(key_path, old, new))
+    }
+    fn emit_key_style_changed(
+        &mut self,
+        key_path: &[PathSegment],
+        old_key: &str,
+    ) -> Result<(), S::Error> {
+        self.iter_mut()
+            .try_for_each(|sink| sink.emit_key_style_changed(key_path, old_key))
+    }
+    fn emit_moved(
+        &mut self,
+        key_path: &[PathSegment],
+        old_path: &[PathSegment],
+    ) -> Result<(), S::Error> {
+        self.iter_mut()
+            .try_for_each(|sink| sink.emit_moved(key_path, old_path))
+    }
+    fn emit_reordered(
+        &mut self,
+        key_path: &[PathSegment],
+        permutation: &[usize],
+    ) -> Result<(), S::Error> {
+        self.iter_mut()
+            .try_for_each(|sink| sink.emit_reordered(key_path, permutation))
+    }
+    fn emit_annotations(
+        &mut self,
+        key_path: &[PathSegment],
+        annotations: &[(&str, &str)],
+    ) -> Result<(), S::Error> {
+        self.iter_mut()
+            .try_for_each(|sink| sink.emit_annotations(key_path, annotations))
+    }
+    fn emit_summary(&mut self, key_path: &[PathSegment], count: usize) -> Result<(), S::Error> {
+        self.iter_mut()
+            .try_for_each(|sink| sink.emit_summary(key_path, count))
+    }
+    fn emit_truncated(&mut self, count: usize) -> Result<(), S::Error> {
+        self.iter_mut()
+            .try_for_each(|sink| sink.emit_truncated(count))
+    }
+    fn emit_suppressed(&mut self, changes: usize, rules: usize) -> Result<(), S::Error> {
+        self.iter_mut()
+            .try_for_each(|sink| sink.emit_suppressed(changes, rules))
+    }
+}
+
+/// A format [`TomlDiff::render_all`] renders a diff in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Format {
+    /// The [`Display`](fmt::Display) of the diff, with colors if the `color` feature is enabled
+    Color,
+    /// The diff formatted with `{:#}`, without colors
+    Text,
+    /// The text format in a fenced `diff` code block, for a Markdown comment
+    Markdown,
+    /// One JSON object per change, as from [`TomlDiff::to_jsonl`]
+    Json,
+}
+
+/// The sink rendering one of the formats of [`TomlDiff::render_all`]
+enum FormatSink<'s> {
+    Text(StringSink<'s>),
+    Json(JsonlSink),
+}
+
+impl FormatSink<'_> {
+    /// Feed an event to whichever sink this is, with `text` or `json`
+    fn each(
+        &mut self,
+        text: impl FnOnce(&mut StringSink) -> fmt::Result,
+        json: impl FnOnce(&mut JsonlSink) -> Result<(), Infallible>,
+    ) -> fmt::Result {
+        match self {
+            FormatSink::Text(sink) => text(sink),
+            FormatSink::Json(sink) => {
+                let Ok(()) = json(sink);
+                Ok(())
+            }
+        }
+    }
+}
+
+impl RenderSink for FormatSink<'_> {
+    type Error = fmt::Error;
+
+    fn emit_added(&mut self, key_path: &[PathSegment], val: &TomlValue) -> fmt::Result {
+        self.each(
+            |sink| sink.emit_added(key_path, val),
+            |sink| sink.emit_added(key_path, val),
+        )
+    }
+    fn emit_deleted(&mut self, key_path: &[PathSegment], val: &TomlValue) -> fmt::Result {
+        self.each(
+            |sink| sink.emit_deleted(key_path, val),
+            |sink| sink.emit_deleted(key_path, val),
+        )
+    }
+    fn emit_changed(
+        &mut self,
+        key_path: &[PathSegment],
+        old: &TomlValue,
+        new: &TomlValue,
+    ) -> fmt::Result {
+        self.each(
+            |sink| sink.emit_changed(key_path, old, new),
+            |sink| sink.emit_changed(key_path, old, new),
+        )
+    }
+    fn emit_key_style_changed(&mut self, key_path: &[PathSegment], old_key: &str) -> fmt::Result {
+        self.each(
+            |sink| sink.emit_key_style_changed(key_path, old_key),
+            |sink| sink.emit_key_style_changed(key_path, old_key),
+        )
+    }
+    fn emit_moved(&mut self, key_path: &[PathSegment], old_path: &[PathSegment]) -> fmt::Result {
+        self.each(
+            |sink| sink.emit_moved(key_path, old_path),
+            |sink| sink.emit_moved(key_path, old_path),
+        )
+    }
+    fn emit_reordered(&mut self, key_path: &[PathSegment], permutation: &[usize]) -> fmt::Result {
+        self.each(
+            |sink| sink.emit_reordered(key_path, permutation),
+            |sink| sink.emit_reordered(key_path, permutation),
+        )
+    }
+    fn emit_annotations(
+        &mut self,
+        key_path: &[PathSegment],
+        annotations: &[(&str, &str)],
+    ) -> fmt::Result {
+        self.each(
+            |sink| sink.emit_annotations(key_path, annotations),
+            |sink| sink.emit_annotations(key_path, annotations),
+        )
+    }
+    fn emit_summary(&mut self, key_path: &[PathSegment], count: usize) -> fmt::Result {
+        self.each(
+            |sink| sink.emit_summary(key_path, count),
+            |sink| sink.emit_summary(key_path, count),
+        )
+    }
+    fn emit_truncated(&mut self, count: usize) -> fmt::Result {
+        self.each(
+            |sink| sink.emit_truncated(count),
+            |sink| sink.emit_truncated(count),
+        )
+    }
+    fn emit_suppressed(&mut self, changes: usize, rules: usize) -> fmt::Result {
+        self.each(
+            |sink| sink.emit_suppressed(changes, rules),
+            |sink| sink.emit_suppressed(changes, rules),
+        )
+    }
+}
+
+impl<'a> TomlDiff<'a> {
+    /// Render the diff in each of `formats`, in the same order, walking its changes only once
+    /// however many formats there are, so that a large diff can be shown on the console, stored
+    /// as JSON and posted as a comment without being rendered from scratch for each. Each
+    /// rendering is the same as the diff would have on its own in that format.
+    pub fn render_all(&self, formats: &[Format]) -> Vec<String> {
+        let mut sinks: Vec<_> = formats
+            .iter()
+            .map(|format| match format {
+                Format::Color => FormatSink::Text(StringSink::new(self, true)),
+                Format::Text | Format::Markdown => FormatSink::Text(StringSink::new(self, false)),
+                Format::Json => FormatSink::Json(JsonlSink(String::new())),
+            })
+            .collect();
+        // Rendering to strings can only fail if a value can't be written as TOML, which the
+        // display of the diff would panic on too
+        self.render_to(&mut sinks[..]).unwrap();
+        formats
+            .iter()
+            .zip(sinks)
+            .map(|(format, sink)| match (format, sink) {
+                (Format::Markdown, FormatSink::Text(sink)) => format!("```diff\n{}```\n", sink.out),
+                (_, FormatSink::Text(sink)) => sink.out,
+                (_, FormatSink::Json(sink)) => sink.0,
+            })
+            .collect()
+    }
+
+    /// Feed the diff to `sink`, stopping at the first error it returns. Values are passed with
+    /// [redactions](TomlDiff::redact) applied.
+    pub fn render_to<S: RenderSink>(&self, mut sink: S) -> Result<(), S::Error> {
+        // The path of the innermost table currently begun
+        let mut open: &[PathSegment<'a>] = &[];
+        for change in &self.changes {
+            let key_path = change.key_path();
+            // Array elements belong to the table that holds their array
+            let table_len = key_path
+                .iter()
+                .rposition(|segment| segment.as_key().is_some())
+                .unwrap_or(0);
+            let table = &key_path[..table_len];
+            let common = open.iter().zip(table).take_while(|(x, y)| x == y).count();
+            for len in (common + 1..=open.len()).rev() {
+                sink.end_table(&open[..len])?;
+            }
+            for len in common + 1..=table.len() {
+                sink.begin_table(&table[..len])?;
+            }
+            open = table;
+
+            let redact = |val| redact(&self.redactions, key_path, val);
+            match change {
+                TomlChange::Added(_, val) => sink.emit_added(key_path, &redact(val))?,
+                TomlChange::Deleted(_, val) => sink.emit_deleted(key_path, &redact(val))?,
+                TomlChange::Changed(_, old, new) => {
+                    sink.emit_changed(key_path, &redact(old), &redact(new))?
+                }
+                TomlChange::KeyStyleChanged(_, old_key) => {
+                    sink.emit_key_style_changed(key_path, old_key)?
+                }
+                TomlChange::Moved(_, old_path) => sink.emit_moved(key_path, old_path)?,
+                TomlChange::Reordered(_, permutation) => {
+                    sink.emit_reordered(key_path, permutation)?
+                }
+            }
+            let annotations = self.annotations(change);
+            if !annotations.is_empty() {
+                sink.emit_annotations(key_path, &annotations)?;
+            }
+        }
+        for len in (1..=open.len()).rev() {
+            sink.end_table(&open[..len])?;
+        }
+
+        let mut truncated = self.truncated;
+        for (key_path, count) in &self.summaries {
+            sink.emit_summary(key_path, *count)?;
+            truncated -= count;
+        }
+        if truncated > 0 {
+            sink.emit_truncated(truncated)?;
+        }
+        let suppressed: usize = self.suppressed.iter().map(|(_, n)| n).sum();
+        if suppressed > 0 {
+            sink.emit_suppressed(suppressed, self.suppressed.len())?;
+        }
+        Ok(())
+    }
+}
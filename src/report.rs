@@ -0,0 +1,221 @@
+use std::borrow::Cow;
+use std::fmt::Write;
+
+use toml::{map::Map, Value as TomlValue};
+
+use crate::json::write_str;
+use crate::path::{format_key, format_key_path};
+use crate::redact::redact;
+use crate::value::{maps_are_sorted, raw};
+use crate::{PathSegment, TomlChange, TomlDiff};
+
+impl<'a> TomlDiff<'a> {
+    /// Render everything in both documents as a single TOML document, with a comment beside
+    /// each change, like `port = 8081  # CHANGED: was 8080` or `[cache]  # REMOVED in new
+    /// version`. `new` is the newer of the two documents the diff was made from. Deleted keys
+    /// are kept with the values they had, and deleted array elements follow the elements of the
+    /// new array, so the report reads like any other TOML document and opens in any editor that
+    /// understands TOML.
+    pub fn to_annotated_toml(&self, new: &TomlValue) -> String {
+        let new = redact(&self.redactions, &[], new);
+        let mut s = String::new();
+        match &*new {
+            TomlValue::Table(table) => self.write_table(&mut s, &[], table, None),
+            new => writeln!(s, "{}{}", inline(new), self.comment(&[])).unwrap(),
+        }
+        s
+    }
+
+    /// Write the table at `key_path`, with everything deleted from it put back in. The keys
+    /// holding plain values come first, under `header` if there is one, then each nested table
+    /// and array of tables under a header of its own. The header of a table holding nothing but
+    /// tables is left out, unless the flag alongside `header` is set.
+    fn write_table(
+        &self,
+        s: &mut String,
+        key_path: &[PathSegment],
+        table: &Map<String, TomlValue>,
+        header: Option<(String, bool)>,
+    ) {
+        let mut entries: Vec<(&str, Cow<TomlValue>)> = table
+            .iter()
+            .map(|(key, val)| (key.as_str(), Cow::Borrowed(val)))
+            .collect();
+        for change in &self.changes {
+            if let TomlChange::Deleted(path, old) = change {
+                if let Some((PathSegment::Key(key), parent)) = path.split_last() {
+                    if parent == key_path {
+                        entries.push((key, redact(&self.redactions, path, old)));
+                    }
+                }
+            }
+        }
+        if maps_are_sorted() {
+            entries.sort_by_key(|(key, _)| *key);
+        }
+
+        let mut plain = vec![];
+        let mut nested = vec![];
+        for (key, val) in &entries {
+            let mut path = key_path.to_vec();
+            path.push(PathSegment::Key(key));
+            let elements = match &**val {
+                TomlValue::Array(array) => self.elements(&path, array),
+                _ => vec![],
+            };
+            let is_array_of_tables =
+                !elements.is_empty() && elements.iter().all(|(elem, _)| elem.is_table());
+            if val.is_table() || is_array_of_tables {
+                nested.push((path, val, elements));
+            } else {
+                plain.push((path, val, elements));
+            }
+        }
+
+        if let Some((header, keep_header)) = header {
+            if keep_header || !plain.is_empty() || nested.is_empty() {
+                if !s.is_empty() {
+                    s.push('\n');
+                }
+                s.push_str(&header);
+            }
+        }
+        for (path, val, elements) in plain {
+            let key = format_key(path.last().and_then(PathSegment::as_key).unwrap());
+            let comment = self.comment(&path);
+            if elements.iter().any(|(_, comment)| !comment.is_empty()) {
+                // Put each element on a line of its own, so that it has room for a comment
+                writeln!(s, "{key} = [{comment}").unwrap();
+                for (elem, comment) in elements {
+                    writeln!(s, "    {},{comment}", inline(&elem)).unwrap();
+                }
+                s.push_str("]\n");
+            } else {
+                writeln!(s, "{key} = {}{comment}", inline(val)).unwrap();
+            }
+        }
+        for (path, val, elements) in nested {
+            // Headers name the keys of a table, since any array in the way is an array of
+            // tables, whose last element the header belongs to
+            let keys: Vec<_> = path
+                .iter()
+                .copied()
+                .filter(|segment| segment.as_key().is_some())
+                .collect();
+            let name = format_key_path(&keys);
+            match &**val {
+                TomlValue::Table(table) => {
+                    let comment = self.comment(&path);
+                    let header = format!("[{name}]{comment}\n");
+                    self.write_table(s, &path, table, Some((header, !comment.is_empty())));
+                }
+                _ => {
+                    let comment = self.comment(&path);
+                    if !comment.is_empty() {
+                        writeln!(s, "\n{}", comment.trim_start()).unwrap();
+                    }
+                    for (i, (elem, comment)) in elements.iter().enumerate() {
+                        let mut path = path.clone();
+                        path.push(PathSegment::Index(i));
+                        let header = format!("[[{name}]]{comment}\n");
+                        let table = elem.as_table().unwrap();
+                        self.write_table(s, &path, table, Some((header, true)));
+                    }
+                }
+            }
+        }
+    }
+
+    /// The elements of the array at `key_path` in both documents, each with the comment marking
+    /// it: those of the new array, then those deleted from the old one.
+    fn elements<'v>(
+        &self,
+        key_path: &[PathSegment],
+        array: &'v [TomlValue],
+    ) -> Vec<(Cow<'v, TomlValue>, String)>
+    where
+        'a: 'v,
+    {
+        let mut elements: Vec<_> = array
+            .iter()
+            .enumerate()
+            .map(|(i, elem)| {
+                let added = self.changes.iter().any(|change| {
+                    matches!(change, TomlChange::Added(path, _)
+                        if path.split_last() == Some((&PathSegment::Index(i), key_path)))
+                });
+                let comment = if added {
+                    "  # ADDED in new version"
+                } else {
+                    ""
+                };
+                (Cow::Borrowed(elem), comment.to_owned())
+            })
+            .collect();
+        for change in &self.changes {
+            if let TomlChange::Deleted(path, old) = change {
+                if let Some((PathSegment::Index(_), parent)) = path.split_last() {
+                    if parent == key_path {
+                        let old = redact(&self.redactions, path, old);
+                        elements.push((old, "  # REMOVED in new version".to_owned()));
+                    }
+                }
+            }
+        }
+        elements
+    }
+
+    /// The comment marking the changes at exactly `key_path`, or nothing if there are none
+    fn comment(&self, key_path: &[PathSegment]) -> String {
+        let marks: Vec<_> = self
+            .changes
+            .iter()
+            .filter(|change| change.key_path() == key_path)
+            .map(|change| match change {
+                TomlChange::Added(..) => "ADDED in new version".to_owned(),
+                TomlChange::Deleted(..) => "REMOVED in new version".to_owned(),
+                TomlChange::Changed(_, old, _) => {
+                    let old = redact(&self.redactions, key_path, old);
+                    format!("CHANGED: was {}", inline(&old))
+                }
+                TomlChange::KeyStyleChanged(_, old_key) => {
+                    format!("RENAMED: was {}", format_key(old_key))
+                }
+                TomlChange::Moved(_, old_path) => {
+                    format!("MOVED: was {}", format_key_path(old_path))
+                }
+                TomlChange::Reordered(..) => "REORDERED in new version".to_owned(),
+            })
+            .collect();
+        if marks.is_empty() {
+            String::new()
+        } else {
+            format!("  # {}", marks.join("; "))
+        }
+    }
+}
+
+/// Render `val` as TOML on a single line, with tables as inline tables
+pub(crate) fn inline(val: &TomlValue) -> String {
+    match val {
+        TomlValue::String(string) => {
+            // Every escape in a JSON string is also valid in a TOML basic string
+            let mut s = String::new();
+            write_str(&mut s, string);
+            s
+        }
+        TomlValue::Array(array) => {
+            let elems: Vec<_> = array.iter().map(inline).collect();
+            format!("[{}]", elems.join(", "))
+        }
+        TomlValue::Table(table) if table.is_empty() => "{}".to_owned(),
+        TomlValue::Table(table) => {
+            let entries: Vec<_> = table
+                .iter()
+                .map(|(key, val)| format!("{} = {}", format_key(key), inline(val)))
+                .collect();
+            format!("{{ {} }}", entries.join(", "))
+        }
+        val => raw(val),
+    }
+}
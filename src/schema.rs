@@ -0,0 +1,143 @@
+//! Checking diffs against a JSON Schema, enabled with the `schema` feature.
+//!
+//! Only the parts of JSON Schema that describe a document's shape are understood: `type`,
+//! `properties`, `required`, `additionalProperties` and `items`. Datetimes are treated as
+//! strings, as they would be in JSON.
+
+use std::fmt;
+
+use toml::Value as TomlValue;
+
+use crate::path::format_key_path;
+use crate::{parse_json, JsonError, PathSegment, TomlChange, TomlDiff};
+
+/// A JSON Schema document describing the TOML documents being diffed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Schema {
+    root: TomlValue,
+}
+
+/// What a [`Schema`] has to say about a change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SchemaAnnotation {
+    /// A key the schema requires was deleted
+    RemovedRequiredKey,
+    /// A key the schema allows to be left out was deleted
+    RemovedOptionalKey,
+    /// A key the schema doesn't describe was added
+    UnknownKey,
+    /// A value was added or changed to a type the schema doesn't allow
+    WrongType,
+}
+
+/// The changes of a diff that a [`Schema`] had something to say about, in diff order.
+#[derive(Debug)]
+pub struct SchemaReport<'d, 'a> {
+    pub entries: Vec<(&'d TomlChange<'a>, SchemaAnnotation)>,
+}
+
+impl Schema {
+    /// Use `root`, a JSON Schema document already parsed into a TOML value, as a schema.
+    pub fn new(root: TomlValue) -> Self {
+        Self { root }
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, JsonError> {
+        parse_json(json).map(Self::new)
+    }
+
+    /// The schema of the value at `key_path`, if the schema describes it.
+    fn at(&self, key_path: &[PathSegment]) -> Option<&TomlValue> {
+        key_path
+            .iter()
+            .try_fold(&self.root, |schema, segment| match segment {
+                PathSegment::Key(key) => schema
+                    .get("properties")
+                    .and_then(|properties| properties.get(key))
+                    .or_else(|| schema.get("additionalProperties").filter(|s| s.is_table())),
+                PathSegment::Index(_) => schema.get("items"),
+            })
+    }
+
+    fn annotate(&self, change: &TomlChange) -> Option<SchemaAnnotation> {
+        let key_path = change.key_path();
+        let (last, parent_path) = key_path.split_last()?;
+        let parent = self.at(parent_path)?;
+        match (change, last) {
+            (TomlChange::Deleted(..), PathSegment::Key(key)) => {
+                let required = parent
+                    .get("required")
+                    .and_then(TomlValue::as_array)
+                    .is_some_and(|required| required.iter().any(|k| k.as_str() == Some(key)));
+                Some(if required {
+                    SchemaAnnotation::RemovedRequiredKey
+                } else {
+                    SchemaAnnotation::RemovedOptionalKey
+                })
+            }
+            (TomlChange::Added(_, val) | TomlChange::Changed(_, _, val), _) => {
+                let Some(schema) = self.at(key_path) else {
+                    // Anything that isn't described is unknown, unless the schema leaves it open
+                    let closed = parent.get("properties").is_some()
+                        || parent.get("additionalProperties") == Some(&TomlValue::Boolean(false));
+                    let is_key = matches!(last, PathSegment::Key(_));
+                    return (closed && is_key).then_some(SchemaAnnotation::UnknownKey);
+                };
+                (!type_matches(schema, val)).then_some(SchemaAnnotation::WrongType)
+            }
+            _ => None,
+        }
+    }
+}
+
+fn type_matches(schema: &TomlValue, val: &TomlValue) -> bool {
+    let matches = |ty: &str| {
+        matches!(
+            (ty, val),
+            ("string", TomlValue::String(_) | TomlValue::Datetime(_))
+                | ("integer", TomlValue::Integer(_))
+                | ("number", TomlValue::Integer(_) | TomlValue::Float(_))
+                | ("boolean", TomlValue::Boolean(_))
+                | ("array", TomlValue::Array(_))
+                | ("object", TomlValue::Table(_))
+        )
+    };
+    match schema.get("type") {
+        Some(TomlValue::String(ty)) => matches(ty),
+        Some(TomlValue::Array(types)) => types.iter().filter_map(TomlValue::as_str).any(matches),
+        _ => true,
+    }
+}
+
+impl<'a> TomlDiff<'a> {
+    /// Check every change against `schema`, reporting deleted keys as required or optional, and
+    /// flagging added keys the schema doesn't know about and values of the wrong type.
+    pub fn check_schema<'d>(&'d self, schema: &Schema) -> SchemaReport<'d, 'a> {
+        let entries = self
+            .changes
+            .iter()
+            .filter_map(|change| Some((change, schema.annotate(change)?)))
+            .collect();
+        SchemaReport { entries }
+    }
+}
+
+impl fmt::Display for SchemaAnnotation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            SchemaAnnotation::RemovedRequiredKey => "removed required key",
+            SchemaAnnotation::RemovedOptionalKey => "removed optional key",
+            SchemaAnnotation::UnknownKey => "added unknown key",
+            SchemaAnnotation::WrongType => "value has the wrong type",
+        })
+    }
+}
+
+impl fmt::Display for SchemaReport<'_, '_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (change, annotation) in &self.entries {
+            writeln!(f, "{}: {annotation}", format_key_path(change.key_path()))?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,59 @@
+use serde::Serialize;
+use toml::Value as TomlValue;
+
+use crate::{DiffOptions, TomlDiff};
+
+/// Two values serialized as TOML documents by [`diff_serialize`]. A diff borrows the documents
+/// it was made from, so they're kept here for as long as the diff is needed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Serialized {
+    /// The newer of the two documents, the `a` of [`TomlDiff::diff`]
+    pub a: TomlValue,
+    /// The older of the two documents, the `b` of [`TomlDiff::diff`]
+    pub b: TomlValue,
+}
+
+impl Serialized {
+    pub fn diff(&self) -> TomlDiff<'_> {
+        TomlDiff::diff(&self.a, &self.b)
+    }
+
+    pub fn diff_with_options(&self, options: &DiffOptions) -> TomlDiff<'_> {
+        TomlDiff::diff_with_options(&self.a, &self.b, options)
+    }
+}
+
+/// Serialize `a` and `b` as TOML documents, ready to be diffed like the `a` and `b` of
+/// [`TomlDiff::diff`], so that an application can diff its configuration structs without
+/// converting them itself:
+///
+/// ```
+/// # use std::collections::BTreeMap;
+/// let old = BTreeMap::from([("port", 80)]);
+/// let new = BTreeMap::from([("port", 8080)]);
+/// let serialized = toml_diff::diff_serialize(&new, &old).unwrap();
+/// assert_eq!(serialized.diff().changes.len(), 1);
+/// ```
+///
+/// Fails if either value can't be serialized as TOML, or isn't serialized as a table, as values
+/// at the top level of a document must be.
+pub fn diff_serialize<A, B>(a: &A, b: &B) -> Result<Serialized, toml::ser::Error>
+where
+    A: Serialize + ?Sized,
+    B: Serialize + ?Sized,
+{
+    Ok(Serialized {
+        a: document(a)?,
+        b: document(b)?,
+    })
+}
+
+fn document<T: Serialize + ?Sized>(val: &T) -> Result<TomlValue, toml::ser::Error> {
+    match TomlValue::try_from(val)? {
+        table @ TomlValue::Table(_) => Ok(table),
+        val => Err(toml::ser::Error::Custom(format!(
+            "Expected a table at the top level, found {}",
+            val.type_str()
+        ))),
+    }
+}
@@ -0,0 +1,55 @@
+use crate::TomlChange;
+
+/// `lcs[i][j]` is the length of the longest common subsequence of `old[i..]` and `new[j..]`
+pub(crate) fn lcs_table<T: PartialEq>(old: &[T], new: &[T]) -> Vec<Vec<usize>> {
+    let mut lcs = vec![vec![0; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+    lcs
+}
+
+/// How alike two strings are, from 0 when they have no characters in common to 1 when they're
+/// equal: twice the length of their longest common subsequence of characters, over the number
+/// of characters in both.
+pub(crate) fn string_similarity(old: &str, new: &str) -> f64 {
+    let (old, new): (Vec<_>, Vec<_>) = (old.chars().collect(), new.chars().collect());
+    if old.is_empty() && new.is_empty() {
+        return 1.0;
+    }
+    // The common ends are part of the subsequence, so the table only needs what's between them
+    let prefix = old.iter().zip(&new).take_while(|(x, y)| x == y).count();
+    let (old_rest, new_rest) = (&old[prefix..], &new[prefix..]);
+    let suffix = old_rest
+        .iter()
+        .rev()
+        .zip(new_rest.iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    let lcs = lcs_table(
+        &old_rest[..old_rest.len() - suffix],
+        &new_rest[..new_rest.len() - suffix],
+    );
+    let common = prefix + suffix + lcs[0][0];
+    2.0 * common as f64 / (old.len() + new.len()) as f64
+}
+
+impl<'a> TomlChange<'a> {
+    /// For a changed string, how alike its old and new values are, from 0 when they have no
+    /// characters in common to 1. A renderer can show a change to a similar string as an edit
+    /// within it, and a change to an unrelated one as one value replacing another.
+    pub fn similarity(&self) -> Option<f64> {
+        match self {
+            TomlChange::Changed(_, old, new) => {
+                Some(string_similarity(old.as_str()?, new.as_str()?))
+            }
+            _ => None,
+        }
+    }
+}
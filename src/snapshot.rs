@@ -0,0 +1,112 @@
+use std::fmt::Write;
+
+use toml::Value as TomlValue;
+
+use crate::json::write_str;
+use crate::path::{format_key, format_key_path};
+use crate::redact::redact;
+use crate::{TomlChange, TomlDiff};
+
+/// The version of the format [`TomlDiff::to_canonical_string`] renders, named on its first line.
+/// Any change to the format, however small, comes with a new version.
+pub const CANONICAL_FORMAT_VERSION: u32 = 1;
+
+impl<'a> TomlDiff<'a> {
+    /// Render the diff in a stable format for snapshot tests, which doesn't depend on the order
+    /// of keys in either document or change along with the other renderers. The first line is
+    /// `# toml-diff canonical v1`, naming the [version](CANONICAL_FORMAT_VERSION) of the format,
+    /// followed by one line per change, sorted by key path:
+    ///
+    /// ```text
+    /// + servers[0].host = "db2"
+    /// - cache = { size = 64 }
+    /// ~ port = 8080 -> 8081
+    /// ~ max-connections renamed from max_connections
+    /// ~ server.port moved from http_port
+    /// ~ tags reordered [1, 0]
+    /// ```
+    ///
+    /// Values are written on one line, with the keys of tables sorted, strings escaped as in
+    /// JSON and floats always holding a `.` or exponent. If the diff was truncated, the last line
+    /// is `truncated = N`. Redactions apply, and annotations are left out.
+    pub fn to_canonical_string(&self) -> String {
+        let mut lines: Vec<_> = self
+            .changes
+            .iter()
+            .map(|change| {
+                let key_path = change.key_path();
+                let path = format_key_path(key_path);
+                let value = |val| canonical_value(&redact(&self.redactions, key_path, val));
+                let line = match change {
+                    TomlChange::Added(_, new) => format!("+ {path} = {}", value(new)),
+                    TomlChange::Deleted(_, old) => format!("- {path} = {}", value(old)),
+                    TomlChange::Changed(_, old, new) => {
+                        format!("~ {path} = {} -> {}", value(old), value(new))
+                    }
+                    TomlChange::KeyStyleChanged(_, old_key) => {
+                        format!("~ {path} renamed from {}", format_key(old_key))
+                    }
+                    TomlChange::Moved(_, old_path) => {
+                        format!("~ {path} moved from {}", format_key_path(old_path))
+                    }
+                    TomlChange::Reordered(_, permutation) => {
+                        format!("~ {path} reordered {permutation:?}")
+                    }
+                };
+                (key_path, line)
+            })
+            .collect();
+        lines.sort();
+        let mut s = format!("# toml-diff canonical v{CANONICAL_FORMAT_VERSION}\n");
+        for (_, line) in lines {
+            writeln!(s, "{line}").unwrap();
+        }
+        if self.truncated > 0 {
+            writeln!(s, "truncated = {}", self.truncated).unwrap();
+        }
+        s
+    }
+}
+
+/// Render `val` on one line, without relying on how `toml` formats values
+fn canonical_value(val: &TomlValue) -> String {
+    match val {
+        TomlValue::String(string) => {
+            let mut s = String::new();
+            write_str(&mut s, string);
+            s
+        }
+        TomlValue::Integer(i) => i.to_string(),
+        TomlValue::Float(x) if x.is_nan() => "nan".to_owned(),
+        TomlValue::Float(x) if x.is_infinite() => if *x > 0.0 { "inf" } else { "-inf" }.to_owned(),
+        TomlValue::Float(x) => {
+            let s = x.to_string();
+            if s.contains(['.', 'e']) {
+                s
+            } else {
+                s + ".0"
+            }
+        }
+        TomlValue::Boolean(b) => b.to_string(),
+        TomlValue::Datetime(datetime) => datetime.to_string(),
+        TomlValue::Array(array) => {
+            let elems: Vec<_> = array.iter().map(canonical_value).collect();
+            format!("[{}]", elems.join(", "))
+        }
+        TomlValue::Table(table) if table.is_empty() => "{}".to_owned(),
+        TomlValue::Table(table) => {
+            let mut entries: Vec<_> = table
+                .iter()
+                .map(|(key, val)| {
+                    (
+                        key,
+                        format!("{} = {}", format_key(key), canonical_value(val)),
+                    )
+                })
+                .collect();
+            entries.sort();
+            let entries: Vec<_> = entries.into_iter().map(|(_, entry)| entry).collect();
+            format!("{{ {} }}", entries.join(", "))
+        }
+    }
+}
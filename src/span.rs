@@ -0,0 +1,523 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::path::format_key_path;
+use crate::{PathSegment, TomlChange, TomlDiff};
+
+/// A line and column in a source document, both counting from 1. Columns count characters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+/// A range of a source document, from `start` up to but not including `end`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Span {
+    pub start: Position,
+    pub end: Position,
+}
+
+/// Where a change is in the source of each of the two documents, as found by
+/// [`TomlDiff::spans`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ChangeSpans {
+    pub old: Option<Span>,
+    pub new: Option<Span>,
+}
+
+/// How a table is written in a source document. The same table reads the same however it's
+/// written, so a diff never reports a change of style; [`style_changes`] does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TableStyle {
+    /// Under a `[table]` header, or a `[[table]]` header for elements of an array of tables
+    Header,
+    /// As an inline table, like `table = { key = 1 }`
+    Inline,
+    /// By dotted keys, like `table.key = 1`
+    Dotted,
+    /// Only by the headers of the tables inside it, like `[table.inner]`
+    Implicit,
+}
+
+impl fmt::Display for TableStyle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            TableStyle::Header => "header",
+            TableStyle::Inline => "inline table",
+            TableStyle::Dotted => "dotted keys",
+            TableStyle::Implicit => "implicit",
+        })
+    }
+}
+
+/// A table written in one style in the old source and another in the new one, as found by
+/// [`style_changes`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct StyleChange {
+    /// The table's key path, rendered as TOML dotted keys
+    pub key_path: String,
+    pub old: TableStyle,
+    pub new: TableStyle,
+}
+
+/// Renders the change like `server: header -> inline table`.
+impl fmt::Display for StyleChange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {} -> {}", self.key_path, self.old, self.new)
+    }
+}
+
+/// Find the tables written in a different style in the two TOML sources, like a `[server]` table
+/// turned into `server = { ... }`, in the order they appear in the new source. Such changes
+/// leave the documents the same, so they're never among a diff's changes, but they still show in
+/// review and may matter to tools that edit the files. Only tables in both sources are compared.
+/// The sources are expected to be valid TOML, and anything that isn't is skipped over.
+pub fn style_changes(old_source: &str, new_source: &str) -> Vec<StyleChange> {
+    let old = Spans::new(old_source);
+    let new = Spans::new(new_source);
+    let mut changes: Vec<_> = new
+        .styles
+        .iter()
+        .filter_map(|(path, &new_style)| {
+            let old_style = *old.styles.get(path)?;
+            (old_style != new_style).then_some((path, old_style, new_style))
+        })
+        .collect();
+    // Implicit tables have no range of their own, so they go where their first table does
+    let start = |path: &Vec<Segment>| {
+        let starts = new
+            .ranges
+            .iter()
+            .filter(|(inner, _)| inner.starts_with(path));
+        starts.map(|(_, &(start, _))| start).min()
+    };
+    changes.sort_by_key(|(path, ..)| (start(path), path.len()));
+    changes
+        .into_iter()
+        .map(|(path, old, new)| {
+            let key_path: Vec<_> = path
+                .iter()
+                .map(|segment| match segment {
+                    Segment::Key(key) => PathSegment::Key(key),
+                    Segment::Index(i) => PathSegment::Index(*i),
+                })
+                .collect();
+            StyleChange {
+                key_path: format_key_path(&key_path),
+                old,
+                new,
+            }
+        })
+        .collect()
+}
+
+impl<'a, V> TomlDiff<'a, V> {
+    /// Find each change in the TOML sources the two documents were parsed from, in the order of
+    /// [`TomlDiff::changes`], so that editors can jump to and highlight the lines that changed.
+    ///
+    /// A key's span covers the key and its value, a table's covers its header and its keys, and
+    /// an element's covers just the element. Where a change's key isn't in one of the documents,
+    /// such as an added key in the old document, its span is that of the nearest table or array
+    /// around it that is, or `None` for keys at the top level. The sources are expected to be
+    /// valid TOML, and anything that isn't is skipped over.
+    pub fn spans(&self, old_source: &str, new_source: &str) -> Vec<ChangeSpans> {
+        let old = Spans::new(old_source);
+        let new = Spans::new(new_source);
+        self.changes
+            .iter()
+            .map(|change| {
+                let key_path = change.key_path();
+                let old = match change {
+                    // The old document spells the last key differently
+                    TomlChange::KeyStyleChanged(_, old_key) => {
+                        let mut key_path = key_path.to_vec();
+                        *key_path.last_mut().unwrap() = PathSegment::Key(old_key);
+                        old.find(&key_path)
+                    }
+                    _ => old.find(key_path),
+                };
+                ChangeSpans {
+                    old,
+                    new: new.find(key_path),
+                }
+            })
+            .collect()
+    }
+}
+
+/// An owned [`PathSegment`], for paths whose keys had to be unquoted
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Segment {
+    Key(String),
+    Index(usize),
+}
+
+impl From<&PathSegment<'_>> for Segment {
+    fn from(segment: &PathSegment) -> Self {
+        match segment {
+            PathSegment::Key(key) => Segment::Key((*key).to_owned()),
+            PathSegment::Index(i) => Segment::Index(*i),
+        }
+    }
+}
+
+/// The byte ranges of every key, table and array element of a source document, and the style of
+/// every table
+pub(crate) struct Spans {
+    ranges: HashMap<Vec<Segment>, (usize, usize)>,
+    styles: HashMap<Vec<Segment>, TableStyle>,
+    line_starts: Vec<usize>,
+    source: String,
+}
+
+impl Spans {
+    pub(crate) fn new(source: &str) -> Self {
+        let mut scanner = Scanner {
+            source,
+            pos: 0,
+            ranges: HashMap::new(),
+            styles: HashMap::new(),
+        };
+        scanner.document();
+        let line_starts = std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        Self {
+            ranges: scanner.ranges,
+            styles: scanner.styles,
+            line_starts,
+            source: source.to_owned(),
+        }
+    }
+
+    /// The span of `key_path`, or of the nearest value around it in the document
+    pub(crate) fn find(&self, key_path: &[PathSegment]) -> Option<Span> {
+        let mut key_path: Vec<Segment> = key_path.iter().map(Segment::from).collect();
+        while !key_path.is_empty() {
+            if let Some(&(start, end)) = self.ranges.get(&key_path) {
+                return Some(Span {
+                    start: self.position(start),
+                    end: self.position(end),
+                });
+            }
+            key_path.pop();
+        }
+        None
+    }
+
+    fn position(&self, offset: usize) -> Position {
+        let line = self.line_starts.partition_point(|&start| start <= offset) - 1;
+        let line_start = self.line_starts[line];
+        Position {
+            line: line + 1,
+            column: self.source[line_start..offset].chars().count() + 1,
+        }
+    }
+}
+
+/// Just enough of a TOML parser to find where everything is, without parsing any values
+struct Scanner<'s> {
+    source: &'s str,
+    pos: usize,
+    ranges: HashMap<Vec<Segment>, (usize, usize)>,
+    styles: HashMap<Vec<Segment>, TableStyle>,
+}
+
+impl<'s> Scanner<'s> {
+    fn peek(&self) -> Option<u8> {
+        self.source.as_bytes().get(self.pos).copied()
+    }
+
+    fn rest(&self) -> &'s str {
+        &self.source[self.pos..]
+    }
+
+    /// Skip spaces and tabs, and also newlines and comments if `newlines` is set
+    fn whitespace(&mut self, newlines: bool) {
+        loop {
+            match self.peek() {
+                Some(b' ' | b'\t') => self.pos += 1,
+                Some(b'\r' | b'\n') if newlines => self.pos += 1,
+                Some(b'#') if newlines => self.skip_line(),
+                _ => return,
+            }
+        }
+    }
+
+    fn skip_line(&mut self) {
+        self.pos = self
+            .rest()
+            .find('\n')
+            .map_or(self.source.len(), |i| self.pos + i);
+    }
+
+    fn document(&mut self) {
+        // The path of the table the following keys belong to
+        let mut table = vec![];
+        // The start of the current table's header, and the end of the last thing in the table
+        let mut table_range: Option<(usize, usize)> = None;
+        // How many elements each array of tables has so far
+        let mut array_lens: HashMap<Vec<Segment>, usize> = HashMap::new();
+        loop {
+            self.whitespace(true);
+            let start = self.pos;
+            if self.peek() == Some(b'[') || self.peek().is_none() {
+                if let Some(range) = table_range.take() {
+                    self.close_table(&table, range, &array_lens);
+                }
+                if self.peek().is_none() {
+                    return;
+                }
+                match self.header(&mut array_lens) {
+                    Some(path) => {
+                        table = path;
+                        table_range = Some((start, self.pos));
+                    }
+                    None => self.skip_line(),
+                }
+                continue;
+            }
+            let mut path = table.clone();
+            if self.key_value(&mut path).is_none() {
+                self.skip_line();
+            } else if let Some((_, end)) = &mut table_range {
+                *end = self.pos;
+            }
+            // Guarantee progress on malformed input
+            if self.pos == start {
+                self.pos += 1;
+            }
+        }
+    }
+
+    /// Record the range of the table at `path`, and extend the range of the array of tables it's
+    /// an element of, if it is one
+    fn close_table(
+        &mut self,
+        path: &[Segment],
+        range: (usize, usize),
+        array_lens: &HashMap<Vec<Segment>, usize>,
+    ) {
+        self.ranges.insert(path.to_vec(), range);
+        if let Some((Segment::Index(_), array)) = path.split_last() {
+            if array_lens.contains_key(array) {
+                let array_range = self.ranges.entry(array.to_vec()).or_insert(range);
+                array_range.1 = range.1;
+            }
+        }
+    }
+
+    /// Scan a `[table]` or `[[array.of.tables]]` header, returning the path of the table
+    fn header(&mut self, array_lens: &mut HashMap<Vec<Segment>, usize>) -> Option<Vec<Segment>> {
+        let is_array = self.rest().starts_with("[[");
+        self.pos += if is_array { 2 } else { 1 };
+        let keys = self.keys()?;
+        let close = if is_array { "]]" } else { "]" };
+        if !self.rest().starts_with(close) {
+            return None;
+        }
+        self.pos += close.len();
+
+        let mut path = vec![];
+        for (i, key) in keys.iter().enumerate() {
+            path.push(Segment::Key(key.clone()));
+            if is_array && i == keys.len() - 1 {
+                let len = array_lens.entry(path.clone()).or_insert(0);
+                path.push(Segment::Index(*len));
+                *len += 1;
+            } else if let Some(len) = array_lens.get(&path) {
+                // Tables under an array of tables belong to its last element
+                path.push(Segment::Index(len - 1));
+            }
+            if i < keys.len() - 1 {
+                self.styles
+                    .entry(path.clone())
+                    .or_insert(TableStyle::Implicit);
+            }
+        }
+        self.styles.insert(path.clone(), TableStyle::Header);
+        Some(path)
+    }
+
+    /// Scan a `key = value` pair, recording the range of the value at `path` and everything in it
+    fn key_value(&mut self, path: &mut Vec<Segment>) -> Option<()> {
+        let start = self.pos;
+        let keys = self.keys()?;
+        if self.peek() != Some(b'=') {
+            return None;
+        }
+        self.pos += 1;
+        self.whitespace(false);
+        let len = path.len();
+        path.extend(keys.into_iter().map(Segment::Key));
+        for end in len + 1..path.len() {
+            let table = path[..end].to_vec();
+            self.styles.entry(table).or_insert(TableStyle::Dotted);
+        }
+        self.value(path)?;
+        self.ranges.insert(path.clone(), (start, self.pos));
+        path.truncate(len);
+        Some(())
+    }
+
+    /// Scan a dotted key, leaving any whitespace after it skipped
+    fn keys(&mut self) -> Option<Vec<String>> {
+        let mut keys = vec![];
+        loop {
+            self.whitespace(false);
+            keys.push(self.key()?);
+            self.whitespace(false);
+            if self.peek() != Some(b'.') {
+                return Some(keys);
+            }
+            self.pos += 1;
+        }
+    }
+
+    fn key(&mut self) -> Option<String> {
+        match self.peek()? {
+            b'"' => {
+                let end = self.string_end(b'"')?;
+                let key = unescape(&self.source[self.pos + 1..end - 1]);
+                self.pos = end;
+                Some(key)
+            }
+            b'\'' => {
+                let end = self.string_end(b'\'')?;
+                let key = self.source[self.pos + 1..end - 1].to_owned();
+                self.pos = end;
+                Some(key)
+            }
+            _ => {
+                let len = self
+                    .rest()
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '-'))
+                    .unwrap_or(self.rest().len());
+                let key = &self.rest()[..len];
+                self.pos += len;
+                (!key.is_empty()).then(|| key.to_owned())
+            }
+        }
+    }
+
+    /// The offset just past the closing quote of the single-line string starting here
+    fn string_end(&self, quote: u8) -> Option<usize> {
+        let bytes = self.source.as_bytes();
+        let mut i = self.pos + 1;
+        while let Some(&byte) = bytes.get(i) {
+            match byte {
+                b'\\' if quote == b'"' => i += 1,
+                b'\n' => return None,
+                _ if byte == quote => return Some(i + 1),
+                _ => {}
+            }
+            i += 1;
+        }
+        None
+    }
+
+    /// Scan any value, recording the ranges of everything inside it under `path`
+    fn value(&mut self, path: &mut Vec<Segment>) -> Option<()> {
+        let rest = self.rest();
+        if rest.starts_with("\"\"\"") || rest.starts_with("'''") {
+            let delimiter = &rest[..3];
+            let escapes = delimiter == "\"\"\"";
+            let mut i = 3;
+            loop {
+                let next = rest
+                    .get(i..)?
+                    .find([delimiter.as_bytes()[0] as char, '\\'])?
+                    + i;
+                if rest.as_bytes()[next] == b'\\' {
+                    // Step past the escaped character, however many bytes it takes up
+                    let escaped = rest[next + 1..].chars().next().filter(|_| escapes);
+                    i = next + 1 + escaped.map_or(0, char::len_utf8);
+                } else if rest[next..].starts_with(delimiter) {
+                    // Up to two quotes right before the closing ones are part of the string
+                    let quotes = rest[next..]
+                        .bytes()
+                        .take_while(|&b| b == rest.as_bytes()[next]);
+                    self.pos += next + quotes.count().min(5);
+                    return Some(());
+                } else {
+                    i = next + 1;
+                }
+            }
+        }
+        match self.peek()? {
+            quote @ (b'"' | b'\'') => self.pos = self.string_end(quote)?,
+            b'[' => {
+                self.pos += 1;
+                for i in 0.. {
+                    self.whitespace(true);
+                    if self.peek()? == b']' {
+                        break;
+                    }
+                    let start = self.pos;
+                    path.push(Segment::Index(i));
+                    self.value(path)?;
+                    self.ranges.insert(path.clone(), (start, self.pos));
+                    path.pop();
+                    self.whitespace(true);
+                    if self.peek()? == b',' {
+                        self.pos += 1;
+                    }
+                }
+                self.pos += 1;
+            }
+            b'{' => {
+                self.styles.insert(path.clone(), TableStyle::Inline);
+                self.pos += 1;
+                loop {
+                    self.whitespace(false);
+                    match self.peek()? {
+                        b'}' => break,
+                        b',' => self.pos += 1,
+                        _ => self.key_value(path)?,
+                    }
+                }
+                self.pos += 1;
+            }
+            _ => {
+                // Numbers, booleans and datetimes, which may hold a space between date and time
+                let len = self.rest().find([',', ']', '}', '#', '\r', '\n']);
+                let len = len.unwrap_or(self.rest().len());
+                let scalar = self.rest()[..len].trim_end();
+                if scalar.is_empty() {
+                    return None;
+                }
+                self.pos += scalar.len();
+            }
+        }
+        Some(())
+    }
+}
+
+/// Resolve the escapes of a basic string's contents
+fn unescape(s: &str) -> String {
+    let mut unescaped = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('b') => unescaped.push('\u{8}'),
+            Some('t') => unescaped.push('\t'),
+            Some('n') => unescaped.push('\n'),
+            Some('f') => unescaped.push('\u{c}'),
+            Some('r') => unescaped.push('\r'),
+            Some(c @ ('u' | 'U')) => {
+                let len = if c == 'u' { 4 } else { 8 };
+                let hex: String = chars.by_ref().take(len).collect();
+                let c = u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32);
+                unescaped.push(c.unwrap_or(char::REPLACEMENT_CHARACTER));
+            }
+            Some(c) => unescaped.push(c),
+            None => {}
+        }
+    }
+    unescaped
+}
@@ -0,0 +1,82 @@
+use std::collections::HashSet;
+use std::fmt::Write;
+
+use crate::path::format_key_path;
+use crate::{DiffValue, TomlChange, TomlDiff};
+
+impl<'a, V: DiffValue> TomlChange<'a, V> {
+    /// An identifier for this change that stays the same from one diff to the next, made from
+    /// its kind, key path and values, for listing changes to ignore with
+    /// [`TomlDiff::suppress`]. It's 16 hexadecimal digits.
+    pub fn id(&self) -> String {
+        let mut s = String::new();
+        let key_path = format_key_path(self.key_path());
+        match self {
+            TomlChange::Added(_, val) => write!(s, "added\0{key_path}\0{}", val.sort_key()),
+            TomlChange::Deleted(_, val) => write!(s, "deleted\0{key_path}\0{}", val.sort_key()),
+            TomlChange::Changed(_, old, new) => write!(
+                s,
+                "changed\0{key_path}\0{}\0{}",
+                old.sort_key(),
+                new.sort_key()
+            ),
+            TomlChange::KeyStyleChanged(_, old_key) => {
+                write!(s, "key_style_changed\0{key_path}\0{old_key}")
+            }
+            TomlChange::Moved(_, old_path) => {
+                write!(s, "moved\0{key_path}\0{}", format_key_path(old_path))
+            }
+            TomlChange::Reordered(_, permutation) => {
+                write!(s, "reordered\0{key_path}\0{permutation:?}")
+            }
+        }
+        .unwrap();
+        format!("{:016x}", fnv1a(s.as_bytes()))
+    }
+}
+
+impl<'a, V: DiffValue> TomlDiff<'a, V> {
+    /// Leave out the changes listed in `suppressions`, so that known and accepted differences,
+    /// such as between environments, stop showing up in every report. `suppressions` is the
+    /// text of a suppression file as written by [`TomlDiff::to_suppressions`]: one
+    /// [change ID](TomlChange::id) per line, followed by anything at all, with blank lines and
+    /// lines starting with `#` ignored. Changes left out by truncation can't be suppressed.
+    pub fn suppress(self, suppressions: &str) -> Self {
+        let ids: HashSet<_> = suppressions
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .filter_map(|line| line.split_whitespace().next())
+            .collect();
+        let truncated = self.truncated;
+        let mut diff = self.select(|change| !ids.contains(change.id().as_str()));
+        diff.truncated = truncated;
+        diff
+    }
+
+    /// A suppression file for [`TomlDiff::suppress`] listing every change of this diff, each
+    /// ID followed by the change's kind and key path.
+    pub fn to_suppressions(&self) -> String {
+        let mut s = String::new();
+        for change in &self.changes {
+            let kind = match change {
+                TomlChange::Added(..) => "added",
+                TomlChange::Deleted(..) => "deleted",
+                TomlChange::Changed(..) => "changed",
+                TomlChange::KeyStyleChanged(..) => "renamed",
+                TomlChange::Moved(..) => "moved",
+                TomlChange::Reordered(..) => "reordered",
+            };
+            let key_path = format_key_path(change.key_path());
+            writeln!(s, "{}  {kind} {key_path}", change.id()).unwrap();
+        }
+        s
+    }
+}
+
+/// The 64-bit FNV-1a hash of `bytes`, which unlike std's hashers is guaranteed never to change
+pub(crate) fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
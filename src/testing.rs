@@ -0,0 +1,131 @@
+//! Generators for random TOML documents, enabled with the `testing` feature.
+//!
+//! These are the generators this crate's own tests use to check invariants such as
+//! [`verify_roundtrip`](crate::verify_roundtrip). They are deterministic for a given seed, so a
+//! failing case can be reproduced from the seed alone.
+
+use toml::{map::Map, Value as TomlValue};
+
+/// A small, deterministic xorshift random number generator.
+#[derive(Debug, Clone)]
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // xorshift gets stuck on zero, and close seeds should still diverge quickly
+        Self(seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// A number in `0..n`
+    pub fn below(&mut self, n: u64) -> u64 {
+        self.next_u64() % n
+    }
+
+    /// `true` with a probability of roughly `1 / n`
+    pub fn one_in(&mut self, n: u64) -> bool {
+        self.below(n) == 0
+    }
+}
+
+/// Generate a random table nested at most `depth` levels deep.
+pub fn table(rng: &mut Rng, depth: u32) -> TomlValue {
+    let mut map = Map::new();
+    for _ in 0..rng.below(6) {
+        map.insert(key(rng), value(rng, depth));
+    }
+    TomlValue::Table(map)
+}
+
+/// Generate a random value of any kind, nested at most `depth` levels deep.
+pub fn value(rng: &mut Rng, depth: u32) -> TomlValue {
+    let kinds = if depth == 0 { 5 } else { 7 };
+    match rng.below(kinds) {
+        5 => table(rng, depth - 1),
+        6 => array(rng, depth - 1),
+        kind => scalar(rng, kind),
+    }
+}
+
+/// Generate a random array. Elements are all of one kind, as TOML requires.
+pub fn array(rng: &mut Rng, depth: u32) -> TomlValue {
+    let kinds = if depth == 0 { 5 } else { 7 };
+    let kind = rng.below(kinds);
+    let elems = (0..rng.below(5))
+        .map(|_| match kind {
+            5 => table(rng, depth - 1),
+            6 => array(rng, depth - 1),
+            kind => scalar(rng, kind),
+        })
+        .collect();
+    TomlValue::Array(elems)
+}
+
+/// Return a copy of `val` with a few random additions, deletions, replacements and reorderings,
+/// so that diffing the two produces an interesting set of changes.
+pub fn mutate(rng: &mut Rng, val: &TomlValue, depth: u32) -> TomlValue {
+    match val {
+        TomlValue::Table(table) => {
+            let mut map = Map::new();
+            for (k, v) in table {
+                if rng.one_in(5) {
+                    continue;
+                }
+                let v = if rng.one_in(5) {
+                    value(rng, depth)
+                } else {
+                    mutate(rng, v, depth.saturating_sub(1))
+                };
+                map.insert(k.clone(), v);
+            }
+            if rng.one_in(3) {
+                map.insert(key(rng), value(rng, depth));
+            }
+            TomlValue::Table(map)
+        }
+        TomlValue::Array(array) => {
+            let mut elems: Vec<_> = array.iter().filter(|_| !rng.one_in(4)).cloned().collect();
+            // Only duplicate existing elements, so the array stays homogeneous
+            if let Some(elem) = array.first().filter(|_| rng.one_in(3)) {
+                elems.push(mutate(rng, elem, depth.saturating_sub(1)));
+            }
+            // Moving an element is a change of order alone
+            if elems.len() > 1 && rng.one_in(4) {
+                elems.rotate_left(1);
+            }
+            TomlValue::Array(elems)
+        }
+        val => val.clone(),
+    }
+}
+
+fn key(rng: &mut Rng) -> String {
+    // A small alphabet makes keys collide between documents often enough to be interesting
+    const KEYS: [&str; 8] = ["a", "b", "c", "name", "port", "my key", "a.b", "x-y"];
+    KEYS[rng.below(KEYS.len() as u64) as usize].to_owned()
+}
+
+fn scalar(rng: &mut Rng, kind: u64) -> TomlValue {
+    const STRINGS: [&str; 4] = ["", "abc", "def", "with \"quotes\"\nand lines"];
+    const DATETIMES: [&str; 4] = [
+        "1979-05-27T07:32:00Z",
+        "1979-05-27T07:32:00",
+        "1979-05-27",
+        "07:32:00",
+    ];
+    match kind {
+        0 => TomlValue::String(STRINGS[rng.below(4) as usize].to_owned()),
+        1 => TomlValue::Integer(rng.below(5) as i64 - 2),
+        // No NaN, since it isn't equal to itself
+        2 => TomlValue::Float(rng.below(5) as f64 / 2.0),
+        3 => TomlValue::Boolean(rng.one_in(2)),
+        4 => TomlValue::Datetime(DATETIMES[rng.below(4) as usize].parse().unwrap()),
+        _ => unreachable!("Scalar kinds are 0..5"),
+    }
+}
@@ -0,0 +1,147 @@
+use std::borrow::Cow;
+
+use crate::unicode_tables::{
+    COMBINING_CLASSES, COMPAT_DECOMPOSITIONS, COMPOSITIONS, DECOMPOSITIONS, WIDE_CHARS,
+};
+
+/// A Unicode normalization form that strings and keys are put in before they're compared, with
+/// [`DiffOptions::unicode_normalization`](crate::DiffOptions::unicode_normalization).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnicodeForm {
+    /// Canonical composition, under which an accented letter typed as one character is the
+    /// same as the letter followed by a combining accent
+    Nfc,
+    /// Compatibility composition, which goes further than [`UnicodeForm::Nfc`] and also makes
+    /// characters that merely look alike the same, like `ﬁ` and `fi`, or a full-width `Ａ` and
+    /// `A`
+    Nfkc,
+}
+
+// Hangul syllables are composed of jamo by arithmetic rather than by table
+const S_BASE: u32 = 0xAC00;
+const L_BASE: u32 = 0x1100;
+const V_BASE: u32 = 0x1161;
+const T_BASE: u32 = 0x11A7;
+const L_COUNT: u32 = 19;
+const V_COUNT: u32 = 21;
+const T_COUNT: u32 = 28;
+const S_COUNT: u32 = L_COUNT * V_COUNT * T_COUNT;
+
+/// `s` in normalization `form`
+pub(crate) fn normalize(s: &str, form: UnicodeForm) -> Cow<'_, str> {
+    // Nothing below these changes under the form, or combines with what comes before it
+    let stable = match form {
+        UnicodeForm::Nfc => '\u{300}',
+        UnicodeForm::Nfkc => '\u{a0}',
+    };
+    if s.chars().all(|c| c < stable) {
+        return Cow::Borrowed(s);
+    }
+
+    let mut chars = vec![];
+    for c in s.chars() {
+        decompose(c, form == UnicodeForm::Nfkc, &mut chars);
+    }
+    // Put each run of combining marks in the canonical order, which a stable sort keeps for
+    // marks of the same class
+    let mut start = 0;
+    while start < chars.len() {
+        let len = chars[start..]
+            .iter()
+            .take_while(|&&c| combining_class(c) != 0)
+            .count();
+        chars[start..start + len].sort_by_key(|&c| combining_class(c));
+        start += len.max(1);
+    }
+    Cow::Owned(compose(&chars))
+}
+
+fn decompose(c: char, compat: bool, chars: &mut Vec<char>) {
+    let s = c as u32;
+    if (S_BASE..S_BASE + S_COUNT).contains(&s) {
+        let i = s - S_BASE;
+        let jamo = [
+            Some(L_BASE + i / (V_COUNT * T_COUNT)),
+            Some(V_BASE + i % (V_COUNT * T_COUNT) / T_COUNT),
+            Some(T_BASE + i % T_COUNT).filter(|&t| t != T_BASE),
+        ];
+        chars.extend(jamo.into_iter().flatten().filter_map(char::from_u32));
+        return;
+    }
+    let lookup = |table: &[(char, &'static str)]| {
+        let i = table.binary_search_by_key(&c, |&(c, _)| c).ok()?;
+        Some(table[i].1)
+    };
+    let decomposition = compat
+        .then(|| lookup(COMPAT_DECOMPOSITIONS))
+        .flatten()
+        .or_else(|| lookup(DECOMPOSITIONS));
+    match decomposition {
+        Some(decomposition) => chars.extend(decomposition.chars()),
+        None => chars.push(c),
+    }
+}
+
+/// Compose decomposed `chars`, combining each mark with the closest character before it that
+/// can take it, unless a mark of the same or a lower class comes between them
+fn compose(chars: &[char]) -> String {
+    let mut composed: Vec<char> = Vec::with_capacity(chars.len());
+    let mut starter = None;
+    let mut last_class = 0;
+    for &c in chars {
+        let class = combining_class(c);
+        if let Some(i) = starter {
+            let adjacent = i + 1 == composed.len();
+            if adjacent || (last_class != 0 && last_class < class) {
+                if let Some(pair) = compose_pair(composed[i], c) {
+                    composed[i] = pair;
+                    continue;
+                }
+            }
+        }
+        if class == 0 {
+            starter = Some(composed.len());
+        }
+        last_class = class;
+        composed.push(c);
+    }
+    composed.into_iter().collect()
+}
+
+fn compose_pair(a: char, b: char) -> Option<char> {
+    let (a_u32, b_u32) = (a as u32, b as u32);
+    if (L_BASE..L_BASE + L_COUNT).contains(&a_u32) && (V_BASE..V_BASE + V_COUNT).contains(&b_u32) {
+        let l = a_u32 - L_BASE;
+        let v = b_u32 - V_BASE;
+        return char::from_u32(S_BASE + (l * V_COUNT + v) * T_COUNT);
+    }
+    let is_lv =
+        (S_BASE..S_BASE + S_COUNT).contains(&a_u32) && (a_u32 - S_BASE).is_multiple_of(T_COUNT);
+    if is_lv && (T_BASE + 1..T_BASE + T_COUNT).contains(&b_u32) {
+        return char::from_u32(a_u32 + b_u32 - T_BASE);
+    }
+    let i = COMPOSITIONS
+        .binary_search_by_key(&(a, b), |&(pair, _)| pair)
+        .ok()?;
+    Some(COMPOSITIONS[i].1)
+}
+
+/// The number of terminal columns `c` takes up: two for East Asian Wide and Fullwidth
+/// characters, none for combining marks, which go on the character before them, and one for
+/// anything else.
+pub fn char_width(c: char) -> usize {
+    let i = WIDE_CHARS.partition_point(|&(_, end)| end < c);
+    match WIDE_CHARS.get(i) {
+        Some(&(start, _)) if start <= c => 2,
+        _ if combining_class(c) != 0 => 0,
+        _ => 1,
+    }
+}
+
+fn combining_class(c: char) -> u8 {
+    let i = COMBINING_CLASSES.partition_point(|&(_, end, _)| end < c);
+    match COMBINING_CLASSES.get(i) {
+        Some(&(start, _, class)) if start <= c => class,
+        _ => 0,
+    }
+}
@@ -0,0 +1,87 @@
+use crate::path::SharedPath;
+use crate::{diff_level, ChangeList, DiffOptions, DiffValue, TomlDiff, ValueKind};
+
+impl<'a, V: DiffValue> TomlDiff<'a, V> {
+    /// Diff the new document of this diff against `new_b` in place of its old document, with
+    /// the `options` this diff was made with, reusing the changes inside each top-level table
+    /// or array that `new_b` holds the same as the old document did rather than diffing it
+    /// again. A document re-diffed every few seconds while it's edited, as when hot-reloading
+    /// configuration, usually only changes in a table or two at a time, and only those are
+    /// diffed. The changes are the same as a diff from scratch would have, although reused
+    /// changes can refer to values of the old document, which are equal to those in `new_b`.
+    ///
+    /// Everything is diffed again with options that need the whole diff at once, which are
+    /// [aliases](DiffOptions::alias), [`DiffOptions::max_changes`],
+    /// [`DiffOptions::memory_budget`], [`DiffOptions::record_unchanged`],
+    /// [`DiffOptions::record_suppressed`] and [`DiffOptions::on_progress`]. The diff is made on
+    /// one thread in any case.
+    ///
+    /// Panics if this diff wasn't made by [`TomlDiff::diff_with_options`] or `update`, or has
+    /// since been [selected](TomlDiff::select) from or [sorted](TomlDiff::sort_by), or if
+    /// `new_b` isn't a table.
+    pub fn update(&self, new_b: &'a V, options: &DiffOptions<V>) -> Self {
+        let (a, b) = self
+            .documents
+            .expect("Expected a diff made from two documents");
+        if !options.aliases.is_empty()
+            || options.max_changes.is_some()
+            || options.memory_budget.is_some()
+            || options.record_unchanged
+            || options.record_suppressed
+            || options.on_progress.is_some()
+            || options.max_depth.is_some()
+        {
+            return Self::diff_with_options(a, new_b, options);
+        }
+        if new_b.kind() != ValueKind::Table {
+            panic!("Expected a table at the top level");
+        }
+
+        // Diffing the top level leaves the tables and arrays that differ on the stack. Those
+        // that the old document held the same as `new_b` keep their changes from this diff.
+        let mut changes = ChangeList::new(options);
+        let mut subtrees = vec![];
+        diff_level(
+            a,
+            new_b,
+            SharedPath::default(),
+            options,
+            None,
+            &mut changes,
+            &mut subtrees,
+        );
+        // A key spelled the same is the one it was matched with, even if another key of the
+        // old document has the same normalized form
+        let old_value = |key: &str| {
+            let normalized = options.normalize_key(key);
+            let exact = b.entries().find(|&(k, _)| k == key);
+            exact
+                .or_else(|| {
+                    b.entries()
+                        .find(|(k, _)| options.normalize_key(k) == normalized)
+                })
+                .map(|(_, val)| val)
+        };
+        while let Some((a, new_b, key_path)) = subtrees.pop() {
+            let key = key_path.to_vec()[0];
+            if key.as_key().and_then(old_value) == Some(new_b) {
+                let reused = self.changes.iter().filter(|change| {
+                    let change_path = change.key_path();
+                    change_path.len() > 1 && change_path[0] == key
+                });
+                changes.extend(reused.cloned());
+                continue;
+            }
+            let mut stack = vec![(a, new_b, key_path)];
+            while let Some((a, b, key_path)) = stack.pop() {
+                diff_level(a, b, key_path, options, None, &mut changes, &mut stack);
+            }
+        }
+        Self {
+            #[cfg(feature = "render")]
+            style: crate::display::Style::with_options(),
+            documents: Some((a, new_b)),
+            ..Self::from_changes(changes.changes, changes.truncated)
+        }
+    }
+}
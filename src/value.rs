@@ -0,0 +1,226 @@
+use std::sync::OnceLock;
+
+use toml::value::Datetime;
+use toml::{map::Map, Value as TomlValue};
+
+/// The shape of a [`DiffValue`], which is all the diff needs to know about it beyond equality.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueKind {
+    Table,
+    Array,
+    /// Anything that isn't a table or an array, which is only ever compared as a whole. This
+    /// includes any kind of value the diff doesn't know about, such as ones added by a newer
+    /// version of TOML.
+    Scalar,
+}
+
+/// A document model the diff engine can walk. Implemented for [`toml::Value`], and for any other
+/// model a downstream crate wants to diff with [`TomlDiff::diff`](crate::TomlDiff::diff)
+/// without forking the algorithm.
+///
+/// Only diffs of `toml::Value` can be rendered, applied or checked, but the changes of a diff of
+/// any `DiffValue` can be inspected. Implementations must be `Sync` so that subtrees can be
+/// diffed in parallel.
+pub trait DiffValue: PartialEq + Sync {
+    fn kind(&self) -> ValueKind;
+    /// The keys and values of a table, in any order. Only called on tables.
+    fn entries(&self) -> impl Iterator<Item = (&str, &Self)>;
+    /// The elements of an array, in order. Only called on arrays.
+    fn elements(&self) -> impl Iterator<Item = &Self>;
+    /// A string that is the same for equal values and different for unequal ones. Array
+    /// elements are matched up by sorting them by this key.
+    fn sort_key(&self) -> String;
+    /// The text of a string, so that options comparing strings, like
+    /// [`DiffOptions::unicode_normalization`](crate::DiffOptions::unicode_normalization), apply
+    /// to it. By default, no value is a string.
+    fn as_str(&self) -> Option<&str> {
+        None
+    }
+    /// The value of a boolean, so that [`ChangeCounts`](crate::ChangeCounts) can count flipped
+    /// flags. By default, no value is a boolean.
+    fn as_bool(&self) -> Option<bool> {
+        None
+    }
+    /// Whether two values are of the same type, which is all that
+    /// [`DiffOptions::structure_only`](crate::DiffOptions::structure_only) compares. By default,
+    /// values of the same kind are of the same type.
+    fn same_type(&self, other: &Self) -> bool {
+        self.kind() == other.kind()
+    }
+}
+
+impl DiffValue for TomlValue {
+    fn kind(&self) -> ValueKind {
+        match self {
+            TomlValue::Table(_) => ValueKind::Table,
+            TomlValue::Array(_) => ValueKind::Array,
+            _ => ValueKind::Scalar,
+        }
+    }
+
+    fn entries(&self) -> impl Iterator<Item = (&str, &Self)> {
+        self.as_table()
+            .into_iter()
+            .flatten()
+            .map(|(key, val)| (key.as_str(), val))
+    }
+
+    fn elements(&self) -> impl Iterator<Item = &Self> {
+        self.as_array().into_iter().flatten()
+    }
+
+    fn sort_key(&self) -> String {
+        if maps_are_sorted() {
+            raw(self)
+        } else {
+            raw(&sorted(self))
+        }
+    }
+
+    fn as_str(&self) -> Option<&str> {
+        TomlValue::as_str(self)
+    }
+
+    fn as_bool(&self) -> Option<bool> {
+        TomlValue::as_bool(self)
+    }
+
+    /// Datetimes are of the same type only if they're of the same [`DatetimeKind`].
+    fn same_type(&self, other: &Self) -> bool {
+        match (self, other) {
+            (TomlValue::Datetime(a), TomlValue::Datetime(b)) => {
+                DatetimeKind::of(a) == DatetimeKind::of(b)
+            }
+            _ => self.type_str() == other.type_str(),
+        }
+    }
+}
+
+/// Which of TOML's four kinds of datetime a [`Datetime`] is. Each is a type of its own, so a
+/// local date changed to an offset datetime is a change of type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DatetimeKind {
+    /// A date and time with an offset from UTC, like `1979-05-27T07:32:00Z`
+    OffsetDatetime,
+    /// A date and time without an offset, like `1979-05-27T07:32:00`
+    LocalDatetime,
+    /// Just a date, like `1979-05-27`
+    LocalDate,
+    /// Just a time of day, like `07:32:00`
+    LocalTime,
+}
+
+impl DatetimeKind {
+    pub fn of(datetime: &Datetime) -> Self {
+        match (&datetime.date, &datetime.time, &datetime.offset) {
+            (Some(_), Some(_), Some(_)) => DatetimeKind::OffsetDatetime,
+            (Some(_), Some(_), None) => DatetimeKind::LocalDatetime,
+            (Some(_), None, _) => DatetimeKind::LocalDate,
+            (None, _, _) => DatetimeKind::LocalTime,
+        }
+    }
+
+    /// The name of the kind as the TOML spec gives it, like `offset datetime`
+    pub fn name(self) -> &'static str {
+        match self {
+            DatetimeKind::OffsetDatetime => "offset datetime",
+            DatetimeKind::LocalDatetime => "local datetime",
+            DatetimeKind::LocalDate => "local date",
+            DatetimeKind::LocalTime => "local time",
+        }
+    }
+}
+
+/// The type of a TOML value, as the TOML spec names them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ValueType {
+    String,
+    Integer,
+    Float,
+    Boolean,
+    /// Any of the [kinds of datetime](DatetimeKind)
+    Datetime,
+    Array,
+    Table,
+}
+
+impl ValueType {
+    /// Every type, in the order of their variants
+    pub const ALL: [ValueType; 7] = [
+        ValueType::String,
+        ValueType::Integer,
+        ValueType::Float,
+        ValueType::Boolean,
+        ValueType::Datetime,
+        ValueType::Array,
+        ValueType::Table,
+    ];
+
+    pub fn of(val: &TomlValue) -> Self {
+        match val {
+            TomlValue::String(_) => ValueType::String,
+            TomlValue::Integer(_) => ValueType::Integer,
+            TomlValue::Float(_) => ValueType::Float,
+            TomlValue::Boolean(_) => ValueType::Boolean,
+            TomlValue::Datetime(_) => ValueType::Datetime,
+            TomlValue::Array(_) => ValueType::Array,
+            TomlValue::Table(_) => ValueType::Table,
+        }
+    }
+
+    /// The name of the type, as the CLI takes it: `string`, `integer`, `float`, `boolean`,
+    /// `datetime`, `array` or `table`
+    pub fn name(self) -> &'static str {
+        match self {
+            ValueType::String => "string",
+            ValueType::Integer => "integer",
+            ValueType::Float => "float",
+            ValueType::Boolean => "boolean",
+            ValueType::Datetime => "datetime",
+            ValueType::Array => "array",
+            ValueType::Table => "table",
+        }
+    }
+
+    /// The type called `name`, as given by [`ValueType::name`]
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|ty| ty.name() == name)
+    }
+}
+
+/// The TOML form of `val`, or its debug form if `toml` can't render it. Values of kinds this
+/// crate doesn't know about, which is any scalar that isn't a string, number or boolean, are
+/// rendered this way and so still compare and display as a whole instead of failing.
+pub(crate) fn raw(val: &TomlValue) -> String {
+    toml::to_string(val).unwrap_or_else(|_| format!("{val:?}"))
+}
+
+/// Whether `toml`'s maps keep their keys sorted, which they do unless something in the
+/// dependency graph enables its `preserve_order` feature, making them keep insertion order.
+pub(crate) fn maps_are_sorted() -> bool {
+    static SORTED: OnceLock<bool> = OnceLock::new();
+    *SORTED.get_or_init(|| {
+        let mut map = Map::new();
+        map.insert("b".to_owned(), TomlValue::Boolean(true));
+        map.insert("a".to_owned(), TomlValue::Boolean(true));
+        map.keys().next().map(String::as_str) == Some("a")
+    })
+}
+
+/// A copy of `val` with the keys of every table inserted in sorted order, so that it renders the
+/// same way whatever order its keys were in.
+fn sorted(val: &TomlValue) -> TomlValue {
+    match val {
+        TomlValue::Table(table) => {
+            let mut entries: Vec<_> = table.iter().collect();
+            entries.sort_by_key(|(key, _)| *key);
+            let table: Map<_, _> = entries
+                .into_iter()
+                .map(|(key, val)| (key.clone(), sorted(val)))
+                .collect();
+            TomlValue::Table(table)
+        }
+        TomlValue::Array(array) => TomlValue::Array(array.iter().map(sorted).collect()),
+        val => val.clone(),
+    }
+}
@@ -0,0 +1,98 @@
+use toml::Value as TomlValue;
+
+use crate::{PathSegment, TomlChange, TomlDiff};
+
+/// Receives the changes of a [`TomlDiff`] one at a time from [`TomlDiff::walk`].
+///
+/// Every method has an empty default, so visitors only implement what they care about. Key
+/// paths passed to the `on_*` methods are the full path of the change, while `enter_table` and
+/// `leave_table` bracket the changes made inside each table. The top-level table is never
+/// entered or left, and the changed elements of an array are visited as part of the table that
+/// holds the array.
+#[allow(unused_variables)]
+pub trait DiffVisitor<'a> {
+    fn on_added(&mut self, key_path: &[PathSegment<'a>], val: &'a TomlValue) {}
+    fn on_deleted(&mut self, key_path: &[PathSegment<'a>], val: &'a TomlValue) {}
+    fn on_changed(&mut self, key_path: &[PathSegment<'a>], old: &'a TomlValue, new: &'a TomlValue) {
+    }
+    /// The key at `key_path` was spelled `old_key` in the old document
+    fn on_key_style_changed(&mut self, key_path: &[PathSegment<'a>], old_key: &'a str) {}
+    /// The key at `key_path` was at `old_path` in the old document
+    fn on_moved(&mut self, key_path: &[PathSegment<'a>], old_path: &[PathSegment<'a>]) {}
+    /// Element `i` of the array at `key_path` was element `permutation[i]` of the old array
+    fn on_reordered(&mut self, key_path: &[PathSegment<'a>], permutation: &[usize]) {}
+    fn enter_table(&mut self, key_path: &[PathSegment<'a>]) {}
+    fn leave_table(&mut self, key_path: &[PathSegment<'a>]) {}
+}
+
+impl<'a, V: DiffVisitor<'a> + ?Sized> DiffVisitor<'a> for &mut V {
+    fn on_added(&mut self, key_path: &[PathSegment<'a>], val: &'a TomlValue) {
+        (**self).on_added(key_path, val)
+    }
+    fn on_deleted(&mut self, key_path: &[PathSegment<'a>], val: &'a TomlValue) {
+        (**self).on_deleted(key_path, val)
+    }
+    fn on_changed(&mut self, key_path: &[PathSegment<'a>], old: &'a TomlValue, new: &'a TomlValue) {
+        (**self).on_changed(key_path, old, new)
+    }
+    fn on_key_style_changed(&mut self, key_path: &[PathSegment<'a>], old_key: &'a str) {
+        (**self).on_key_style_changed(key_path, old_key)
+    }
+    fn on_moved(&mut self, key_path: &[PathSegment<'a>], old_path: &[PathSegment<'a>]) {
+        (**self).on_moved(key_path, old_path)
+    }
+    fn on_reordered(&mut self, key_path: &[PathSegment<'a>], permutation: &[usize]) {
+        (**self).on_reordered(key_path, permutation)
+    }
+    fn enter_table(&mut self, key_path: &[PathSegment<'a>]) {
+        (**self).enter_table(key_path)
+    }
+    fn leave_table(&mut self, key_path: &[PathSegment<'a>]) {
+        (**self).leave_table(key_path)
+    }
+}
+
+impl<'a> TomlDiff<'a> {
+    /// Feed every change to `visitor`, ordered by key path so that the changes inside each table
+    /// are visited together between its `enter_table` and `leave_table` calls.
+    pub fn walk(&self, mut visitor: impl DiffVisitor<'a>) {
+        let mut changes: Vec<_> = self.changes.iter().collect();
+        changes.sort_by(|x, y| x.key_path().cmp(y.key_path()));
+
+        // The path of the innermost table currently entered
+        let mut open: &[PathSegment<'a>] = &[];
+        for change in changes {
+            let key_path = change.key_path();
+            // Array elements belong to the table that holds their array
+            let table_len = key_path
+                .iter()
+                .rposition(|segment| segment.as_key().is_some())
+                .unwrap_or(0);
+            let table = &key_path[..table_len];
+            let common = open.iter().zip(table).take_while(|(x, y)| x == y).count();
+            for len in (common + 1..=open.len()).rev() {
+                visitor.leave_table(&open[..len]);
+            }
+            for len in common + 1..=table.len() {
+                visitor.enter_table(&table[..len]);
+            }
+            open = table;
+
+            match change {
+                TomlChange::Added(key_path, val) => visitor.on_added(key_path, val),
+                TomlChange::Deleted(key_path, val) => visitor.on_deleted(key_path, val),
+                TomlChange::Changed(key_path, old, new) => visitor.on_changed(key_path, old, new),
+                TomlChange::KeyStyleChanged(key_path, old_key) => {
+                    visitor.on_key_style_changed(key_path, old_key)
+                }
+                TomlChange::Moved(key_path, old_path) => visitor.on_moved(key_path, old_path),
+                TomlChange::Reordered(key_path, permutation) => {
+                    visitor.on_reordered(key_path, permutation)
+                }
+            }
+        }
+        for len in (1..=open.len()).rev() {
+            visitor.leave_table(&open[..len]);
+        }
+    }
+}
@@ -0,0 +1,83 @@
+//! Watching a TOML file for changes, the building block of reloading configuration while a
+//! program runs. The file is polled rather than watched through the operating system, which
+//! works the same on every platform and every file system, network mounts included.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use toml::Value as TomlValue;
+
+use crate::{DiffOptions, TomlDiff};
+
+/// How often the file is read to check whether it changed
+pub const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A file being watched by [`watch_file`]. Watching stops when this is dropped.
+#[derive(Debug)]
+pub struct Watcher {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Drop for Watcher {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            // A panic in the callback has already been reported, so there's nothing to add
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Watch the TOML file at `path`, calling `callback` on another thread with the diff from the
+/// file's previous contents to its new ones, made with `options`, each time it changes. The
+/// file is read and parsed once up front, and any error doing so is returned. After that,
+/// contents that can't be read or aren't valid TOML are skipped, as happens while an editor is
+/// partway through saving the file, and the next valid contents are diffed against the last.
+/// Changes that don't change the document, like reformatting, don't call `callback`, and
+/// several changes within one [`POLL_INTERVAL`] are seen as one.
+pub fn watch_file(
+    path: impl Into<PathBuf>,
+    options: DiffOptions,
+    mut callback: impl FnMut(&TomlDiff) + Send + 'static,
+) -> io::Result<Watcher> {
+    let path = path.into();
+    let (mut text, mut doc) = read(&path)?;
+    let stop = Arc::new(AtomicBool::new(false));
+    let thread = thread::spawn({
+        let stop = stop.clone();
+        move || {
+            while !stop.load(Ordering::Relaxed) {
+                thread::sleep(POLL_INTERVAL);
+                let Ok((new_text, new_doc)) = read(&path) else {
+                    continue;
+                };
+                if new_text == text {
+                    continue;
+                }
+                let diff = TomlDiff::diff_with_options(&new_doc, &doc, &options);
+                if !diff.changes.is_empty() {
+                    callback(&diff);
+                }
+                (text, doc) = (new_text, new_doc);
+            }
+        }
+    });
+    Ok(Watcher {
+        stop,
+        thread: Some(thread),
+    })
+}
+
+fn read(path: &Path) -> io::Result<(String, TomlValue)> {
+    let text = fs::read_to_string(path)?;
+    let doc = text
+        .parse()
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+    Ok((text, doc))
+}
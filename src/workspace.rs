@@ -0,0 +1,225 @@
+//! Diffs of the dependencies of Cargo workspaces. A [`WorkspaceDiff`] lists each dependency
+//! whose declaration changed in any member's `Cargo.toml`, with a change made the same way in
+//! several members listed once, followed by the members it was made in:
+//!
+//! ```text
+//! rand
+//!   - "0.8" in crates/core (dev-dependencies)
+//! serde
+//!   ~ "1.0.190" -> { features = ["derive"], version = "1.0.203" } in crates/cli, crates/core
+//!   ~ "1.0.190" -> "1.0.203" in crates/server
+//! tokio
+//!   + { features = ["full"], version = "1" } in crates/server
+//! 3 dependencies changed in 3 members
+//! ```
+//!
+//! A single pair of manifests is best diffed with [`Profile::CargoManifest`](crate::Profile),
+//! which compares dependencies the same way.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+use toml::Value as TomlValue;
+
+use crate::options::dependency_table;
+use crate::path::format_key_path;
+use crate::report::inline;
+use crate::PathSegment;
+
+/// The tables of a manifest that declare dependencies, other than those under `target`
+const DEPENDENCY_TABLES: [&str; 3] = ["dependencies", "dev-dependencies", "build-dependencies"];
+
+/// The changes to the dependencies of a workspace, from [`diff`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct WorkspaceDiff<'a> {
+    /// The dependencies whose declaration changed in any member, ordered by name
+    pub dependencies: Vec<DependencyDiff<'a>>,
+}
+
+/// The changes to the declarations of one dependency across the members of a workspace.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DependencyDiff<'a> {
+    pub name: &'a str,
+    /// Each distinct change to the declaration, in the order of the first member it was made in
+    pub changes: Vec<DependencyChange<'a>>,
+}
+
+/// A change to the declaration of a dependency, made the same way in one or more members.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DependencyChange<'a> {
+    /// The declaration in the old workspace, or `None` if the dependency was added
+    pub old: Option<&'a TomlValue>,
+    /// The declaration in the new workspace, or `None` if the dependency was removed
+    pub new: Option<&'a TomlValue>,
+    /// The members the change was made in, ordered by name
+    pub members: Vec<Member<'a>>,
+}
+
+/// Where a [`DependencyChange`] was made.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Member<'a> {
+    /// The name the member's manifest was given to [`diff`]
+    pub name: &'a str,
+    /// The key path of the table declaring the dependency, like `dependencies`,
+    /// `target."cfg(unix)".dev-dependencies` or `workspace.dependencies`
+    pub table: String,
+}
+
+/// Diff the dependencies declared by the members of two workspaces, each given as the name of
+/// a member, like the path of its directory, and its `Cargo.toml`. As with
+/// [`TomlDiff::diff`](crate::TomlDiff::diff), `a` is the new workspace and `b` the old one, and
+/// a member missing from one of them is diffed as if its manifest were empty.
+///
+/// The dependencies of a manifest are those under `[dependencies]`, `[dev-dependencies]` and
+/// `[build-dependencies]`, the same tables under `[target.*]`, and
+/// `[workspace.dependencies]`. A dependency written as a version string is the same as a table
+/// holding just that version. Changes are the same if the old and the new declarations are
+/// both written the same, whichever table of whichever member they were made in.
+pub fn diff<'a>(
+    a: &[(&'a str, &'a TomlValue)],
+    b: &[(&'a str, &'a TomlValue)],
+) -> WorkspaceDiff<'a> {
+    let names: BTreeSet<&str> = a.iter().chain(b).map(|&(name, _)| name).collect();
+    let manifest = |members: &[(&str, &'a TomlValue)], name| {
+        members
+            .iter()
+            .find(|&&(member, _)| member == name)
+            .map(|&(_, manifest)| manifest)
+    };
+
+    let mut dependencies: BTreeMap<&str, Vec<DependencyChange>> = BTreeMap::new();
+    for name in names {
+        // The old, then the new declaration of each dependency, by table and name
+        let mut declarations: BTreeMap<_, (Option<&TomlValue>, Option<&TomlValue>)> =
+            BTreeMap::new();
+        for (table, dependency, declaration) in manifest(b, name).map(declared).unwrap_or_default()
+        {
+            declarations.entry((table, dependency)).or_default().0 = Some(declaration);
+        }
+        for (table, dependency, declaration) in manifest(a, name).map(declared).unwrap_or_default()
+        {
+            declarations.entry((table, dependency)).or_default().1 = Some(declaration);
+        }
+
+        for ((table, dependency), (old, new)) in declarations {
+            if old.map(dependency_table) == new.map(dependency_table) {
+                continue;
+            }
+            let member = Member {
+                name,
+                table: format_key_path(&table),
+            };
+            let changes = dependencies.entry(dependency).or_default();
+            match changes
+                .iter_mut()
+                .find(|change| change.old == old && change.new == new)
+            {
+                Some(change) => change.members.push(member),
+                None => changes.push(DependencyChange {
+                    old,
+                    new,
+                    members: vec![member],
+                }),
+            }
+        }
+    }
+    let dependencies = dependencies
+        .into_iter()
+        .map(|(name, changes)| DependencyDiff { name, changes })
+        .collect();
+    WorkspaceDiff { dependencies }
+}
+
+/// The key path of the table declaring each dependency of `manifest`, its name and its
+/// declaration
+fn declared(manifest: &TomlValue) -> Vec<(Vec<PathSegment<'_>>, &str, &TomlValue)> {
+    let mut tables = vec![];
+    for table in DEPENDENCY_TABLES {
+        tables.push((vec![PathSegment::Key(table)], manifest.get(table)));
+    }
+    let targets = manifest.get("target").and_then(TomlValue::as_table);
+    for (target, dependencies) in targets.into_iter().flatten() {
+        for table in DEPENDENCY_TABLES {
+            let key_path = vec![
+                PathSegment::Key("target"),
+                PathSegment::Key(target),
+                PathSegment::Key(table),
+            ];
+            tables.push((key_path, dependencies.get(table)));
+        }
+    }
+    let workspace = manifest
+        .get("workspace")
+        .and_then(|w| w.get("dependencies"));
+    tables.push((
+        vec![
+            PathSegment::Key("workspace"),
+            PathSegment::Key("dependencies"),
+        ],
+        workspace,
+    ));
+
+    let mut declarations = vec![];
+    for (key_path, table) in tables {
+        let table = table.and_then(TomlValue::as_table);
+        for (name, declaration) in table.into_iter().flatten() {
+            declarations.push((key_path.clone(), &**name, declaration));
+        }
+    }
+    declarations
+}
+
+impl WorkspaceDiff<'_> {
+    /// The number of members in which any dependency changed
+    pub fn members(&self) -> usize {
+        let changes = self.dependencies.iter().flat_map(|d| &d.changes);
+        let members: BTreeSet<&str> = changes.flat_map(|c| &c.members).map(|m| m.name).collect();
+        members.len()
+    }
+}
+
+/// Renders the name of each dependency on a line of its own, then each change to it indented
+/// below, marked with `+` if the dependency was added, `-` if it was removed and `~` if its
+/// declaration changed, followed by the members it was made in. A member's table is shown
+/// after it unless it's `dependencies`. Then a line counts the dependencies and members.
+/// Nothing is rendered if no dependency changed.
+impl fmt::Display for WorkspaceDiff<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.dependencies.is_empty() {
+            return Ok(());
+        }
+        for dependency in &self.dependencies {
+            writeln!(f, "{}", dependency.name)?;
+            for change in &dependency.changes {
+                match (change.old, change.new) {
+                    (None, Some(new)) => write!(f, "  + {}", inline(new))?,
+                    (Some(old), None) => write!(f, "  - {}", inline(old))?,
+                    (Some(old), Some(new)) => write!(f, "  ~ {} -> {}", inline(old), inline(new))?,
+                    (None, None) => unreachable!(),
+                }
+                let members: Vec<_> = change
+                    .members
+                    .iter()
+                    .map(|member| match &*member.table {
+                        "dependencies" => member.name.to_owned(),
+                        table => format!("{} ({table})", member.name),
+                    })
+                    .collect();
+                writeln!(f, " in {}", members.join(", "))?;
+            }
+        }
+
+        let count = self.dependencies.len();
+        let members = self.members();
+        writeln!(
+            f,
+            "{count} {} changed in {members} {}",
+            if count == 1 {
+                "dependency"
+            } else {
+                "dependencies"
+            },
+            if members == 1 { "member" } else { "members" },
+        )
+    }
+}